@@ -0,0 +1,161 @@
+//! Políticas de acceso: modo solo lectura y listas de permitidos/denegados
+//!
+//! Algunas integraciones (por ejemplo, el Cursor de un revisor) deben poder leer
+//! la memoria pero nunca escribirla. Una política decide qué herramientas ve y
+//! puede llamar el cliente, y a qué proyectos (`project_path`) puede acceder.
+
+use crate::project::{normalize_path, path_under};
+use crate::tools::Tool;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Política de acceso de un cliente
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessPolicy {
+    /// Oculta y rechaza toda herramienta que no sea de solo lectura
+    pub read_only: bool,
+    /// Si no está vacía, solo estas herramientas están permitidas
+    pub allow_tools: Vec<String>,
+    /// Herramientas siempre denegadas
+    pub deny_tools: Vec<String>,
+    /// Si no está vacía, solo proyectos bajo alguno de estos prefijos
+    pub allow_projects: Vec<String>,
+    /// Proyectos bajo alguno de estos prefijos están denegados
+    pub deny_projects: Vec<String>,
+}
+
+impl AccessPolicy {
+    /// ¿Puede el cliente ver y llamar a esta herramienta?
+    pub fn tool_allowed(&self, tool: &dyn Tool) -> bool {
+        let name = tool.name();
+        if self.deny_tools.iter().any(|t| t == name) {
+            return false;
+        }
+        if !self.allow_tools.is_empty() && !self.allow_tools.iter().any(|t| t == name) {
+            return false;
+        }
+        if self.read_only && tool.annotations().read_only_hint != Some(true) {
+            return false;
+        }
+        true
+    }
+
+    /// ¿Puede el cliente acceder a entradas de este proyecto?
+    ///
+    /// Las entradas sin proyecto solo son accesibles si no hay lista de permitidos.
+    pub fn project_allowed(&self, project_path: Option<&str>) -> bool {
+        let Some(path) = project_path else {
+            return self.allow_projects.is_empty();
+        };
+        if self.deny_projects.iter().any(|p| path_under(path, p)) {
+            return false;
+        }
+        self.allow_projects.is_empty() || self.allow_projects.iter().any(|p| path_under(path, p))
+    }
+
+    /// Normaliza los prefijos de proyecto igual que los `project_path` guardados
+    fn normalize_projects(&mut self) {
+        for prefix in self.allow_projects.iter_mut().chain(self.deny_projects.iter_mut()) {
+            *prefix = normalize_path(prefix);
+        }
+    }
+
+    /// Error si el cliente no puede acceder al proyecto
+    pub fn check_project(&self, project_path: Option<&str>) -> anyhow::Result<()> {
+        if !self.project_allowed(project_path) {
+            anyhow::bail!(
+                "Acceso denegado al proyecto: {}",
+                project_path.unwrap_or("(sin proyecto)")
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Política por defecto más políticas específicas por cliente
+///
+/// El cliente se identifica por `clientInfo.name` de `initialize` (sin distinguir
+/// mayúsculas).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccessConfig {
+    #[serde(flatten)]
+    pub default: AccessPolicy,
+    pub clients: HashMap<String, AccessPolicy>,
+}
+
+impl AccessConfig {
+    /// Normaliza los prefijos de proyecto de todas las políticas
+    pub fn normalize_projects(&mut self) {
+        self.default.normalize_projects();
+        for policy in self.clients.values_mut() {
+            policy.normalize_projects();
+        }
+    }
+
+    pub fn for_client(&self, client_name: Option<&str>) -> &AccessPolicy {
+        client_name
+            .and_then(|name| {
+                self.clients
+                    .iter()
+                    .find(|(client, _)| client.eq_ignore_ascii_case(name))
+                    .map(|(_, policy)| policy)
+            })
+            .unwrap_or(&self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(allow: &[&str], deny: &[&str]) -> AccessPolicy {
+        AccessPolicy {
+            allow_projects: allow.iter().map(|p| p.to_string()).collect(),
+            deny_projects: deny.iter().map(|p| p.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn allow_prefix_stops_at_path_boundary() {
+        let policy = policy(&["/work/app"], &[]);
+        assert!(policy.project_allowed(Some("/work/app")));
+        assert!(policy.project_allowed(Some("/work/app/api")));
+        assert!(!policy.project_allowed(Some("/work/app-secrets")));
+        assert!(!policy.project_allowed(Some("/work")));
+        assert!(!policy.project_allowed(None));
+    }
+
+    #[test]
+    fn deny_prefix_stops_at_path_boundary() {
+        let policy = policy(&[], &["/x/secret"]);
+        assert!(!policy.project_allowed(Some("/x/secret")));
+        assert!(!policy.project_allowed(Some("/x/secret/sub")));
+        assert!(policy.project_allowed(Some("/x/secret2")));
+        assert!(policy.project_allowed(None));
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let policy = policy(&["/work"], &["/work/private"]);
+        assert!(policy.project_allowed(Some("/work/app")));
+        assert!(!policy.project_allowed(Some("/work/private/app")));
+        assert!(policy.project_allowed(Some("/work/private-notes")));
+    }
+
+    #[test]
+    fn configured_prefixes_are_normalized() {
+        let mut config = AccessConfig {
+            default: policy(&["C:\\Work\\App\\"], &[]),
+            clients: HashMap::from([("reviewer".to_string(), policy(&[], &["/work/app/"]))]),
+        };
+        config.normalize_projects();
+        assert!(config.default.project_allowed(Some(&normalize_path("/mnt/c/work/app/src"))));
+        assert!(!config.default.project_allowed(Some(&normalize_path("c:/work/app2"))));
+        let reviewer = config.for_client(Some("Reviewer"));
+        assert!(!reviewer.project_allowed(Some("/work/app")));
+        assert!(reviewer.project_allowed(Some("/work/apps")));
+    }
+}
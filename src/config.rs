@@ -0,0 +1,546 @@
+//! Configuración por capas (TOML)
+//!
+//! Orden de precedencia, de menor a mayor:
+//!
+//! 1. `~/.config/ide-memory/config.toml` (o el indicado con `--config` / `IDE_MEMORY_CONFIG`)
+//! 2. `.ide-memory.toml` en la raíz del proyecto (se busca desde el directorio actual hacia arriba)
+//! 3. Variables de entorno `IDE_MEMORY_<SECCION>__<CLAVE>` (por ejemplo `IDE_MEMORY_SEARCH__DEFAULT_LIMIT=10`)
+//! 4. Argumentos de línea de comandos
+//!
+//! Las capas se combinan clave por clave: una capa solo reemplaza lo que define.
+//! El archivo del proyecto viene con el repositorio, así que solo puede ajustar
+//! las secciones de [`PROJECT_SECTIONS`]: nunca la política de acceso ni dónde se
+//! leen y escriben las bases.
+
+use crate::access::AccessConfig;
+use crate::memory::{RankingWeights, SnippetOptions};
+use crate::stores::DEFAULT_STORE;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+/// Nombre del archivo de configuración por proyecto
+pub const PROJECT_CONFIG_FILE: &str = ".ide-memory.toml";
+
+/// Secciones que puede definir `.ide-memory.toml`; las demás se ignoran
+pub const PROJECT_SECTIONS: [&str; 4] = ["search", "retention", "mirror", "session"];
+
+/// Prefijo de las variables de entorno de configuración
+const ENV_PREFIX: &str = "IDE_MEMORY_";
+
+/// Claves cuyas rutas relativas se resuelven respecto del archivo que las define
+const PATH_KEYS: [&[&str]; 4] = [
+    &["database"],
+    &["metrics", "database"],
+    &["log_file"],
+    &["sync", "dir"],
+];
+
+/// Configuración efectiva del servidor
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Ruta al archivo de base de datos SQLite
+    pub database: PathBuf,
+    /// Archivo de log en formato JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<PathBuf>,
+    pub metrics: MetricsConfig,
+    pub search: SearchConfig,
+    pub tools: ToolsConfig,
+    pub access: AccessConfig,
+    pub retention: RetentionConfig,
+    /// Bases adicionales montadas junto a la principal, por nombre
+    pub stores: BTreeMap<String, StoreConfig>,
+    /// Store donde `mem_save` guarda si no se indica `store`
+    pub default_store: String,
+    pub sync: SyncConfig,
+    pub mirror: MirrorConfig,
+    pub session: SessionConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            database: PathBuf::from("memory.db"),
+            log_file: None,
+            metrics: MetricsConfig::default(),
+            search: SearchConfig::default(),
+            tools: ToolsConfig::default(),
+            access: AccessConfig::default(),
+            retention: RetentionConfig::default(),
+            stores: BTreeMap::new(),
+            default_store: DEFAULT_STORE.to_string(),
+            sync: SyncConfig::default(),
+            mirror: MirrorConfig::default(),
+            session: SessionConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    /// Base de datos de métricas (default: `<database>_metrics.db`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<PathBuf>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: true,
+            database: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// Resultados de `mem_search` cuando no se indica `limit`
+    pub default_limit: i32,
+    /// Pesos del ranking: BM25 por columna y uso de cada entrada
+    pub weights: RankingWeights,
+    /// Fragmento de contenido con las coincidencias resaltadas
+    pub snippet: SnippetOptions,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            default_limit: 5,
+            weights: RankingWeights::default(),
+            snippet: SnippetOptions::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ToolsConfig {
+    /// Si se indica, solo se cargan estas herramientas
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<Vec<String>>,
+    /// Herramientas que no se cargan
+    pub disabled: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionConfig {
+    /// Días que se conservan las métricas de solicitudes (sin valor = siempre)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics_days: Option<u32>,
+    /// Días de vigencia por defecto de las entradas nuevas, por tipo
+    pub ttl_days: BTreeMap<String, u32>,
+    /// Qué hacer con las entradas vencidas
+    pub expired: ExpiredAction,
+    /// Cada cuántos minutos el servidor procesa las entradas vencidas (0 = nunca)
+    pub gc_interval_minutes: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            metrics_days: None,
+            ttl_days: BTreeMap::new(),
+            expired: ExpiredAction::default(),
+            gc_interval_minutes: 60,
+        }
+    }
+}
+
+/// Destino de las entradas vencidas
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExpiredAction {
+    /// Se conservan fuera de las búsquedas, con un evento `archived` en el timeline
+    #[default]
+    Archive,
+    /// Se eliminan de la base
+    Purge,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Directorio compartido (o dentro de un repositorio git) con los changelogs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    /// Hacer pull antes y commit/push después de sincronizar
+    pub git: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// Indexar `<proyecto>/<dir>/*.md` y escribir ahí las entradas nuevas del proyecto
+    pub enabled: bool,
+    /// Directorio relativo a la raíz del proyecto
+    pub dir: String,
+    /// Cada cuántos segundos se revisa el directorio
+    pub poll_seconds: u64,
+    /// Escribir las entradas de `mem_save` como archivos
+    pub write_back: bool,
+    /// Store donde se indexan (default: `default_store`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<String>,
+}
+
+impl Default for MirrorConfig {
+    fn default() -> Self {
+        MirrorConfig {
+            enabled: false,
+            dir: ".ide-memory".to_string(),
+            poll_seconds: 5,
+            write_back: true,
+            store: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    /// Guardar el resumen de la sesión (tipo `summary`) al cerrarse el servidor si
+    /// se guardó o actualizó alguna entrada
+    pub summarize_on_exit: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        SessionConfig { summarize_on_exit: true }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    /// Base de datos SQLite del store (en `[stores.default]`, reemplaza a `database`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub database: Option<PathBuf>,
+    /// Peso de sus resultados en la búsqueda combinada
+    pub weight: f64,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        StoreConfig {
+            database: None,
+            weight: 1.0,
+        }
+    }
+}
+
+impl Config {
+    /// Carga y combina todas las capas. Devuelve la configuración efectiva y la
+    /// lista de fuentes aplicadas, en orden.
+    ///
+    /// `global` reemplaza la ruta del archivo global; `start_dir` es desde donde se
+    /// busca `.ide-memory.toml`; `cli` son los valores dados por línea de comandos.
+    pub fn load(
+        global: Option<&Path>,
+        start_dir: &Path,
+        cli: Table,
+    ) -> anyhow::Result<(Config, Vec<String>)> {
+        let mut merged = Table::new();
+        let mut sources = Vec::new();
+
+        let global = global
+            .map(Path::to_path_buf)
+            .or_else(|| std::env::var_os("IDE_MEMORY_CONFIG").map(PathBuf::from))
+            .or_else(global_config_path);
+        if let Some(path) = global.filter(|p| p.is_file()) {
+            merge(&mut merged, read_layer(&path)?);
+            sources.push(path.display().to_string());
+        }
+
+        if let Some(path) = find_project_config(start_dir) {
+            let mut layer = read_layer(&path)?;
+            let ignored: Vec<String> = layer
+                .keys()
+                .filter(|key| !PROJECT_SECTIONS.contains(&key.as_str()))
+                .cloned()
+                .collect();
+            layer.retain(|key, _| PROJECT_SECTIONS.contains(&key));
+            merge(&mut merged, layer);
+            if ignored.is_empty() {
+                sources.push(path.display().to_string());
+            } else {
+                sources.push(format!("{} (ignorado: {})", path.display(), ignored.join(", ")));
+            }
+        }
+
+        let env = env_layer(std::env::vars());
+        if !env.is_empty() {
+            merge(&mut merged, env);
+            sources.push("variables de entorno".to_string());
+        }
+
+        if !cli.is_empty() {
+            merge(&mut merged, cli);
+            sources.push("línea de comandos".to_string());
+        }
+
+        inherit_client_policies(&mut merged);
+        let mut config: Config = Value::Table(merged)
+            .try_into()
+            .map_err(|e| anyhow::anyhow!("Configuración inválida: {}", e))?;
+        config.access.normalize_projects();
+        config.validate()?;
+        Ok((config, sources))
+    }
+
+    /// Valores que se aceptan sintácticamente pero no tienen sentido
+    ///
+    /// Los pesos del ranking y de los stores multiplican la relevancia BM25: uno
+    /// negativo o no finito la invertiría o haría fallar todas las búsquedas.
+    fn validate(&self) -> anyhow::Result<()> {
+        self.search
+            .weights
+            .validate()
+            .map_err(|e| anyhow::anyhow!("Configuración inválida: search.weights.{}", e))?;
+        for (name, store) in &self.stores {
+            if !store.weight.is_finite() || store.weight <= 0.0 {
+                anyhow::bail!(
+                    "Configuración inválida: stores.{}.weight debe ser mayor que 0 (es {})",
+                    name,
+                    store.weight
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Ruta de la base de datos de métricas
+    pub fn metrics_database(&self) -> PathBuf {
+        self.metrics.database.clone().unwrap_or_else(|| {
+            // Usar la misma base de datos pero con sufijo _metrics
+            let mut path = self.database.clone();
+            let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("memory");
+            path.set_file_name(format!("{}_metrics.db", stem));
+            path
+        })
+    }
+}
+
+/// `~/.config/ide-memory/config.toml`
+fn global_config_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join("ide-memory").join("config.toml"))
+}
+
+/// Busca `.ide-memory.toml` desde `start` hacia arriba
+fn find_project_config(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG_FILE))
+        .find(|path| path.is_file())
+}
+
+/// Lee un archivo TOML y resuelve sus rutas relativas respecto de su directorio
+fn read_layer(path: &Path) -> anyhow::Result<Table> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("No se pudo leer {}: {}", path.display(), e))?;
+    let mut table: Table = text
+        .parse()
+        .map_err(|e| anyhow::anyhow!("Error de sintaxis en {}: {}", path.display(), e))?;
+
+    let base = path.parent().unwrap_or(Path::new("."));
+    let resolve = |value: &mut Value| {
+        if let Value::String(value) = value {
+            if Path::new(value.as_str()).is_relative() {
+                *value = base.join(value.as_str()).display().to_string();
+            }
+        }
+    };
+    for keys in PATH_KEYS {
+        lookup_mut(&mut table, keys).map(resolve);
+    }
+    if let Some(Value::Table(stores)) = table.get_mut("stores") {
+        for (_, store) in stores.iter_mut() {
+            if let Some(database) = store.get_mut("database") {
+                resolve(database);
+            }
+        }
+    }
+    Ok(table)
+}
+
+fn lookup_mut<'a>(table: &'a mut Table, keys: &[&str]) -> Option<&'a mut Value> {
+    let (last, parents) = keys.split_last()?;
+    let mut current = table;
+    for key in parents {
+        current = current.get_mut(*key)?.as_table_mut()?;
+    }
+    current.get_mut(*last)
+}
+
+/// Capa de variables de entorno: `IDE_MEMORY_A__B=valor` equivale a `[a] b = valor`
+///
+/// El valor se interpreta como TOML (números, booleanos, listas) y si no lo es,
+/// como texto.
+fn env_layer(vars: impl Iterator<Item = (String, String)>) -> Table {
+    let mut layer = Table::new();
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+        // IDE_MEMORY_CONFIG indica la ruta del archivo global, no es una clave
+        if key.is_empty() || key == "CONFIG" {
+            continue;
+        }
+        let keys: Vec<String> = key.split("__").map(|k| k.to_lowercase()).collect();
+        let value = format!("v = {}", raw)
+            .parse::<Table>()
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or(Value::String(raw));
+        merge(&mut layer, nest(&keys, value));
+    }
+    layer
+}
+
+/// Construye `{a = {b = valor}}` a partir de `["a", "b"]`
+pub fn nest(keys: &[String], value: Value) -> Table {
+    let mut table = Table::new();
+    if let Some((last, parents)) = keys.split_last() {
+        let mut inner = Table::new();
+        inner.insert(last.clone(), value);
+        table = parents.iter().rev().fold(inner, |acc, key| {
+            let mut outer = Table::new();
+            outer.insert(key.clone(), Value::Table(acc));
+            outer
+        });
+    }
+    table
+}
+
+/// Combina `layer` sobre `base`: las tablas se combinan recursivamente y el resto
+/// de valores (incluidas las listas) se reemplazan
+pub fn merge(base: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(incoming)) => merge(existing, incoming),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Las políticas `[access.clients.<nombre>]` heredan de `[access]` lo que no definen
+fn inherit_client_policies(merged: &mut Table) {
+    let Some(Value::Table(access)) = merged.get_mut("access") else {
+        return;
+    };
+    let mut defaults = access.clone();
+    defaults.remove("clients");
+    let Some(Value::Table(clients)) = access.get_mut("clients") else {
+        return;
+    };
+    for (_, policy) in clients.iter_mut() {
+        if let Value::Table(own) = policy {
+            let mut inherited = defaults.clone();
+            merge(&mut inherited, std::mem::take(own));
+            *own = inherited;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Proyecto con su `.ide-memory.toml` y un archivo global aparte
+    fn layers(name: &str, global: &str, project: &str) -> (TempDir, PathBuf, PathBuf) {
+        let dir = TempDir::new(name);
+        let global_path = dir.join("global/config.toml");
+        let project_dir = dir.join("project");
+        std::fs::create_dir_all(global_path.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(project_dir.join("src")).unwrap();
+        std::fs::write(&global_path, global).unwrap();
+        std::fs::write(project_dir.join(PROJECT_CONFIG_FILE), project).unwrap();
+        (dir, global_path, project_dir)
+    }
+
+    #[test]
+    fn layers_merge_key_by_key() {
+        let (dir, global, project) = layers(
+            "config-layers",
+            "database = \"memoria.db\"\n[search]\ndefault_limit = 7\n[search.weights]\ntitle = 8.0\n",
+            "[search]\ndefault_limit = 9\n",
+        );
+        let cli = nest(&["metrics".to_string(), "enabled".to_string()], Value::Boolean(false));
+        let (config, sources) = Config::load(Some(&global), &project.join("src"), cli).unwrap();
+
+        assert_eq!(config.search.default_limit, 9);
+        assert_eq!(config.search.weights.title, 8.0);
+        assert_eq!(config.database, dir.join("global/memoria.db"));
+        assert!(!config.metrics.enabled);
+        assert_eq!(sources.first(), Some(&global.display().to_string()));
+        assert_eq!(sources.last().map(String::as_str), Some("línea de comandos"));
+    }
+
+    #[test]
+    fn project_file_cannot_change_access_or_databases() {
+        let (_dir, global, project) = layers(
+            "config-project",
+            "",
+            "database = \"otra.db\"\n[access]\nread_only = false\ndeny_tools = []\n[session]\nsummarize_on_exit = false\n",
+        );
+        let cli = nest(&["access".to_string(), "read_only".to_string()], Value::Boolean(true));
+        let (config, sources) = Config::load(Some(&global), &project, cli).unwrap();
+
+        assert!(config.access.default.read_only);
+        assert_eq!(config.database, PathBuf::from("memory.db"));
+        assert!(!config.session.summarize_on_exit);
+        assert!(sources.iter().any(|s| s.ends_with("(ignorado: access, database)")), "{:?}", sources);
+    }
+
+    #[test]
+    fn env_values_are_parsed_as_toml() {
+        let vars = [
+            ("IDE_MEMORY_SEARCH__DEFAULT_LIMIT", "10"),
+            ("IDE_MEMORY_ACCESS__DENY_TOOLS", "[\"mem_save\"]"),
+            ("IDE_MEMORY_DATABASE", "/datos/memoria.db"),
+            ("IDE_MEMORY_CONFIG", "/no/es/una/clave.toml"),
+            ("HOME", "/root"),
+        ];
+        let layer = env_layer(vars.iter().map(|(k, v)| (k.to_string(), v.to_string())));
+        let expected: Table = "database = \"/datos/memoria.db\"\n[search]\ndefault_limit = 10\n[access]\ndeny_tools = [\"mem_save\"]\n"
+            .parse()
+            .unwrap();
+        assert_eq!(layer, expected);
+    }
+
+    #[test]
+    fn client_policies_inherit_the_default_policy() {
+        let (_dir, global, project) = layers(
+            "config-clients",
+            "[access]\ndeny_tools = [\"mem_pin\"]\nallow_projects = [\"/mnt/c/Work\"]\n[access.clients.cursor]\nread_only = true\n",
+            "",
+        );
+        let (config, _) = Config::load(Some(&global), &project, Table::new()).unwrap();
+
+        let cursor = config.access.for_client(Some("Cursor"));
+        assert!(cursor.read_only);
+        assert_eq!(cursor.deny_tools, ["mem_pin"]);
+        assert_eq!(cursor.allow_projects, ["c:/work"]);
+        assert!(!config.access.for_client(Some("claude-code")).read_only);
+    }
+
+    #[test]
+    fn non_positive_weights_are_rejected() {
+        let (_dir, global, project) = layers("config-weights", "[stores.team]\nweight = 0.0\n", "");
+        let error = Config::load(Some(&global), &project, Table::new()).unwrap_err();
+        assert!(error.to_string().contains("stores.team.weight"), "{}", error);
+
+        let (_dir, global, project) = layers("config-bm25", "[search.weights]\ncontent = -1.0\n", "");
+        let error = Config::load(Some(&global), &project, Table::new()).unwrap_err();
+        assert!(error.to_string().contains("search.weights.content"), "{}", error);
+    }
+}
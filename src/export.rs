@@ -0,0 +1,221 @@
+//! Exportación de la memoria como archivo de reglas
+//!
+//! Genera el contexto del proyecto para herramientas que no hablan MCP (reglas de
+//! Cursor, `CLAUDE.md`, `AGENTS.md`, instrucciones de Copilot). El texto generado
+//! va entre los marcadores [`BEGIN`] y [`END`]: al regenerar solo se reemplaza esa
+//! región y lo escrito a mano fuera de ella se conserva.
+
+use crate::memory::{ExportFilter, KnowledgeEntry, Memory};
+use crate::project::Project;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+/// Inicio de la región generada (puede seguir texto antes de `-->`)
+pub const BEGIN: &str = "<!-- ide-memory:begin";
+/// Fin de la región generada
+pub const END: &str = "<!-- ide-memory:end -->";
+
+/// Formato de destino
+#[derive(Debug, Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Target {
+    /// `.cursor/rules/ide-memory.mdc`
+    Cursor,
+    /// `CLAUDE.md`
+    Claude,
+    /// `AGENTS.md`
+    Agents,
+    /// `.github/copilot-instructions.md`
+    Copilot,
+}
+
+impl Target {
+    /// Archivo por defecto, relativo a la raíz del proyecto
+    pub fn default_path(self) -> &'static str {
+        match self {
+            Target::Cursor => ".cursor/rules/ide-memory.mdc",
+            Target::Claude => "CLAUDE.md",
+            Target::Agents => "AGENTS.md",
+            Target::Copilot => ".github/copilot-instructions.md",
+        }
+    }
+
+    /// Texto inicial de un archivo nuevo, antes de la región generada
+    fn preamble(self) -> &'static str {
+        match self {
+            // Regla que Cursor aplica siempre, sin depender de globs
+            Target::Cursor => "---\ndescription: Conocimiento del proyecto guardado en IDE-Memory\nalwaysApply: true\n---\n\n",
+            Target::Claude => "# CLAUDE.md\n\n",
+            Target::Agents => "# AGENTS.md\n\n",
+            Target::Copilot => "",
+        }
+    }
+}
+
+/// Resultado de exportar
+#[derive(Debug, Serialize)]
+pub struct ExportReport {
+    pub path: PathBuf,
+    pub entries: usize,
+    /// `false` si el archivo ya tenía exactamente este contenido
+    pub changed: bool,
+}
+
+/// Exporta las entradas elegidas por `filter` al archivo del destino
+///
+/// `output` reemplaza el archivo por defecto del destino (relativo al proyecto).
+pub fn export(
+    memory: &Memory,
+    project: &Project,
+    target: Target,
+    filter: ExportFilter,
+    output: Option<&Path>,
+) -> anyhow::Result<ExportReport> {
+    let root = &project.dir;
+    let path = root.join(output.unwrap_or(Path::new(target.default_path())));
+    let relative = path
+        .strip_prefix(root)
+        .map(|p| p.to_string_lossy().replace('\\', "/"))
+        .ok();
+
+    // Lo importado de este mismo archivo ya está en él, fuera de la región
+    let filter = ExportFilter {
+        project: Some(project.key.clone()),
+        exclude_source: relative,
+        ..filter
+    };
+    let entries = memory.export_entries(&filter)?;
+    let region = render(&entries);
+
+    let current = match std::fs::read_to_string(&path) {
+        Ok(text) => Some(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => anyhow::bail!("No se pudo leer {}: {}", path.display(), e),
+    };
+    let text = match current.as_deref() {
+        Some(text) => replace_region(text, &region)
+            .ok_or_else(|| anyhow::anyhow!("{}: falta el marcador de fin de la región generada", path.display()))?,
+        None => format!("{}{}\n", target.preamble(), region),
+    };
+
+    let changed = current.as_deref() != Some(text.as_str());
+    if changed {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, &text)?;
+    }
+    Ok(ExportReport {
+        path,
+        entries: entries.len(),
+        changed,
+    })
+}
+
+/// Región generada: las entradas agrupadas por tipo
+///
+/// Las entradas fijadas llevan su contenido completo y las demás, su resumen.
+fn render(entries: &[KnowledgeEntry]) -> String {
+    const ORDER: [(&str, &str); 6] = [
+        ("decision", "Decisiones"),
+        ("pattern", "Patrones"),
+        ("config", "Configuración"),
+        ("bugfix", "Errores resueltos"),
+        ("context", "Contexto"),
+        ("summary", "Resúmenes"),
+    ];
+
+    let mut types: Vec<&str> = entries.iter().map(|e| e.knowledge_type.as_str()).collect();
+    types.sort_by_key(|t| (ORDER.iter().position(|(name, _)| name == t).unwrap_or(ORDER.len()), *t));
+    types.dedup();
+
+    let mut text = format!(
+        "{} (generado por IDE_Memory export-rules; los cambios dentro de esta región se sobrescriben) -->\n## Memoria del proyecto\n",
+        BEGIN
+    );
+    if entries.is_empty() {
+        text.push_str("\n_Sin entradas seleccionadas._\n");
+    }
+    for knowledge_type in types {
+        let heading = ORDER
+            .iter()
+            .find(|(name, _)| *name == knowledge_type)
+            .map_or(knowledge_type, |(_, heading)| heading);
+        text.push_str(&format!("\n### {}\n", heading));
+        for entry in entries.iter().filter(|e| e.knowledge_type == knowledge_type) {
+            let body = if entry.pinned { &entry.content } else { &entry.summary };
+            text.push_str(&format!("\n#### {}\n\n{}\n", entry.title, body.trim()));
+        }
+    }
+    text.push_str(END);
+    text
+}
+
+/// Reemplaza la región generada de `text`, o la agrega al final si no tiene
+///
+/// `None` si la región empieza pero no termina.
+fn replace_region(text: &str, region: &str) -> Option<String> {
+    let Some(start) = text.find(BEGIN) else {
+        let separator = if text.is_empty() || text.ends_with("\n\n") {
+            ""
+        } else if text.ends_with('\n') {
+            "\n"
+        } else {
+            "\n\n"
+        };
+        return Some(format!("{}{}{}\n", text, separator, region));
+    };
+    let end = start + text[start..].find(END)? + END.len();
+    Some(format!("{}{}{}", &text[..start], region, &text[end..]))
+}
+
+/// Texto sin la región generada, para no reimportar lo exportado
+pub fn strip_generated(text: &str) -> String {
+    match text.find(BEGIN) {
+        Some(start) => {
+            let end = text[start..].find(END).map_or(text.len(), |end| start + end + END.len());
+            format!("{}{}", &text[..start], &text[end..])
+        }
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(body: &str) -> String {
+        format!("{} -->\n{}\n{}", BEGIN, body, END)
+    }
+
+    #[test]
+    fn replace_region_appends_when_missing() {
+        assert_eq!(replace_region("", "R").unwrap(), "R\n");
+        assert_eq!(replace_region("# Reglas", "R").unwrap(), "# Reglas\n\nR\n");
+        assert_eq!(replace_region("# Reglas\n", "R").unwrap(), "# Reglas\n\nR\n");
+        assert_eq!(replace_region("# Reglas\n\n", "R").unwrap(), "# Reglas\n\nR\n");
+    }
+
+    #[test]
+    fn replace_region_keeps_manual_text() {
+        let text = format!("antes\n\n{}\n\ndespués\n", region("viejo"));
+        let replaced = replace_region(&text, &region("nuevo")).unwrap();
+        assert_eq!(replaced, format!("antes\n\n{}\n\ndespués\n", region("nuevo")));
+        // Regenerar con el mismo contenido no cambia nada
+        assert_eq!(replace_region(&replaced, &region("nuevo")).unwrap(), replaced);
+    }
+
+    #[test]
+    fn replace_region_rejects_unterminated_region() {
+        let text = format!("antes\n{} -->\nsin cierre\n", BEGIN);
+        assert_eq!(replace_region(&text, &region("nuevo")), None);
+    }
+
+    #[test]
+    fn strip_generated_removes_only_the_region() {
+        let text = format!("antes\n{}\ndespués\n", region("generado"));
+        assert_eq!(strip_generated(&text), "antes\n\ndespués\n");
+        assert_eq!(strip_generated("solo a mano\n"), "solo a mano\n");
+        // Sin cierre se descarta hasta el final
+        assert_eq!(strip_generated(&format!("antes\n{} -->\nresto", BEGIN)), "antes\n");
+    }
+}
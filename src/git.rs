@@ -0,0 +1,102 @@
+//! Información del repositorio git de un proyecto
+//!
+//! Se usa el binario `git` del sistema; si no está instalado o el directorio no
+//! es un repositorio, las funciones devuelven `None` y la entrada se guarda sin
+//! datos de git.
+
+use std::path::Path;
+use std::process::Command;
+
+/// Commit y rama actuales de un repositorio
+#[derive(Debug, Clone)]
+pub struct GitHead {
+    pub commit: String,
+    /// `None` si HEAD está desacoplado
+    pub branch: Option<String>,
+}
+
+/// HEAD del repositorio que contiene `dir`
+pub fn head(dir: &Path) -> Option<GitHead> {
+    let commit = run(dir, &["rev-parse", "HEAD"])?;
+    let branch = run(dir, &["rev-parse", "--abbrev-ref", "HEAD"]).filter(|b| b != "HEAD");
+    Some(GitHead { commit, branch })
+}
+
+/// Ejecuta `git -C dir <args>` y devuelve su salida sin espacios finales
+fn run(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let text = String::from_utf8(output.stdout).ok()?;
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+/// Ejecuta `git -C dir <args>`; si falla, el error incluye lo que git escribió
+pub fn command(dir: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow::anyhow!("No se pudo ejecutar git: {}", e))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git {} falló: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Repositorio con un commit en la rama `main`
+    fn repo(name: &str) -> TempDir {
+        let dir = TempDir::new(name);
+        command(dir.path(), &["init", "-q", "-b", "main"]).unwrap();
+        std::fs::write(dir.join("lib.rs"), "fn main() {}\n").unwrap();
+        command(dir.path(), &["add", "lib.rs"]).unwrap();
+        command(
+            dir.path(),
+            &["-c", "user.name=prueba", "-c", "user.email=prueba@example.com", "commit", "-q", "-m", "inicio"],
+        )
+        .unwrap();
+        dir
+    }
+
+    #[test]
+    fn head_has_commit_and_branch() {
+        let dir = repo("git-head");
+        // Desde un subdirectorio se ve el HEAD del repositorio que lo contiene
+        std::fs::create_dir(dir.join("src")).unwrap();
+        let head = head(&dir.join("src")).unwrap();
+        assert_eq!(head.commit, command(dir.path(), &["rev-parse", "HEAD"]).unwrap());
+        assert_eq!(head.commit.len(), 40);
+        assert_eq!(head.branch.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn detached_head_has_no_branch() {
+        let dir = repo("git-detached");
+        command(dir.path(), &["checkout", "-q", "--detach"]).unwrap();
+        let head = head(dir.path()).unwrap();
+        assert_eq!(head.branch, None);
+    }
+
+    #[test]
+    fn outside_a_repository_there_is_no_head() {
+        let dir = TempDir::new("git-none");
+        assert!(head(dir.path()).is_none());
+    }
+}
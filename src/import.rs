@@ -0,0 +1,497 @@
+//! Importación de conocimiento desde documentos existentes
+//!
+//! Adaptadores para `.cursorrules`, `.cursor/rules/*.mdc`, `CLAUDE.md`,
+//! `AGENTS.md` y los ADR de `docs/adr/*.md`. Cada documento se divide en
+//! secciones que se guardan como entradas con su origen (`<archivo>#<sección>`)
+//! y el hash de su texto: reimportar solo actualiza lo que cambió y elimina las
+//! secciones que ya no existen.
+
+use crate::export;
+use crate::memory::{Memory, NewKnowledge};
+use crate::project::Project;
+use crate::verify;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+/// Cambios aplicados al importar
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub added: usize,
+    pub updated: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+impl ImportReport {
+    pub fn is_empty(&self) -> bool {
+        self.added + self.updated + self.removed == 0
+    }
+
+    fn add(&mut self, other: ImportReport) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.removed += other.removed;
+        self.unchanged += other.unchanged;
+    }
+}
+
+/// Un origen (archivo o sección de un archivo) y el hash de su texto
+pub struct Source {
+    /// Ruta relativa a la raíz del proyecto, opcionalmente con `#<sección>`
+    pub path: String,
+    pub hash: String,
+}
+
+/// Refleja en la memoria los orígenes de un proyecto que empiezan por `prefix`
+///
+/// Los orígenes con el mismo hash que la última vez no se tocan; para los nuevos
+/// o modificados se llama a `build(índice, ID existente)` (que puede devolver
+/// `None` para omitirlo) y las entradas cuyo origen ya no aparece se eliminan.
+pub fn reconcile<F>(
+    memory: &Memory,
+    project: &str,
+    prefix: &str,
+    sources: &[Source],
+    mut build: F,
+) -> anyhow::Result<ImportReport>
+where
+    F: FnMut(usize, Option<i64>) -> anyhow::Result<Option<NewKnowledge>>,
+{
+    let mut known: HashMap<String, (i64, Option<String>)> = memory
+        .sources_under(project, prefix)?
+        .into_iter()
+        .map(|(path, id, hash)| (path, (id, hash)))
+        .collect();
+
+    let mut report = ImportReport::default();
+    for (i, source) in sources.iter().enumerate() {
+        let existing = match known.remove(&source.path) {
+            Some((_, Some(hash))) if hash == source.hash => {
+                report.unchanged += 1;
+                continue;
+            }
+            other => other.map(|(id, _)| id),
+        };
+        let Some(mut entry) = build(i, existing)? else {
+            continue;
+        };
+        entry.project_path = Some(project.to_string());
+        entry.source_path = Some(source.path.clone());
+        entry.source_hash = Some(source.hash.clone());
+
+        match existing {
+            Some(id) => {
+                let description = format!("Actualizada desde {}", source.path);
+                if memory.update_knowledge(id, &entry, &description)? {
+                    report.updated += 1;
+                } else {
+                    report.unchanged += 1;
+                }
+            }
+            None => {
+                memory.save_knowledge(&entry)?;
+                report.added += 1;
+            }
+        }
+    }
+
+    // Lo que quedó en `known` ya no tiene origen
+    for (_, (id, _)) in known {
+        memory.delete_knowledge(id)?;
+        report.removed += 1;
+    }
+    Ok(report)
+}
+
+/// Formato de un documento importable
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    /// `.cursorrules`
+    CursorRules,
+    /// `.cursor/rules/*.mdc`: una regla por archivo, con front matter
+    CursorRule,
+    /// `CLAUDE.md`, `AGENTS.md`
+    AgentInstructions,
+    /// Architecture Decision Record
+    Adr,
+}
+
+impl Format {
+    fn detect(relative: &str) -> Option<Format> {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        match name {
+            ".cursorrules" => Some(Format::CursorRules),
+            "CLAUDE.md" | "AGENTS.md" => Some(Format::AgentInstructions),
+            _ if relative.starts_with(".cursor/rules/") && name.ends_with(".mdc") => Some(Format::CursorRule),
+            _ if relative.starts_with("docs/adr/") && name.ends_with(".md") => Some(Format::Adr),
+            _ => None,
+        }
+    }
+
+    fn tag(self, relative: &str) -> String {
+        match self {
+            Format::CursorRules | Format::CursorRule => "cursor-rules".to_string(),
+            Format::AgentInstructions => slug(relative.rsplit('/').next().unwrap_or(relative)),
+            Format::Adr => "adr".to_string(),
+        }
+    }
+}
+
+/// Sección de un documento, lista para guardarse
+struct Section {
+    anchor: String,
+    title: String,
+    content: String,
+    summary: String,
+    knowledge_type: &'static str,
+    tags: Vec<String>,
+}
+
+/// Documentos importables conocidos que existen en el proyecto
+pub fn discover(project: &Path) -> Vec<PathBuf> {
+    let mut found: Vec<PathBuf> = [".cursorrules", "CLAUDE.md", "AGENTS.md"]
+        .iter()
+        .map(|name| project.join(name))
+        .filter(|path| path.is_file())
+        .collect();
+    for (dir, extension) in [(".cursor/rules", "mdc"), ("docs/adr", "md")] {
+        let mut files: Vec<PathBuf> = std::fs::read_dir(project.join(dir))
+            .map(|entries| entries.filter_map(|e| e.ok().map(|e| e.path())).collect())
+            .unwrap_or_default();
+        files.retain(|path| path.extension().is_some_and(|ext| ext == extension));
+        files.sort();
+        found.extend(files);
+    }
+    found
+}
+
+/// Importa documentos de un proyecto
+///
+/// Con `prune` también se eliminan las entradas importadas de documentos que ya
+/// no están en `files` (por ejemplo, una regla de Cursor borrada).
+pub fn import(memory: &Memory, project: &Project, files: &[PathBuf], prune: bool) -> anyhow::Result<ImportReport> {
+    let root = &project.dir;
+    let mut report = ImportReport::default();
+    let mut imported = HashSet::new();
+    memory.remember_project_dir(&project.key, root)?;
+    for file in files {
+        let absolute = if file.is_absolute() { file.clone() } else { root.join(file) };
+        // La raíz del proyecto tiene los symlinks resueltos
+        let absolute = std::fs::canonicalize(&absolute).unwrap_or(absolute);
+        let relative = absolute
+            .strip_prefix(root)
+            .map_err(|_| anyhow::anyhow!("{} no está dentro del proyecto {}", file.display(), root.display()))?
+            .to_string_lossy()
+            .replace('\\', "/");
+        let format = Format::detect(&relative)
+            .ok_or_else(|| anyhow::anyhow!("Formato no reconocido: {}", relative))?;
+        let text = std::fs::read_to_string(&absolute)
+            .map_err(|e| anyhow::anyhow!("No se pudo leer {}: {}", absolute.display(), e))?;
+
+        // La región generada por `export-rules` no es conocimiento nuevo
+        let text = export::strip_generated(&text);
+        let mut sections = split(format, &relative, &text);
+        sections.retain(|section| !section.content.is_empty());
+        let sources: Vec<Source> = sections
+            .iter()
+            .map(|section| Source {
+                path: format!("{}#{}", relative, section.anchor),
+                hash: verify::hash_text(&format!("{}\n{}", section.title, section.content)),
+            })
+            .collect();
+        let mut sections: Vec<Option<Section>> = sections.into_iter().map(Some).collect();
+        let prefix = format!("{}#", relative);
+        imported.insert(relative.clone());
+        report.add(reconcile(memory, &project.key, &prefix, &sources, |i, _| {
+            Ok(sections[i].take().map(|section| NewKnowledge {
+                summary: section.summary,
+                tags: section.tags,
+                ..NewKnowledge::new(section.knowledge_type, &section.title, &section.content)
+            }))
+        })?);
+    }
+
+    if prune {
+        for (source_path, id, _) in memory.sources_under(&project.key, "")? {
+            let Some((relative, _)) = source_path.split_once('#') else {
+                continue;
+            };
+            if Format::detect(relative).is_some() && !imported.contains(relative) {
+                memory.delete_knowledge(id)?;
+                report.removed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Divide un documento en secciones según su formato
+fn split(format: Format, relative: &str, text: &str) -> Vec<Section> {
+    let name = relative.rsplit('/').next().unwrap_or(relative);
+    let tag = format.tag(relative);
+    match format {
+        Format::Adr => {
+            let (title, body) = match headings(text).into_iter().find(|(level, _, _)| *level == 1) {
+                Some((_, title, _)) => (title, text.to_string()),
+                None => (name.trim_end_matches(".md").to_string(), text.to_string()),
+            };
+            let mut tags = vec![tag];
+            let sections = markdown_sections(text);
+            if let Some((_, status)) = sections.iter().find(|(h, _)| is_heading(h, &["status", "estado"])) {
+                if let Some(word) = status.split_whitespace().next() {
+                    tags.push(slug(word));
+                }
+            }
+            // El resumen es la decisión tomada, si el ADR tiene esa sección
+            let summary = sections
+                .iter()
+                .find(|(h, _)| is_heading(h, &["decision", "decisión"]))
+                .map(|(_, body)| first_paragraph(body))
+                .unwrap_or_else(|| first_paragraph(strip_heading(&body)));
+            vec![Section {
+                anchor: slug(&title),
+                title,
+                content: body.trim().to_string(),
+                summary,
+                knowledge_type: "decision",
+                tags,
+            }]
+        }
+        Format::CursorRule => {
+            let (description, body) = match text.strip_prefix("---") {
+                Some(rest) => match rest.split_once("\n---") {
+                    Some((front, body)) => (
+                        front
+                            .lines()
+                            .find_map(|l| l.trim().strip_prefix("description:"))
+                            .map(|d| d.trim().trim_matches('"').to_string())
+                            .filter(|d| !d.is_empty()),
+                        body.split_once('\n').map_or("", |(_, b)| b),
+                    ),
+                    None => (None, text),
+                },
+                None => (None, text),
+            };
+            let title = description.unwrap_or_else(|| name.trim_end_matches(".mdc").to_string());
+            vec![rule_section(&title, body, vec![tag])]
+        }
+        Format::CursorRules | Format::AgentInstructions => {
+            let label = name.trim_start_matches('.');
+            let mut used: HashMap<String, usize> = HashMap::new();
+            markdown_sections(text)
+                .into_iter()
+                .filter(|(_, body)| !body.trim().is_empty())
+                .map(|(heading, body)| {
+                    let heading = if heading.is_empty() { "General".to_string() } else { heading };
+                    let mut section = rule_section(&format!("{}: {}", label, heading), &body, vec![tag.clone()]);
+                    // Encabezados repetidos: `#pruebas`, `#pruebas-2`...
+                    let base = slug(&heading);
+                    let n = used.entry(base.clone()).or_insert(0);
+                    *n += 1;
+                    section.anchor = if *n == 1 { base } else { format!("{}-{}", base, n) };
+                    section
+                })
+                .collect()
+        }
+    }
+}
+
+/// Sección de reglas: configuración si el encabezado habla de entorno,
+/// comandos o herramientas; patrón en otro caso
+fn rule_section(title: &str, body: &str, tags: Vec<String>) -> Section {
+    const CONFIG_WORDS: [&str; 12] = [
+        "config", "setup", "install", "build", "command", "comando", "entorno", "environment",
+        "dependenc", "tooling", "herramienta", "variables",
+    ];
+    let lower = title.to_lowercase();
+    let knowledge_type = if CONFIG_WORDS.iter().any(|w| lower.contains(w)) {
+        "config"
+    } else {
+        "pattern"
+    };
+    Section {
+        anchor: slug(title),
+        title: title.to_string(),
+        content: body.trim().to_string(),
+        summary: first_paragraph(body),
+        knowledge_type,
+        tags,
+    }
+}
+
+/// Encabezados Markdown (fuera de bloques de código): (nivel, texto, línea)
+fn headings(text: &str) -> Vec<(usize, String, usize)> {
+    let mut in_code = false;
+    let mut found = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        let level = line.chars().take_while(|&c| c == '#').count();
+        if (1..=6).contains(&level) && line[level..].starts_with(' ') {
+            found.push((level, line[level..].trim().to_string(), n));
+        }
+    }
+    found
+}
+
+/// Divide en secciones por encabezados de nivel 1 y 2: (encabezado, cuerpo)
+///
+/// El texto antes del primer encabezado forma una sección con encabezado vacío;
+/// los niveles 3 o más quedan dentro del cuerpo de su sección.
+fn markdown_sections(text: &str) -> Vec<(String, String)> {
+    let lines: Vec<&str> = text.lines().collect();
+    let cuts: Vec<(String, usize)> = headings(text)
+        .into_iter()
+        .filter(|(level, _, _)| *level <= 2)
+        .map(|(_, heading, line)| (heading, line))
+        .collect();
+
+    let mut sections = Vec::new();
+    let first = cuts.first().map_or(lines.len(), |(_, line)| *line);
+    sections.push((String::new(), lines[..first].join("\n")));
+    for (i, (heading, line)) in cuts.iter().enumerate() {
+        let end = cuts.get(i + 1).map_or(lines.len(), |(_, next)| *next);
+        sections.push((heading.clone(), lines[line + 1..end].join("\n")));
+    }
+    sections
+}
+
+fn is_heading(heading: &str, names: &[&str]) -> bool {
+    let lower = heading.to_lowercase();
+    names.iter().any(|name| lower == *name || lower.ends_with(&format!(" {}", name)))
+}
+
+/// Texto sin su primer encabezado de nivel 1
+fn strip_heading(text: &str) -> &str {
+    match text.trim_start().strip_prefix("# ") {
+        Some(rest) => rest.split_once('\n').map_or("", |(_, body)| body),
+        None => text,
+    }
+}
+
+/// Primer párrafo, limitado a unos 300 caracteres
+fn first_paragraph(text: &str) -> String {
+    let paragraph = text
+        .split("\n\n")
+        .map(str::trim)
+        .find(|p| !p.is_empty() && !p.starts_with('#'))
+        .unwrap_or_default();
+    let paragraph = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+    if paragraph.chars().count() > 300 {
+        format!("{}…", paragraph.chars().take(300).collect::<String>())
+    } else {
+        paragraph
+    }
+}
+
+/// Minúsculas y palabras unidas por `-` (nombres de archivo y anclas)
+pub fn slug(text: &str) -> String {
+    let mut slug = String::new();
+    for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+        if slug.len() + word.len() > 60 {
+            break;
+        }
+        if !slug.is_empty() {
+            slug.push('-');
+        }
+        slug.push_str(&word.to_lowercase());
+    }
+    if slug.is_empty() {
+        "entrada".to_string()
+    } else {
+        slug
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    const CLAUDE: &str = "Proyecto de ejemplo.\n\n# Build\n\nUsar `cargo build`.\n\n## Pruebas\n\nCorrer `cargo test`.\n\n```\n# no es encabezado\n```\n\n## Pruebas\n\nTambién las de integración.\n\n## Vacía\n\n";
+
+    const ADR: &str = "# 3. Usar SQLite\n\n## Estado\n\nAceptada el 2024-05-01\n\n## Contexto\n\nNecesitamos una base embebida.\n\n## Decisión\n\nUsamos SQLite con FTS5.\n\nSin servidor aparte.\n";
+
+    #[test]
+    fn markdown_sections_cut_at_levels_one_and_two_outside_code() {
+        let sections = markdown_sections(CLAUDE);
+        let headings: Vec<&str> = sections.iter().map(|(h, _)| h.as_str()).collect();
+        assert_eq!(headings, ["", "Build", "Pruebas", "Pruebas", "Vacía"]);
+        assert_eq!(sections[0].1.trim(), "Proyecto de ejemplo.");
+        assert!(sections[2].1.contains("# no es encabezado"));
+    }
+
+    #[test]
+    fn repeated_headings_get_numbered_anchors() {
+        let sections = split(Format::AgentInstructions, "CLAUDE.md", CLAUDE);
+        let anchors: Vec<&str> = sections.iter().map(|s| s.anchor.as_str()).collect();
+        assert_eq!(anchors, ["general", "build", "pruebas", "pruebas-2"]);
+        assert_eq!(sections[1].title, "CLAUDE.md: Build");
+        assert_eq!(sections[1].knowledge_type, "config");
+        assert_eq!(sections[2].knowledge_type, "pattern");
+        assert_eq!(sections[3].summary, "También las de integración.");
+        assert!(sections.iter().all(|s| s.tags == ["claude-md"]));
+    }
+
+    #[test]
+    fn adr_takes_status_and_decision() {
+        let sections = split(Format::Adr, "docs/adr/0003-sqlite.md", ADR);
+        assert_eq!(sections.len(), 1);
+        let adr = &sections[0];
+        assert_eq!((adr.title.as_str(), adr.anchor.as_str()), ("3. Usar SQLite", "3-usar-sqlite"));
+        assert_eq!(adr.knowledge_type, "decision");
+        assert_eq!(adr.tags, ["adr", "aceptada"]);
+        assert_eq!(adr.summary, "Usamos SQLite con FTS5.");
+
+        // Sin sección de decisión, el resumen es el primer párrafo
+        let sections = split(Format::Adr, "docs/adr/0004-cache.md", "# Caché\n\nRedis delante de la API.\n");
+        assert_eq!(sections[0].summary, "Redis delante de la API.");
+        assert_eq!(sections[0].tags, ["adr"]);
+    }
+
+    #[test]
+    fn cursor_rule_title_comes_from_its_description() {
+        let text = "---\ndescription: \"Estilo de Rust\"\nglobs: *.rs\n---\nUsar `anyhow` para los errores.\n";
+        let sections = split(Format::CursorRule, ".cursor/rules/rust.mdc", text);
+        assert_eq!(sections[0].title, "Estilo de Rust");
+        assert_eq!(sections[0].content, "Usar `anyhow` para los errores.");
+        let sections = split(Format::CursorRule, ".cursor/rules/rust.mdc", "Sin front matter.\n");
+        assert_eq!(sections[0].title, "rust");
+    }
+
+    #[test]
+    fn reimport_only_touches_what_changed() {
+        let dir = TempDir::new("import-reimport");
+        let project = Project::at(dir.path());
+        let memory = Memory::new(&dir.join("memory.db")).unwrap();
+        std::fs::write(dir.join("CLAUDE.md"), CLAUDE).unwrap();
+        std::fs::create_dir_all(dir.join("docs/adr")).unwrap();
+        std::fs::write(dir.join("docs/adr/0003-sqlite.md"), ADR).unwrap();
+
+        let files = discover(dir.path());
+        assert_eq!(files.len(), 2);
+        let report = import(&memory, &project, &files, true).unwrap();
+        assert_eq!((report.added, report.updated, report.removed, report.unchanged), (5, 0, 0, 0));
+
+        let report = import(&memory, &project, &files, true).unwrap();
+        assert_eq!((report.added, report.updated, report.removed, report.unchanged), (0, 0, 0, 5));
+
+        // Una sección cambia y otra desaparece
+        let edited = CLAUDE.replace("Usar `cargo build`.", "Usar `cargo build --release`.").replace("## Pruebas\n\nTambién las de integración.\n\n", "");
+        std::fs::write(dir.join("CLAUDE.md"), edited).unwrap();
+        let report = import(&memory, &project, &files, true).unwrap();
+        assert_eq!((report.added, report.updated, report.removed, report.unchanged), (0, 1, 1, 3));
+
+        // Sin el ADR, `prune` elimina su entrada
+        std::fs::remove_file(dir.join("docs/adr/0003-sqlite.md")).unwrap();
+        let report = import(&memory, &project, &discover(dir.path()), true).unwrap();
+        assert_eq!((report.removed, report.unchanged), (1, 3));
+        assert_eq!(memory.count().unwrap(), 3);
+    }
+}
@@ -0,0 +1,233 @@
+//! Logging del servidor
+//!
+//! En modo stdio no se puede escribir a stderr (Cursor lo interpreta como error),
+//! así que los eventos de `tracing` se reenvían al cliente como notificaciones MCP
+//! `notifications/message` y, opcionalmente, a un archivo en formato JSON.
+
+use serde_json::{Map, Value};
+use std::path::Path;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Niveles de log definidos por MCP (RFC 5424), de menor a mayor severidad
+const MCP_LEVELS: [&str; 8] = [
+    "debug",
+    "info",
+    "notice",
+    "warning",
+    "error",
+    "critical",
+    "alert",
+    "emergency",
+];
+
+/// Nivel mínimo por defecto hasta que el cliente envíe `logging/setLevel`
+const DEFAULT_LEVEL: u8 = 3; // warning
+
+/// Severidad MCP de un nivel de `tracing`
+fn severity(level: &Level) -> u8 {
+    match *level {
+        Level::TRACE | Level::DEBUG => 0,
+        Level::INFO => 1,
+        Level::WARN => 3,
+        Level::ERROR => 4,
+    }
+}
+
+/// Reenvía eventos de `tracing` al cliente MCP
+///
+/// Se crea antes de arrancar el servidor (para instalarlo como layer) y el servidor
+/// le conecta el canal de salida con `attach` cuando abre stdout.
+#[derive(Clone)]
+pub struct LogForwarder {
+    inner: Arc<ForwarderInner>,
+}
+
+struct ForwarderInner {
+    min_level: AtomicU8,
+    out_tx: Mutex<Option<mpsc::UnboundedSender<String>>>,
+}
+
+impl LogForwarder {
+    pub fn new() -> Self {
+        LogForwarder {
+            inner: Arc::new(ForwarderInner {
+                min_level: AtomicU8::new(DEFAULT_LEVEL),
+                out_tx: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Conecta el canal por el que se envían las notificaciones al cliente
+    pub fn attach(&self, out_tx: mpsc::UnboundedSender<String>) {
+        *self.inner.out_tx.lock().unwrap() = Some(out_tx);
+    }
+
+    /// Desconecta el canal (el servidor está cerrando stdout)
+    pub fn detach(&self) {
+        *self.inner.out_tx.lock().unwrap() = None;
+    }
+
+    /// Aplica `logging/setLevel`. Devuelve error si el nivel no es un nivel MCP válido.
+    pub fn set_level(&self, level: &str) -> anyhow::Result<()> {
+        let index = MCP_LEVELS
+            .iter()
+            .position(|l| *l == level)
+            .ok_or_else(|| anyhow::anyhow!("Nivel de log inválido: {}", level))?;
+        self.inner.min_level.store(index as u8, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn forward(&self, severity: u8, logger: &str, data: Value) {
+        if severity < self.inner.min_level.load(Ordering::SeqCst) {
+            return;
+        }
+        let guard = self.inner.out_tx.lock().unwrap();
+        let Some(ref out_tx) = *guard else {
+            return;
+        };
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": {
+                "level": MCP_LEVELS[severity as usize],
+                "logger": logger,
+                "data": data,
+            }
+        });
+        let _ = out_tx.send(notification.to_string());
+    }
+}
+
+impl<S: Subscriber> Layer<S> for LogForwarder {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        self.forward(
+            severity(metadata.level()),
+            metadata.target(),
+            Value::Object(visitor.fields),
+        );
+    }
+}
+
+/// Recolecta los campos de un evento como objeto JSON
+#[derive(Default)]
+struct JsonVisitor {
+    fields: Map<String, Value>,
+}
+
+impl Visit for JsonVisitor {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.fields.insert(field.name().to_string(), value.into());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.fields
+            .insert(field.name().to_string(), format!("{:?}", value).into());
+    }
+}
+
+/// Inicializa `tracing`: reenvío al cliente MCP y, si se indica, archivo de log JSON
+///
+/// El filtro del archivo se toma de `RUST_LOG` (default: `info`).
+pub fn init(forwarder: &LogForwarder, log_file: Option<&Path>) -> anyhow::Result<()> {
+    let file_layer = match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)?;
+            let filter =
+                EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_writer(file)
+                    .with_filter(filter),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(forwarder.clone())
+        .with(file_layer)
+        .try_init()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Notificaciones que produce `log` con el reenviador como único subscriber
+    fn forwarded(forwarder: &LogForwarder, log: impl FnOnce()) -> Vec<Value> {
+        let (out_tx, mut out_rx) = mpsc::unbounded_channel();
+        forwarder.attach(out_tx);
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(forwarder.clone()), log);
+        forwarder.detach();
+        let mut notifications = Vec::new();
+        while let Ok(line) = out_rx.try_recv() {
+            notifications.push(serde_json::from_str(&line).unwrap());
+        }
+        notifications
+    }
+
+    #[test]
+    fn events_below_the_level_are_dropped() {
+        let forwarder = LogForwarder::new();
+        let sent = forwarded(&forwarder, || {
+            tracing::info!("no llega");
+            tracing::warn!(tool = "mem_save", "llega");
+        });
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0]["method"], "notifications/message");
+        assert_eq!(sent[0]["params"]["level"], "warning");
+        assert_eq!(sent[0]["params"]["data"]["tool"], "mem_save");
+        assert_eq!(sent[0]["params"]["data"]["message"], "llega");
+    }
+
+    #[test]
+    fn set_level_accepts_only_mcp_levels() {
+        let forwarder = LogForwarder::new();
+        forwarder.set_level("debug").unwrap();
+        let sent = forwarded(&forwarder, || tracing::info!("llega"));
+        assert_eq!(sent[0]["params"]["level"], "info");
+
+        assert!(forwarder.set_level("verbose").is_err());
+        forwarder.set_level("error").unwrap();
+        assert!(forwarded(&forwarder, || tracing::warn!("no llega")).is_empty());
+    }
+
+    #[test]
+    fn nothing_is_sent_while_detached() {
+        let forwarder = LogForwarder::new();
+        let (out_tx, mut out_rx) = mpsc::unbounded_channel();
+        forwarder.attach(out_tx);
+        forwarder.detach();
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(forwarder.clone()), || {
+            tracing::error!("sin cliente")
+        });
+        assert!(out_rx.try_recv().is_err());
+    }
+}
@@ -1,23 +1,49 @@
 //! Servidor MCP para Memoria Persistente en IDEs
-//! 
+//!
 //! Este servidor expone herramientas de memoria persistente usando el protocolo MCP
 //! (Model Context Protocol), permitiendo que IDEs como Cursor, Claude Code, etc.
 //! tengan acceso a memoria persistente entre sesiones.
 
+mod access;
+mod config;
+mod export;
+mod git;
+mod import;
+mod logging;
 mod memory;
 mod mcp_server;
 mod metrics;
+mod mirror;
+mod project;
+mod retention;
+mod schema;
+mod session;
+mod stores;
+mod sync;
+#[cfg(test)]
+mod testing;
+mod tools;
+mod types;
+mod verify;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Parser, Debug)]
 #[command(name = "IDE_Memory")]
 #[command(about = "MCP Server para memoria persistente en IDEs", long_about = None)]
 struct Args {
-    /// Ruta al archivo de base de datos SQLite
-    #[arg(short, long, default_value = "memory.db")]
-    database: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Archivo de configuración global (default: ~/.config/ide-memory/config.toml)
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Ruta al archivo de base de datos SQLite (default: memory.db)
+    #[arg(short, long, global = true)]
+    database: Option<PathBuf>,
 
     /// Puerto para el servidor HTTP (si se usa transporte HTTP)
     #[arg(short, long, default_value_t = 3000)]
@@ -28,9 +54,13 @@ struct Args {
     transport: String,
 
     /// Habilitar métricas y logging
-    #[arg(long, default_value_t = true)]
+    #[arg(long, overrides_with = "no_metrics")]
     metrics: bool,
 
+    /// Deshabilitar métricas
+    #[arg(long)]
+    no_metrics: bool,
+
     /// Ruta para base de datos de métricas (si es diferente de la principal)
     #[arg(long)]
     metrics_db: Option<PathBuf>,
@@ -38,36 +68,367 @@ struct Args {
     /// Mostrar estadísticas y salir
     #[arg(long)]
     stats: bool,
+
+    /// Archivo de log en formato JSON (nivel según RUST_LOG, default: info)
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// Herramienta MCP a deshabilitar (se puede repetir)
+    #[arg(long = "disable-tool", value_name = "NOMBRE")]
+    disabled_tools: Vec<String>,
+
+    /// Modo solo lectura: oculta y rechaza las herramientas que modifican la memoria
+    #[arg(long)]
+    read_only: bool,
+
+    /// Cliente (clientInfo.name) que opera en modo solo lectura (se puede repetir)
+    #[arg(long = "read-only-client", value_name = "CLIENTE")]
+    read_only_clients: Vec<String>,
+
+    /// Solo permitir estas herramientas (se puede repetir)
+    #[arg(long = "allow-tool", value_name = "NOMBRE")]
+    allow_tools: Vec<String>,
+
+    /// Denegar esta herramienta (se puede repetir)
+    #[arg(long = "deny-tool", value_name = "NOMBRE")]
+    deny_tools: Vec<String>,
+
+    /// Solo permitir proyectos bajo este prefijo de ruta (se puede repetir)
+    #[arg(long = "allow-project", value_name = "PREFIJO")]
+    allow_projects: Vec<String>,
+
+    /// Denegar proyectos bajo este prefijo de ruta (se puede repetir)
+    #[arg(long = "deny-project", value_name = "PREFIJO")]
+    deny_projects: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Consultar la configuración
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Consultar y definir los tipos de conocimiento
+    Types {
+        #[command(subcommand)]
+        action: TypesAction,
+        /// Store en el que actuar (default: el store por defecto)
+        #[arg(long, global = true)]
+        store: Option<String>,
+    },
+    /// Verificar las entradas contra el árbol de trabajo actual y marcar las obsoletas
+    Verify {
+        /// Proyecto a verificar (default: el repositorio git del directorio actual)
+        #[arg(long)]
+        project: Option<PathBuf>,
+        /// Verificar las entradas de todos los proyectos
+        #[arg(long, conflicts_with = "project")]
+        all: bool,
+        /// Verificar solo esta entrada
+        #[arg(long)]
+        id: Option<i64>,
+        /// Verificar solo este store (default: todos; con --id, el store por defecto)
+        #[arg(long)]
+        store: Option<String>,
+    },
+    /// Sincronizar con otras máquinas a través de un directorio compartido o repositorio git
+    Sync {
+        /// Directorio de los changelogs (default: `sync.dir` de la configuración)
+        #[arg(long)]
+        dir: Option<PathBuf>,
+        /// Hacer pull antes y commit/push después (el directorio debe estar en un repositorio git)
+        #[arg(long)]
+        git: bool,
+        /// Store a sincronizar (default: el store por defecto)
+        #[arg(long)]
+        store: Option<String>,
+    },
+    /// Importar reglas y decisiones de .cursorrules, .cursor/rules, CLAUDE.md, AGENTS.md y docs/adr
+    Import {
+        /// Proyecto (default: el repositorio git del directorio actual)
+        #[arg(long)]
+        project: Option<PathBuf>,
+        /// Store donde guardar (default: el store por defecto)
+        #[arg(long)]
+        store: Option<String>,
+        /// Documentos a importar (default: todos los conocidos que existan en el proyecto)
+        files: Vec<PathBuf>,
+    },
+    /// Generar un archivo de reglas (Cursor, CLAUDE.md, AGENTS.md, Copilot) con la memoria del proyecto
+    ExportRules {
+        /// Formato de destino
+        #[arg(value_enum)]
+        target: export::Target,
+        /// Proyecto (default: el repositorio git del directorio actual)
+        #[arg(long)]
+        project: Option<PathBuf>,
+        /// Store del que exportar (default: el store por defecto)
+        #[arg(long)]
+        store: Option<String>,
+        /// Solo entradas de este tipo (se puede repetir)
+        #[arg(long = "type")]
+        types: Vec<String>,
+        /// Solo entradas con al menos esta importancia (1-5)
+        #[arg(long, value_parser = clap::value_parser!(i64).range(1..=5))]
+        min_importance: Option<i64>,
+        /// Solo entradas fijadas
+        #[arg(long)]
+        pinned: bool,
+        /// Número máximo de entradas
+        #[arg(long)]
+        limit: Option<usize>,
+        /// Archivo a generar, relativo al proyecto (default: el del formato)
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+    /// Archivar o eliminar las entradas vencidas
+    Gc {
+        /// Eliminarlas aunque `retention.expired` indique archivarlas
+        #[arg(long)]
+        purge: bool,
+        /// Solo este store (default: todos)
+        #[arg(long)]
+        store: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigAction {
+    /// Mostrar la configuración efectiva (todas las capas combinadas) en TOML
+    Show,
+}
+
+#[derive(Subcommand, Debug)]
+enum TypesAction {
+    /// Listar los tipos registrados
+    List,
+    /// Registrar un tipo o cambiar la definición de uno existente
+    Add {
+        /// Nombre (minúsculas, dígitos, `_` y `-`)
+        name: String,
+        /// Para qué sirve el tipo (se muestra en tools/list); obligatorio para tipos nuevos
+        #[arg(long)]
+        description: Option<String>,
+        /// Campo estructurado como `nombre=descripción` o `nombre:list=descripción` (lista de
+        /// textos); se puede repetir y reemplaza los que tenía; `--field ""` los quita
+        #[arg(long = "field")]
+        fields: Option<Vec<String>>,
+        /// Argumentos de mem_save (tags, file_paths, git_commit, branch, project_path) o campos
+        /// estructurados obligatorios para este tipo, separados por comas; `--required ""` quita los que tenía
+        #[arg(long, value_delimiter = ',')]
+        required: Option<Vec<String>>,
+        /// Importancia de las entradas que no indican otra (1-5)
+        #[arg(long, value_parser = clap::value_parser!(i64).range(1..=5))]
+        importance: Option<i64>,
+    },
+    /// Eliminar un tipo definido por el equipo que no tenga entradas
+    Remove {
+        name: String,
+    },
+}
+
+impl Args {
+    /// Capa de configuración con los valores dados explícitamente por línea de comandos
+    fn config_layer(&self) -> toml::Table {
+        use toml::Value;
+
+        let mut layer = toml::Table::new();
+        let mut set = |keys: &[&str], value: Value| {
+            let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+            config::merge(&mut layer, config::nest(&keys, value));
+        };
+        let path = |p: &PathBuf| Value::String(p.display().to_string());
+        let list = |items: &[String]| Value::Array(items.iter().cloned().map(Value::String).collect());
+
+        if let Some(ref database) = self.database {
+            set(&["database"], path(database));
+        }
+        if let Some(ref log_file) = self.log_file {
+            set(&["log_file"], path(log_file));
+        }
+        if self.metrics || self.no_metrics {
+            set(&["metrics", "enabled"], Value::Boolean(self.metrics));
+        }
+        if let Some(ref metrics_db) = self.metrics_db {
+            set(&["metrics", "database"], path(metrics_db));
+        }
+        if !self.disabled_tools.is_empty() {
+            set(&["tools", "disabled"], list(&self.disabled_tools));
+        }
+        if self.read_only {
+            set(&["access", "read_only"], Value::Boolean(true));
+        }
+        for client in &self.read_only_clients {
+            set(&["access", "clients", client, "read_only"], Value::Boolean(true));
+        }
+        for (key, items) in [
+            ("allow_tools", &self.allow_tools),
+            ("deny_tools", &self.deny_tools),
+            ("allow_projects", &self.allow_projects),
+            ("deny_projects", &self.deny_projects),
+        ] {
+            if !items.is_empty() {
+                set(&["access", key], list(items));
+            }
+        }
+        layer
+    }
 }
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args = Args::parse();
-    
+
+    let cwd = std::env::current_dir()?;
+    let (config, sources) = config::Config::load(args.config.as_deref(), &cwd, args.config_layer())?;
+
+    if let Some(Command::Config { action: ConfigAction::Show }) = args.command {
+        println!("# Configuración efectiva");
+        if sources.is_empty() {
+            println!("# Fuentes: (solo valores por defecto)");
+        } else {
+            println!("# Fuentes (de menor a mayor precedencia): {}", sources.join(", "));
+        }
+        println!();
+        print!("{}", toml::to_string_pretty(&config)?);
+        return Ok(());
+    }
+
+    // En stdio los logs viajan al cliente como notifications/message (nunca a stderr)
+    let logger = logging::LogForwarder::new();
+    logging::init(&logger, config.log_file.as_deref())?;
+
     // Solo mostrar mensajes de inicio si NO estamos en modo stdio (para evitar confundir a Cursor)
     // En modo stdio, Cursor espera solo JSON-RPC en stdout, cualquier cosa en stderr puede causar errores
     if args.transport != "stdio" {
         eprintln!("🚀 Iniciando servidor MCP de memoria persistente...");
-        eprintln!("📁 Base de datos: {:?}", args.database);
+        eprintln!("📁 Base de datos: {:?}", config.database);
         eprintln!("🚇 Transporte: {}", args.transport);
     }
+    tracing::info!(sources = ?sources, "Configuración cargada");
 
     // Inicializar módulo de memoria
-    let memory_db = memory::Memory::new(&args.database)?;
+    let stores = stores::Stores::open(&config)?;
     if args.transport != "stdio" {
         eprintln!("✅ Base de datos inicializada");
     }
 
+    if let Some(Command::Types { ref action, ref store }) = args.command {
+        let memory = &stores
+            .get(store.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", store.as_deref().unwrap_or_default()))?
+            .memory;
+        match action {
+            TypesAction::List => println!("{}", serde_json::to_string_pretty(&memory.knowledge_types()?)?),
+            TypesAction::Add { name, description, fields, required, importance } => {
+                let change = types::TypeChange {
+                    description: description.clone(),
+                    fields: fields.clone(),
+                    required: required.clone(),
+                    importance: *importance,
+                };
+                let def = types::define_type(memory, name, change)?;
+                println!("{}", serde_json::to_string_pretty(&def)?);
+            }
+            TypesAction::Remove { name } => {
+                types::remove_type(memory, name)?;
+                println!("Tipo '{}' eliminado", name);
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Verify { ref project, all, id, ref store }) = args.command {
+        let project = match (project, all || id.is_some()) {
+            (_, true) => None,
+            (Some(path), false) => Some(project::detect_project(path)),
+            (None, false) => project::find_git_root(&cwd).map(|root| project::detect_project(&root)),
+        };
+        let selected: Vec<&stores::Store> = match (store, id) {
+            (None, None) => stores.iter().collect(),
+            (store, _) => vec![stores
+                .get(store.as_deref())
+                .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", store.as_deref().unwrap_or_default()))?],
+        };
+        let report = verify::verify_entries(selected, project.map(|p| p.key).as_deref(), id, |_| true, |_, _| true)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if let Some(Command::Sync { ref dir, git, ref store }) = args.command {
+        let dir = dir
+            .clone()
+            .or_else(|| config.sync.dir.clone())
+            .ok_or_else(|| anyhow::anyhow!("Indique --dir o sync.dir en la configuración"))?;
+        let store = stores
+            .get(store.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", store.as_deref().unwrap_or_default()))?;
+        let report = sync::sync(&store.memory, &dir, git || config.sync.git)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if let Some(Command::Import { ref project, ref store, ref files }) = args.command {
+        let project = project::detect_project(project.as_deref().unwrap_or(&cwd));
+        let store = stores
+            .get(store.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", store.as_deref().unwrap_or_default()))?;
+        let prune = files.is_empty();
+        let files: Vec<PathBuf> = if prune {
+            import::discover(&project.dir)
+        } else {
+            files.iter().map(|f| std::fs::canonicalize(f).unwrap_or_else(|_| f.clone())).collect()
+        };
+        let report = import::import(&store.memory, &project, &files, prune)?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if let Some(Command::ExportRules {
+        target,
+        ref project,
+        ref store,
+        ref types,
+        min_importance,
+        pinned,
+        limit,
+        ref output,
+    }) = args.command
+    {
+        let project = project::detect_project(project.as_deref().unwrap_or(&cwd));
+        let store = stores
+            .get(store.as_deref())
+            .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", store.as_deref().unwrap_or_default()))?;
+        let filter = memory::ExportFilter {
+            types: types.clone(),
+            min_importance,
+            pinned_only: pinned,
+            limit,
+            ..Default::default()
+        };
+        let report = export::export(&store.memory, &project, target, filter, output.as_deref())?;
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    if let Some(Command::Gc { purge, ref store }) = args.command {
+        let selected: Vec<&stores::Store> = match store {
+            None => stores.iter().collect(),
+            Some(name) => vec![stores
+                .get(Some(name))
+                .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", name))?],
+        };
+        let action = if purge { config::ExpiredAction::Purge } else { config.retention.expired };
+        let reports = retention::collect(selected, action)?;
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+        return Ok(());
+    }
+
     // Inicializar métricas si están habilitadas
-    let metrics_db_path = args.metrics_db.unwrap_or_else(|| {
-        // Usar la misma base de datos pero con sufijo _metrics
-        let mut path = args.database.clone();
-        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("memory");
-        path.set_file_name(format!("{}_metrics.db", stem));
-        path
-    });
-
-    let metrics = if args.metrics {
+    let metrics_db_path = config.metrics_database();
+
+    let metrics = if config.metrics.enabled {
         match metrics::Metrics::new(&metrics_db_path) {
             Ok(m) => {
                 if args.transport != "stdio" {
@@ -91,6 +452,13 @@ async fn main() -> anyhow::Result<()> {
         if let Some(ref m) = metrics {
             match m.get_server_stats() {
                 Ok(stats) => {
+                    // Más el uso de las entradas de cada store
+                    let mut stats = serde_json::to_value(&stats)?;
+                    let mut usage = serde_json::Map::new();
+                    for store in stores.iter() {
+                        usage.insert(store.name.clone(), serde_json::to_value(store.memory.usage_report(10)?)?);
+                    }
+                    stats["usage"] = usage.into();
                     println!("{}", serde_json::to_string_pretty(&stats)?);
                 }
                 Err(e) => {
@@ -103,20 +471,50 @@ async fn main() -> anyhow::Result<()> {
         return Ok(());
     }
 
+    // Retención de métricas: descartar solicitudes más antiguas que lo configurado
+    if let (Some(ref m), Some(days)) = (&metrics, config.retention.metrics_days) {
+        match m.purge_older_than(days) {
+            Ok(purged) => tracing::info!(purged, days, "Métricas antiguas eliminadas"),
+            Err(e) => tracing::warn!(error = %e, "No se pudieron purgar métricas antiguas"),
+        }
+    }
+
     // En modo stdio, NO escribir a stderr para evitar confundir a Cursor
     // Cursor espera solo JSON-RPC válido en stdout
 
+    let mut tools = tools::ToolRegistry::with_builtin_tools();
+    if let Some(ref enabled) = config.tools.enabled {
+        tools.retain_only(enabled);
+    }
+    for unknown in tools.disable(&config.tools.disabled) {
+        tracing::warn!(tool = unknown, "Herramienta a deshabilitar desconocida");
+    }
+
+    let state = mcp_server::ServerState {
+        stores: Arc::new(stores),
+        metrics,
+        logger,
+        tools,
+        client: std::sync::Mutex::new(mcp_server::ClientState {
+            // Hasta conocer las raíces del cliente, el proyecto es el repositorio git actual
+            project: project::find_git_root(&cwd).map(|root| project::detect_project(&root)),
+            ..Default::default()
+        }),
+        config: Arc::new(config),
+        journal: Default::default(),
+    };
+
     // Iniciar servidor MCP
     match args.transport.as_str() {
         "stdio" => {
             // NO escribir a stderr en modo stdio - Cursor puede interpretarlo como error
-            mcp_server::run_stdio_server(memory_db, metrics).await?;
+            mcp_server::run_stdio_server(state).await?;
         }
         "http" => {
             if args.transport != "stdio" {
                 eprintln!("🌐 Modo HTTP: servidor en puerto {}", args.port);
             }
-            mcp_server::run_http_server(memory_db, args.port, metrics).await?;
+            mcp_server::run_http_server(state, args.port).await?;
         }
         _ => {
             anyhow::bail!("Transporte no soportado: {}. Use 'stdio' o 'http'", args.transport);
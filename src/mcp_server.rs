@@ -2,12 +2,28 @@
 //! 
 //! Maneja la comunicación con IDEs usando el protocolo MCP estándar.
 
-use crate::memory::{Memory, KnowledgeType};
+use crate::access::AccessPolicy;
+use crate::config::Config;
+use crate::logging::LogForwarder;
+use crate::memory::SessionInfo;
+use crate::stores::Stores;
 use crate::metrics::Metrics;
+use crate::mirror;
+use crate::retention;
+use crate::project::{self, Project};
+use crate::schema::InvalidArguments;
+use crate::session::{self, Journal};
+use crate::tools::{SaveTool, ToolContext, ToolRegistry};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::io::{self, BufRead, BufReader, Write};
-use std::time::Instant;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinSet;
 
 #[derive(Debug, Serialize, Deserialize)]
 struct MCPRequest {
@@ -36,134 +52,459 @@ struct MCPError {
     data: Option<Value>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Tool {
-    name: String,
-    description: String,
-    input_schema: Value,
+/// Estado compartido por todas las solicitudes del servidor
+pub struct ServerState {
+    pub stores: Arc<Stores>,
+    pub metrics: Option<Metrics>,
+    pub logger: LogForwarder,
+    pub tools: ToolRegistry,
+    pub client: StdMutex<ClientState>,
+    pub config: Arc<Config>,
+    /// Lo hecho en la sesión, para su resumen
+    pub journal: Arc<Journal>,
+}
+
+/// Lo que se sabe del cliente conectado
+#[derive(Debug, Default)]
+pub struct ClientState {
+    /// `clientInfo.name` recibido en `initialize`
+    pub name: Option<String>,
+    /// El cliente declaró la capacidad `roots`
+    pub supports_roots: bool,
+    /// Proyectos de las raíces del cliente
+    pub roots: Vec<Project>,
+    /// Proyecto activo: default de `project_path` al guardar y buscar
+    pub project: Option<Project>,
+    /// Sesión abierta en `initialize`
+    pub session: Option<SessionInfo>,
+}
+
+impl ServerState {
+    /// Política de acceso del cliente conectado
+    pub fn access_policy(&self) -> AccessPolicy {
+        let client = self.client.lock().unwrap();
+        self.config.access.for_client(client.name.as_deref()).clone()
+    }
+
+    /// Proyecto activo del cliente conectado
+    pub fn active_project(&self) -> Option<Project> {
+        self.client.lock().unwrap().project.clone()
+    }
 }
 
-pub async fn run_stdio_server(memory: Memory, metrics: Option<Metrics>) -> anyhow::Result<()> {
-    let stdin = io::stdin();
-    let mut reader = BufReader::new(stdin.lock());
-    let mut stdout = io::stdout();
+pub async fn run_stdio_server(state: ServerState) -> anyhow::Result<()> {
+    serve(state, tokio::io::stdin(), tokio::io::stdout()).await
+}
+
+/// Atiende las solicitudes JSON-RPC de `input` (una por línea) hasta que se cierra,
+/// respondiendo por `output`
+pub async fn serve<R, W>(state: ServerState, input: R, mut output: W) -> anyhow::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let state = Arc::new(state);
+    let logger = state.logger.clone();
+
+    if state.config.mirror.enabled {
+        tokio::spawn(mirror::watch(state.clone()));
+    }
+    if state.config.retention.gc_interval_minutes > 0 {
+        tokio::spawn(retention::watch(state.clone()));
+    }
+
+    // Tarea escritora: es la única que toca la salida, así las respuestas de
+    // solicitudes concurrentes nunca se intercalan a mitad de línea
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<String>();
+    let writer = tokio::spawn(async move {
+        while let Some(line) = out_rx.recv().await {
+            output.write_all(line.as_bytes()).await?;
+            output.write_all(b"\n").await?;
+            output.flush().await?;
+        }
+        Ok::<(), io::Error>(())
+    });
+    logger.attach(out_tx.clone());
+    let peer = Peer::new(out_tx.clone());
+
+    let mut lines = BufReader::new(input).lines();
+    let mut tasks = JoinSet::new();
+    let in_flight: InFlight = Arc::new(StdMutex::new(HashMap::new()));
 
     loop {
-        let mut line = String::new();
-        match reader.read_line(&mut line) {
-            Ok(0) => break, // EOF
-            Ok(_) => {
-                let line = line.trim();
-                if line.is_empty() {
-                    continue;
-                }
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break, // EOF
+            Err(e) => {
+                // Error fatal leyendo stdin: no se puede seguir atendiendo al cliente
+                tracing::error!(error = %e, "Error leyendo stdin");
+                break;
+            }
+        };
 
-                let start_time = Instant::now();
-                let request_line = line.to_string();
-
-                // Extraer id de la solicitud para poder responder con error si es necesario
-                let request_id: Option<Value> = serde_json::from_str::<serde_json::Value>(&request_line)
-                    .ok()
-                    .and_then(|v| v.get("id").cloned())
-                    .and_then(|v| if v.is_null() { None } else { Some(v) });
-
-                let result = handle_request(&memory, &request_line, &metrics).await;
-                let response_time = start_time.elapsed();
-
-                match result {
-                    Ok(Some(response)) => {
-                        let response_json = serde_json::to_string(&response)?;
-                        let response_size = response_json.len();
-                        
-                        // Registrar métricas
-                        if let Some(ref m) = metrics {
-                            let tool_name = extract_tool_name(&request_line);
-                            let _ = m.record_request(
-                                "mcp_request",
-                                tool_name.as_deref(),
-                                response_time,
-                                response_size,
-                                true,
-                                None,
-                            );
-                        }
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
 
-                        writeln!(stdout, "{}", response_json)?;
-                        stdout.flush()?;
-                    }
-                    Ok(None) => {
-                        // No response needed (notifications)
-                        if let Some(ref m) = metrics {
-                            let tool_name = extract_tool_name(&request_line);
-                            let _ = m.record_request(
-                                "mcp_notification",
-                                tool_name.as_deref(),
-                                response_time,
-                                0,
-                                true,
-                                None,
-                            );
-                        }
-                    }
-                    Err(e) => {
-                        // Si hay un id, debemos enviar una respuesta de error JSON-RPC
-                        if let Some(id) = request_id {
-                            let error_response = MCPResponse {
-                                jsonrpc: "2.0".to_string(),
-                                id: Some(id),
-                                result: None,
-                                error: Some(MCPError {
-                                    code: -32700,
-                                    message: format!("Error procesando solicitud: {}", e),
-                                    data: None,
-                                }),
-                            };
-                            let response_json = serde_json::to_string(&error_response)?;
-                            
-                            // Registrar error en métricas
-                            if let Some(ref m) = metrics {
-                                let tool_name = extract_tool_name(&request_line);
-                                let _ = m.record_request(
-                                    "mcp_request",
-                                    tool_name.as_deref(),
-                                    response_time,
-                                    response_json.len(),
-                                    false,
-                                    Some(&e.to_string()),
-                                );
-                            }
-                            
-                            writeln!(stdout, "{}", response_json)?;
-                            stdout.flush()?;
-                        } else {
-                            // No hay id, es una notificación - solo registrar métricas
-                            if let Some(ref m) = metrics {
-                                let tool_name = extract_tool_name(&request_line);
-                                let _ = m.record_request(
-                                    "mcp_notification",
-                                    tool_name.as_deref(),
-                                    response_time,
-                                    0,
-                                    false,
-                                    Some(&e.to_string()),
-                                );
-                            }
-                        }
-                    }
+        let parsed = serde_json::from_str::<Value>(line).ok();
+        let method = parsed.as_ref().and_then(|v| v.get("method")).and_then(|m| m.as_str());
+
+        // Sin `method` es la respuesta del cliente a una solicitud del servidor
+        if let Some(ref message) = parsed {
+            if method.is_none() && message.get("id").is_some() {
+                peer.resolve(message);
+                continue;
+            }
+        }
+
+        // La cancelación se atiende aquí mismo, sin esperar turno, para que
+        // llegue a la solicitud en curso lo antes posible
+        if method == Some("notifications/cancelled") {
+            if let Some(target) = parsed
+                .as_ref()
+                .and_then(|v| v.pointer("/params/requestId"))
+            {
+                if let Some(flag) = in_flight.lock().unwrap().get(&request_key(target)) {
+                    flag.store(true, Ordering::SeqCst);
                 }
             }
-            Err(_e) => {
-                // Error fatal - solo en este caso escribir a stderr
-                // Pero en producción, mejor salir silenciosamente
-                // eprintln!("Error leyendo stdin: {}", _e);
-                break;
+        }
+
+        let request_id = parsed
+            .as_ref()
+            .and_then(|v| v.get("id"))
+            .filter(|v| !v.is_null())
+            .cloned();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        if let Some(ref id) = request_id {
+            let mut in_flight = in_flight.lock().unwrap();
+            // Un id repetido no puede reemplazar la bandera de la solicitud en curso:
+            // la cancelación llegaría a la que no es
+            if in_flight.contains_key(&request_key(id)) {
+                let response = MCPResponse {
+                    jsonrpc: "2.0".to_string(),
+                    id: Some(id.clone()),
+                    result: None,
+                    error: Some(MCPError {
+                        code: -32600,
+                        message: format!("Ya hay una solicitud en curso con el id {}", id),
+                        data: None,
+                    }),
+                };
+                peer.send_line(serde_json::to_string(&response)?);
+                continue;
             }
+            in_flight.insert(request_key(id), cancelled.clone());
         }
+        let ctx = RequestContext {
+            progress_token: parsed
+                .as_ref()
+                .and_then(|v| v.pointer("/params/_meta/progressToken"))
+                .cloned(),
+            cancelled,
+            peer: peer.clone(),
+        };
+
+        // Cada solicitud se procesa en su propia tarea: una búsqueda lenta no bloquea
+        // un `ping` posterior. JSON-RPC permite responder en cualquier orden.
+        let state = state.clone();
+        let in_flight = in_flight.clone();
+        let line = line.to_string();
+        tasks.spawn(async move {
+            process_line(state, ctx, line).await;
+            if let Some(id) = request_id {
+                in_flight.lock().unwrap().remove(&request_key(&id));
+            }
+        });
+
+        // Liberar las tareas ya terminadas
+        while tasks.try_join_next().is_some() {}
+    }
+
+    // Esperar a que terminen las solicitudes en curso antes de cerrar stdout
+    while tasks.join_next().await.is_some() {}
+    if state.config.session.summarize_on_exit {
+        summarize_session(state.clone()).await;
     }
+    end_session(state.clone()).await;
+    logger.detach();
+    drop(peer);
+    drop(out_tx);
+    writer.await??;
 
     Ok(())
 }
 
+/// Guarda el resumen de la sesión en el store por defecto
+///
+/// Solo si en la sesión se guardó o actualizó algo y el cliente podría haber
+/// guardado el resumen él mismo: un cliente de solo lectura, sin `mem_save` o sin
+/// acceso al proyecto activo no escribe memoria al cerrar.
+async fn summarize_session(state: Arc<ServerState>) {
+    let access = state.access_policy();
+    let project = state.active_project().map(|project| project.key);
+    if !access.tool_allowed(&SaveTool) || !access.project_allowed(project.as_deref()) {
+        return;
+    }
+    let result = tokio::task::spawn_blocking(move || {
+        let journal = state.journal.take();
+        let Some(store) = state.stores.get(None).filter(|_| journal.has_changes()) else {
+            return Ok(None);
+        };
+        let session = state.client.lock().unwrap().session.clone();
+        session::summarize(&state.stores, store, &state.config, session.as_ref(), journal, project, None)
+    })
+    .await;
+    match result {
+        Ok(Ok(Some(id))) => tracing::info!(id, "Resumen de la sesión guardado"),
+        Ok(Ok(None)) => {}
+        Ok(Err(e)) => tracing::warn!(error = %e, "No se pudo guardar el resumen de la sesión"),
+        Err(e) => tracing::warn!(error = %e, "No se pudo guardar el resumen de la sesión"),
+    }
+}
+
+/// Registra el fin de la sesión con el último proyecto activo
+async fn end_session(state: Arc<ServerState>) {
+    let (session_id, project) = {
+        let client = state.client.lock().unwrap();
+        (client.session.as_ref().map(|session| session.id.clone()), client.project.as_ref().map(|project| project.key.clone()))
+    };
+    let Some(session_id) = session_id else {
+        return;
+    };
+    let read_only = state.access_policy().read_only;
+    let result = tokio::task::spawn_blocking(move || {
+        let now = chrono::Utc::now().timestamp();
+        if !read_only {
+            state.stores.end_session(&session_id, project.as_deref(), now)?;
+        }
+        if let Some(ref m) = state.metrics {
+            m.end_session(&session_id, project.as_deref(), now)?;
+        }
+        Ok::<_, rusqlite::Error>(())
+    })
+    .await;
+    if let Ok(Err(e)) = result {
+        tracing::warn!(error = %e, "No se pudo registrar el fin de la sesión");
+    }
+}
+
+/// Solicitudes en curso indexadas por id, con su bandera de cancelación
+type InFlight = Arc<StdMutex<HashMap<String, Arc<AtomicBool>>>>;
+
+/// Clave de una solicitud en `InFlight`: el id serializado, así `1` y `"1"` no colisionan
+fn request_key(id: &Value) -> String {
+    id.to_string()
+}
+
+/// Contexto de una solicitud en curso
+///
+/// Permite a las operaciones largas emitir `notifications/progress` (si el cliente
+/// envió `_meta.progressToken`) y detectar `notifications/cancelled`.
+#[derive(Clone)]
+pub struct RequestContext {
+    progress_token: Option<Value>,
+    cancelled: Arc<AtomicBool>,
+    pub peer: Peer,
+}
+
+impl RequestContext {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Devuelve error si el cliente canceló la solicitud
+    pub fn check_cancelled(&self) -> anyhow::Result<()> {
+        if self.is_cancelled() {
+            anyhow::bail!("Solicitud cancelada por el cliente");
+        }
+        Ok(())
+    }
+
+    /// Emite `notifications/progress`; no hace nada si el cliente no pidió progreso
+    pub fn progress(&self, progress: u64, total: Option<u64>, message: Option<&str>) {
+        let Some(ref token) = self.progress_token else {
+            return;
+        };
+        let mut params = serde_json::json!({
+            "progressToken": token,
+            "progress": progress,
+        });
+        if let Some(total) = total {
+            params["total"] = total.into();
+        }
+        if let Some(message) = message {
+            params["message"] = message.into();
+        }
+        self.peer.notify("notifications/progress", params);
+    }
+}
+
+/// Tiempo máximo de espera de la respuesta del cliente a una solicitud del servidor
+const PEER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Respuestas pendientes a solicitudes del servidor, indexadas por id
+type PendingRequests = Arc<StdMutex<HashMap<String, oneshot::Sender<Result<Value, Value>>>>>;
+
+/// Canal hacia el cliente: notificaciones y solicitudes iniciadas por el servidor
+/// (por ejemplo `roots/list`)
+#[derive(Clone)]
+pub struct Peer {
+    out_tx: mpsc::UnboundedSender<String>,
+    pending: PendingRequests,
+    next_id: Arc<AtomicU64>,
+}
+
+impl Peer {
+    fn new(out_tx: mpsc::UnboundedSender<String>) -> Self {
+        Peer {
+            out_tx,
+            pending: Arc::new(StdMutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    /// Envía una línea JSON ya serializada
+    fn send_line(&self, line: String) {
+        // Si la tarea escritora terminó (stdout cerrado) no hay a quién enviar
+        let _ = self.out_tx.send(line);
+    }
+
+    pub fn notify(&self, method: &str, params: Value) {
+        let notification = serde_json::json!({
+            "jsonrpc": "2.0",
+            "method": method,
+            "params": params,
+        });
+        self.send_line(notification.to_string());
+    }
+
+    /// Envía una solicitud al cliente y espera su respuesta
+    pub async fn request(&self, method: &str, params: Value) -> anyhow::Result<Value> {
+        // Prefijo propio para no confundirse con los ids que usa el cliente
+        let id = Value::String(format!("srv-{}", self.next_id.fetch_add(1, Ordering::SeqCst)));
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request_key(&id), tx);
+
+        let request = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        });
+        self.send_line(request.to_string());
+
+        let outcome = tokio::time::timeout(PEER_REQUEST_TIMEOUT, rx).await;
+        self.pending.lock().unwrap().remove(&request_key(&id));
+        match outcome {
+            Ok(Ok(Ok(result))) => Ok(result),
+            Ok(Ok(Err(error))) => anyhow::bail!("El cliente respondió con error a {}: {}", method, error),
+            Ok(Err(_)) => anyhow::bail!("Conexión cerrada esperando respuesta a {}", method),
+            Err(_) => anyhow::bail!("Sin respuesta del cliente a {}", method),
+        }
+    }
+
+    /// Entrega una respuesta del cliente a la solicitud que la espera
+    fn resolve(&self, message: &Value) {
+        let Some(id) = message.get("id") else {
+            return;
+        };
+        let Some(tx) = self.pending.lock().unwrap().remove(&request_key(id)) else {
+            tracing::debug!(id = %id, "Respuesta del cliente sin solicitud pendiente");
+            return;
+        };
+        let outcome = match message.get("error") {
+            Some(error) => Err(error.clone()),
+            None => Ok(message.get("result").cloned().unwrap_or(Value::Null)),
+        };
+        let _ = tx.send(outcome);
+    }
+}
+
+async fn process_line(state: Arc<ServerState>, ctx: RequestContext, request_line: String) {
+    let start_time = Instant::now();
+
+    // Extraer id de la solicitud para poder responder con error si es necesario
+    let request_id: Option<Value> = serde_json::from_str::<serde_json::Value>(&request_line)
+        .ok()
+        .and_then(|v| v.get("id").cloned())
+        .and_then(|v| if v.is_null() { None } else { Some(v) });
+
+    let result = handle_request(&state, &request_line, &ctx).await;
+    let response_time = start_time.elapsed();
+    let tool_name = extract_tool_name(&request_line);
+
+    if let Err(ref e) = result {
+        tracing::warn!(error = %e, "Solicitud inválida");
+    }
+
+    let (method, response_json, error) = match result {
+        Ok(Some(response)) => match serde_json::to_string(&response) {
+            Ok(json) => ("mcp_request", Some(json), None),
+            Err(e) => ("mcp_request", None, Some(e.to_string())),
+        },
+        // No response needed (notifications)
+        Ok(None) => ("mcp_notification", None, None),
+        Err(e) => {
+            // Si hay un id, debemos enviar una respuesta de error JSON-RPC;
+            // si no, es una notificación y solo se registran métricas
+            match request_id {
+                Some(id) => {
+                    let error_response = MCPResponse {
+                        jsonrpc: "2.0".to_string(),
+                        id: Some(id),
+                        result: None,
+                        error: Some(MCPError {
+                            code: -32700,
+                            message: format!("Error procesando solicitud: {}", e),
+                            data: None,
+                        }),
+                    };
+                    (
+                        "mcp_request",
+                        serde_json::to_string(&error_response).ok(),
+                        Some(e.to_string()),
+                    )
+                }
+                None => ("mcp_notification", None, Some(e.to_string())),
+            }
+        }
+    };
+
+    // Una solicitud cancelada no debe recibir respuesta
+    let (response_json, error) = if ctx.is_cancelled() {
+        (None, Some("Solicitud cancelada por el cliente".to_string()))
+    } else {
+        (response_json, error)
+    };
+
+    let response_size = response_json.as_ref().map(|s| s.len()).unwrap_or(0);
+    if let Some(json) = response_json {
+        ctx.peer.send_line(json);
+    }
+
+    // Registrar métricas fuera del runtime asíncrono: es una escritura SQLite
+    if state.metrics.is_some() {
+        let session_id = state.client.lock().unwrap().session.as_ref().map(|session| session.id.clone());
+        let _ = tokio::task::spawn_blocking(move || {
+            let Some(ref m) = state.metrics else {
+                return Ok(());
+            };
+            m.record_request(
+                session_id.as_deref(),
+                method,
+                tool_name.as_deref(),
+                response_time,
+                response_size,
+                error.is_none(),
+                error.as_deref(),
+            )
+        })
+        .await;
+    }
+}
+
 fn extract_tool_name(request_json: &str) -> Option<String> {
     if let Ok(value) = serde_json::from_str::<serde_json::Value>(request_json) {
         if let Some(params) = value.get("params") {
@@ -176,9 +517,9 @@ fn extract_tool_name(request_json: &str) -> Option<String> {
 }
 
 async fn handle_request(
-    memory: &Memory,
+    state: &Arc<ServerState>,
     request_json: &str,
-    _metrics: &Option<Metrics>,
+    ctx: &RequestContext,
 ) -> anyhow::Result<Option<MCPResponse>> {
     let request: MCPRequest = serde_json::from_str(request_json)?;
     
@@ -193,15 +534,51 @@ async fn handle_request(
     
     // Si no hay id válido, es una notificación y no debemos responder
     if id.is_none() {
+        handle_notification(state, &request.method, request.params.as_ref(), ctx).await;
         return Ok(None);
     }
 
     let result = match request.method.as_str() {
         "initialize" => {
+            let client = request
+                .params
+                .as_ref()
+                .and_then(|p| p.pointer("/clientInfo/name"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("desconocido");
+            let supports_roots = request
+                .params
+                .as_ref()
+                .and_then(|p| p.pointer("/capabilities/roots"))
+                .is_some();
+            let session = SessionInfo {
+                id: uuid::Uuid::new_v4().to_string(),
+                client: Some(client.to_string()),
+                project_path: state.active_project().map(|project| project.key),
+                started_at: chrono::Utc::now().timestamp(),
+                ended_at: None,
+                entries: 0,
+            };
+            tracing::info!(client, supports_roots, session = session.id, "Cliente inicializado");
+            {
+                let mut client_state = state.client.lock().unwrap();
+                client_state.name = Some(client.to_string());
+                client_state.supports_roots = supports_roots;
+                client_state.session = Some(session.clone());
+            }
+            state.journal.start();
+            let state = state.clone();
+            match tokio::task::spawn_blocking(move || start_session(&state, &session)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => tracing::warn!(error = %e, "No se pudo registrar la sesión"),
+                Err(e) => tracing::warn!(error = %e, "No se pudo registrar la sesión"),
+            }
+
             serde_json::json!({
                 "protocolVersion": "2024-11-05",
                 "capabilities": {
-                    "tools": {}
+                    "tools": {},
+                    "logging": {}
                 },
                 "serverInfo": {
                     "name": "IDE_Memory",
@@ -209,101 +586,46 @@ async fn handle_request(
                 }
             })
         }
+        "ping" => serde_json::json!({}),
+        "logging/setLevel" => {
+            let level = request
+                .params
+                .as_ref()
+                .and_then(|p| p.get("level"))
+                .and_then(|v| v.as_str());
+            match level.map(|l| state.logger.set_level(l)) {
+                Some(Ok(())) => serde_json::json!({}),
+                Some(Err(e)) => return Ok(Some(invalid_params(id, e.to_string()))),
+                None => {
+                    return Ok(Some(invalid_params(
+                        id,
+                        "Falta 'level' en parámetros de logging/setLevel".to_string(),
+                    )))
+                }
+            }
+        }
         "tools/list" => {
-            let tools = vec![
-                Tool {
-                    name: "mem_search".to_string(),
-                    description: "Busca conocimiento relevante usando búsqueda compacta (Progressive Disclosure Capa 1)".to_string(),
-                    input_schema: serde_json::json!({
-                        "type": "object",
-                        "properties": {
-                            "query": {
-                                "type": "string",
-                                "description": "Consulta de búsqueda"
-                            },
-                            "limit": {
-                                "type": "integer",
-                                "description": "Número máximo de resultados",
-                                "default": 5
-                            }
-                        },
-                        "required": ["query"]
-                    }),
-                },
-                Tool {
-                    name: "mem_save".to_string(),
-                    description: "Guarda una entrada de conocimiento en la memoria persistente".to_string(),
-                    input_schema: serde_json::json!({
-                        "type": "object",
-                        "properties": {
-                            "knowledge_type": {
-                                "type": "string",
-                                "enum": ["decision", "bugfix", "pattern", "config", "context", "summary"],
-                                "description": "Tipo de conocimiento"
-                            },
-                            "title": {
-                                "type": "string",
-                                "description": "Título de la entrada"
-                            },
-                            "content": {
-                                "type": "string",
-                                "description": "Contenido completo"
-                            },
-                            "summary": {
-                                "type": "string",
-                                "description": "Resumen compacto (~100 tokens)"
-                            },
-                            "tags": {
-                                "type": "array",
-                                "items": {"type": "string"},
-                                "description": "Tags para categorización"
-                            },
-                            "project_path": {
-                                "type": "string",
-                                "description": "Ruta del proyecto (opcional)"
-                            }
-                        },
-                        "required": ["knowledge_type", "title", "content", "summary"]
-                    }),
-                },
-                Tool {
-                    name: "mem_get_detail".to_string(),
-                    description: "Obtiene el detalle completo de una entrada (Progressive Disclosure Capa 3)".to_string(),
-                    input_schema: serde_json::json!({
-                        "type": "object",
-                        "properties": {
-                            "id": {
-                                "type": "integer",
-                                "description": "ID de la entrada de conocimiento"
-                            }
-                        },
-                        "required": ["id"]
-                    }),
-                },
-                Tool {
-                    name: "mem_timeline".to_string(),
-                    description: "Obtiene el timeline de una entrada (Progressive Disclosure Capa 2)".to_string(),
-                    input_schema: serde_json::json!({
-                        "type": "object",
-                        "properties": {
-                            "id": {
-                                "type": "integer",
-                                "description": "ID de la entrada de conocimiento"
-                            }
-                        },
-                        "required": ["id"]
-                    }),
-                },
-            ];
-            serde_json::json!({
-                "tools": tools
-            })
+            // Los esquemas incluyen los tipos de conocimiento de la base
+            let state = state.clone();
+            let access = state.access_policy();
+            let tools = tokio::task::spawn_blocking(move || state.tools.list(&access, &state.stores)).await?;
+            serde_json::json!({ "tools": tools })
         }
         "tools/call" => {
             if let Some(params) = request.params {
-                match handle_tool_call(memory, params).await {
+                match handle_tool_call(state, params, ctx).await {
                     Ok(result) => result,
                     Err(e) => {
+                        let tool = extract_tool_name(request_json).unwrap_or_default();
+                        tracing::warn!(tool, error = %e, "Error en herramienta");
+                        // Argumentos que no cumplen el esquema: -32602 con detalle por campo
+                        if let Some(invalid) = e.downcast_ref::<InvalidArguments>() {
+                            let mut response = invalid_params(id, invalid.to_string());
+                            if let Some(ref mut error) = response.error {
+                                error.data = Some(serde_json::json!({ "errors": invalid.errors }));
+                            }
+                            return Ok(Some(response));
+                        }
                         // Convertir error en respuesta JSON-RPC con error
                         return Ok(Some(MCPResponse {
                             jsonrpc: "2.0".to_string(),
@@ -318,16 +640,10 @@ async fn handle_request(
                     }
                 }
             } else {
-                return Ok(Some(MCPResponse {
-                    jsonrpc: "2.0".to_string(),
+                return Ok(Some(invalid_params(
                     id,
-                    result: None,
-                    error: Some(MCPError {
-                        code: -32602,
-                        message: "Faltan parámetros para tools/call".to_string(),
-                        data: None,
-                    }),
-                }));
+                    "Faltan parámetros para tools/call".to_string(),
+                )));
             }
         }
         _ => {
@@ -352,104 +668,527 @@ async fn handle_request(
     }))
 }
 
-async fn handle_tool_call(memory: &Memory, params: Value) -> anyhow::Result<Value> {
+async fn handle_notification(
+    state: &Arc<ServerState>,
+    method: &str,
+    _params: Option<&Value>,
+    ctx: &RequestContext,
+) {
+    match method {
+        // Tras `initialized` ya se pueden hacer solicitudes al cliente
+        "notifications/initialized" | "notifications/roots/list_changed"
+            if state.client.lock().unwrap().supports_roots =>
+        {
+            if let Err(e) = refresh_roots(state, &ctx.peer).await {
+                tracing::warn!(error = %e, "No se pudieron obtener las raíces del cliente");
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Registra el inicio de la sesión en las métricas
+///
+/// En los stores se registra recién al guardar su primera entrada en cada uno.
+fn start_session(state: &ServerState, session: &SessionInfo) -> rusqlite::Result<()> {
+    if let Some(ref m) = state.metrics {
+        m.start_session(&session.id, session.client.as_deref(), session.project_path.as_deref(), session.started_at)?;
+    }
+    Ok(())
+}
+
+/// Pide `roots/list` al cliente y actualiza el proyecto activo
+///
+/// Cada raíz se resuelve a su repositorio git (si lo hay) y se normaliza. El
+/// proyecto activo pasa a ser la primera raíz, y también el de la sesión.
+async fn refresh_roots(state: &Arc<ServerState>, peer: &Peer) -> anyhow::Result<()> {
+    let result = peer.request("roots/list", serde_json::json!({})).await?;
+    let roots: Vec<Project> = result
+        .get("roots")
+        .and_then(|r| r.as_array())
+        .map(|roots| {
+            roots
+                .iter()
+                .filter_map(|root| root.get("uri").and_then(|u| u.as_str()))
+                .filter_map(project::uri_to_path)
+                .map(|path| project::detect_project(&path))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let session = {
+        let mut client = state.client.lock().unwrap();
+        let keys: Vec<&str> = roots.iter().map(|root| root.key.as_str()).collect();
+        tracing::info!(roots = ?keys, "Raíces del cliente actualizadas");
+        let first = roots.first().cloned();
+        client.roots = roots;
+        let first = first.map(|first| {
+            client.project = Some(first.clone());
+            first.key
+        });
+        first.zip(client.session.as_mut()).map(|(project, session)| {
+            session.project_path = Some(project.clone());
+            (session.id.clone(), project)
+        })
+    };
+    if let Some((id, project)) = session {
+        let state = state.clone();
+        tokio::task::spawn_blocking(move || update_session_project(&state, &id, &project)).await??;
+    }
+    Ok(())
+}
+
+/// Corrige el proyecto de la sesión, registrado en `initialize` antes de conocer
+/// las raíces del cliente
+///
+/// Un cliente de solo lectura no escribe en los stores (tampoco tiene sesión en ellos).
+fn update_session_project(state: &ServerState, id: &str, project: &str) -> rusqlite::Result<()> {
+    if !state.access_policy().read_only {
+        state.stores.update_session_project(id, project)?;
+    }
+    if let Some(ref m) = state.metrics {
+        m.update_session_project(id, project)?;
+    }
+    Ok(())
+}
+
+/// Respuesta de error JSON-RPC `-32602` (parámetros inválidos)
+fn invalid_params(id: Option<Value>, message: String) -> MCPResponse {
+    MCPResponse {
+        jsonrpc: "2.0".to_string(),
+        id,
+        result: None,
+        error: Some(MCPError {
+            code: -32602,
+            message,
+            data: None,
+        }),
+    }
+}
+
+async fn handle_tool_call(
+    state: &Arc<ServerState>,
+    params: Value,
+    ctx: &RequestContext,
+) -> anyhow::Result<Value> {
     let name = params
         .get("name")
         .and_then(|v| v.as_str())
         .ok_or_else(|| anyhow::anyhow!("Falta 'name' en parámetros"))?;
 
+    let tool = state
+        .tools
+        .get(name)
+        .ok_or_else(|| anyhow::anyhow!("Herramienta desconocida: {}", name))?;
+
+    // Las herramientas ocultas por la política también se rechazan si se llaman
+    let access = state.access_policy();
+    if !access.tool_allowed(tool.as_ref()) {
+        anyhow::bail!("Herramienta no permitida para este cliente: {}", name);
+    }
+
+    // `arguments` es opcional en MCP: sin él se valida un objeto vacío
     let arguments = params
         .get("arguments")
-        .ok_or_else(|| anyhow::anyhow!("Faltan 'arguments' en parámetros"))?;
+        .cloned()
+        .unwrap_or_else(|| Value::Object(Default::default()));
+    let arguments = {
+        let state = state.clone();
+        let tool = tool.clone();
+        tokio::task::spawn_blocking(move || {
+            state.tools.validate(tool.as_ref(), &state.stores, &arguments)?;
+            Ok::<_, InvalidArguments>(arguments)
+        })
+        .await??
+    };
 
-    match name {
-        "mem_search" => {
-            let query = arguments
-                .get("query")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'query' en arguments"))?;
-            let limit = arguments
-                .get("limit")
-                .and_then(|v| v.as_i64())
-                .unwrap_or(5) as i32;
-
-            let results = memory.search_compact(query, limit)?;
-            Ok(serde_json::to_value(results)?)
-        }
-        "mem_save" => {
-            let knowledge_type_str = arguments
-                .get("knowledge_type")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'knowledge_type' en arguments"))?;
-            let knowledge_type = KnowledgeType::from_str(knowledge_type_str)
-                .ok_or_else(|| anyhow::anyhow!("Tipo de conocimiento inválido: {}", knowledge_type_str))?;
+    let session = state.client.lock().unwrap().session.clone();
+    let tool_ctx = ToolContext {
+        stores: state.stores.clone(),
+        request: ctx.clone(),
+        access,
+        config: state.config.clone(),
+        project: state.active_project(),
+        session,
+        journal: state.journal.clone(),
+    };
+    state.journal.record_call(name);
+    tool.call(&tool_ctx, arguments).await
+}
 
-            let title = arguments
-                .get("title")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'title' en arguments"))?;
-            let content = arguments
-                .get("content")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'content' en arguments"))?;
-            let summary = arguments
-                .get("summary")
-                .and_then(|v| v.as_str())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'summary' en arguments"))?;
-
-            let tags: Vec<String> = arguments
-                .get("tags")
-                .and_then(|v| v.as_array())
-                .map(|arr| {
-                    arr.iter()
-                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                        .collect()
-                })
-                .unwrap_or_default();
-
-            let project_path = arguments
-                .get("project_path")
-                .and_then(|v| v.as_str());
+pub async fn run_http_server(_state: ServerState, _port: u16) -> anyhow::Result<()> {
+    // TODO: Implementar servidor HTTP para MCP
+    anyhow::bail!("Modo HTTP aún no implementado. Use 'stdio' por ahora.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MetricsConfig, StoreConfig};
+    use crate::testing::TempDir;
+    use crate::tools::{Tool, ToolAnnotations};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use tokio::io::{DuplexStream, Lines};
+    use tokio::task::JoinHandle;
+
+    /// Herramienta que no termina hasta que la cancelan
+    struct SlowTool;
+
+    #[async_trait]
+    impl Tool for SlowTool {
+        fn name(&self) -> &'static str {
+            "test_slow"
+        }
+
+        fn description(&self) -> String {
+            "Espera a que la cancelen".to_string()
+        }
+
+        fn input_schema(&self) -> Value {
+            json!({ "type": "object", "properties": {} })
+        }
 
-            let id = memory.save_knowledge(
-                knowledge_type,
-                title,
-                content,
-                summary,
-                &tags,
-                project_path,
-            )?;
+        fn annotations(&self) -> ToolAnnotations {
+            ToolAnnotations::read_only()
+        }
+
+        async fn call(&self, ctx: &ToolContext, _arguments: Value) -> anyhow::Result<Value> {
+            ctx.request.progress(0, None, Some("esperando"));
+            for _ in 0..500 {
+                ctx.request.check_cancelled()?;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Ok(json!({ "done": true }))
+        }
+    }
+
+    /// Cliente conectado a un servidor en memoria, con su base en un directorio temporal
+    struct Client {
+        input: DuplexStream,
+        output: Lines<BufReader<DuplexStream>>,
+        server: JoinHandle<anyhow::Result<()>>,
+        _dir: TempDir,
+    }
+
+    impl Client {
+        async fn start(name: &str) -> Client {
+            Client::start_with(name, |_, _| {}).await
+        }
+
+        /// Como `start`, con `configure` para ajustar la configuración del servidor
+        async fn start_with(name: &str, configure: impl FnOnce(&mut Config, &TempDir)) -> Client {
+            let dir = TempDir::new(name);
+            let mut config = Config {
+                database: dir.join("memory.db"),
+                metrics: MetricsConfig { enabled: false, database: None },
+                ..Default::default()
+            };
+            config.retention.gc_interval_minutes = 0;
+            configure(&mut config, &dir);
+            let mut tools = ToolRegistry::with_builtin_tools();
+            tools.register(SlowTool);
+            let state = ServerState {
+                stores: Arc::new(Stores::open(&config).unwrap()),
+                metrics: None,
+                logger: LogForwarder::new(),
+                tools,
+                client: Default::default(),
+                config: Arc::new(config),
+                journal: Default::default(),
+            };
+            let (input, server_input) = tokio::io::duplex(64 * 1024);
+            let (server_output, output) = tokio::io::duplex(64 * 1024);
+            let server = tokio::spawn(serve(state, server_input, server_output));
+            Client {
+                input,
+                output: BufReader::new(output).lines(),
+                server,
+                _dir: dir,
+            }
+        }
 
-            Ok(serde_json::json!({
+        async fn send(&mut self, message: Value) {
+            self.input.write_all(format!("{}\n", message).as_bytes()).await.unwrap();
+        }
+
+        async fn call(&mut self, id: i64, tool: &str, arguments: Value) {
+            self.send(json!({
+                "jsonrpc": "2.0",
                 "id": id,
-                "success": true
+                "method": "tools/call",
+                "params": { "name": tool, "arguments": arguments }
             }))
+            .await;
+        }
+
+        async fn recv(&mut self) -> Value {
+            let line = tokio::time::timeout(Duration::from_secs(5), self.output.next_line())
+                .await
+                .expect("el servidor no respondió")
+                .unwrap()
+                .expect("el servidor cerró la salida");
+            serde_json::from_str(&line).unwrap()
         }
-        "mem_get_detail" => {
-            let id = arguments
-                .get("id")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'id' en arguments"))?;
 
-            match memory.get_detail(id)? {
-                Some(entry) => Ok(serde_json::to_value(entry)?),
-                None => Err(anyhow::anyhow!("Entrada no encontrada: {}", id)),
+        /// Cierra la entrada y devuelve lo que el servidor envió hasta terminar
+        async fn finish(self) -> Vec<Value> {
+            let Client { input, mut output, server, _dir } = self;
+            drop(input);
+            let mut rest = Vec::new();
+            while let Some(line) = output.next_line().await.unwrap() {
+                rest.push(serde_json::from_str(&line).unwrap());
             }
+            server.await.unwrap().unwrap();
+            rest
         }
-        "mem_timeline" => {
-            let id = arguments
-                .get("id")
-                .and_then(|v| v.as_i64())
-                .ok_or_else(|| anyhow::anyhow!("Falta 'id' en arguments"))?;
+    }
+
+    #[tokio::test]
+    async fn ping_answers_while_a_call_is_in_flight() {
+        let mut client = Client::start("server-ping").await;
+        client.call(1, "test_slow", json!({})).await;
+        client.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" })).await;
+        let response = client.recv().await;
+        assert_eq!(response["id"], 2);
+        assert_eq!(response["result"], json!({}));
+
+        // Un id repetido se rechaza sin afectar a la solicitud en curso
+        client.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "ping" })).await;
+        let response = client.recv().await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], -32600);
+
+        client
+            .send(json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": { "requestId": 1 } }))
+            .await;
+        client.finish().await;
+    }
+
+    #[tokio::test]
+    async fn cancelled_request_gets_no_response() {
+        let mut client = Client::start("server-cancel").await;
+        client.call(1, "test_slow", json!({})).await;
+        client
+            .send(json!({ "jsonrpc": "2.0", "method": "notifications/cancelled", "params": { "requestId": 1 } }))
+            .await;
+        client.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "ping" })).await;
+        assert_eq!(client.recv().await["id"], 2);
+
+        let rest = client.finish().await;
+        assert!(rest.iter().all(|message| message.get("id").is_none()), "{:?}", rest);
+    }
 
-            let timeline = memory.get_timeline(id)?;
-            Ok(serde_json::to_value(timeline)?)
+    /// Guarda entradas y reindexa de a una, con o sin `progressToken`
+    async fn reindex_notifications(name: &str, meta: Option<Value>) -> Vec<Value> {
+        let mut client = Client::start(name).await;
+        for (id, title) in [(1, "a"), (2, "b"), (3, "c")] {
+            let entry = json!({ "knowledge_type": "decision", "title": title, "content": "x", "summary": "x" });
+            client.call(id, "mem_save", entry).await;
+            assert_eq!(client.recv().await["result"]["success"], true);
         }
-        _ => Err(anyhow::anyhow!("Herramienta desconocida: {}", name)),
+        let mut params = json!({ "name": "mem_reindex", "arguments": { "batch_size": 1 } });
+        if let Some(meta) = meta {
+            params["_meta"] = meta;
+        }
+        client.send(json!({ "jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": params })).await;
+        let mut notifications = Vec::new();
+        loop {
+            let message = client.recv().await;
+            if message.get("id").is_some() {
+                assert_eq!(message["id"], 4);
+                assert!(message.get("result").is_some(), "{}", message);
+                break;
+            }
+            notifications.push(message);
+        }
+        client.finish().await;
+        notifications
     }
-}
 
-pub async fn run_http_server(_memory: Memory, _port: u16, _metrics: Option<Metrics>) -> anyhow::Result<()> {
-    // TODO: Implementar servidor HTTP para MCP
-    anyhow::bail!("Modo HTTP aún no implementado. Use 'stdio' por ahora.");
+    #[tokio::test]
+    async fn progress_is_sent_only_with_a_progress_token() {
+        let progress = reindex_notifications("server-progress", Some(json!({ "progressToken": "t" }))).await;
+        let done: Vec<&Value> = progress.iter().map(|n| &n["params"]["progress"]).collect();
+        assert_eq!(done, [1, 2, 3]);
+        assert!(progress.iter().all(|n| n["method"] == "notifications/progress" && n["params"]["progressToken"] == "t"));
+        assert!(progress.iter().all(|n| n["params"]["total"] == 3));
+
+        assert!(reindex_notifications("server-no-progress", None).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn set_level_validates_the_level() {
+        let mut client = Client::start("server-set-level").await;
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": 1, "method": "logging/setLevel", "params": { "level": "info" } }))
+            .await;
+        assert_eq!(client.recv().await["result"], json!({}));
+        client
+            .send(json!({ "jsonrpc": "2.0", "id": 2, "method": "logging/setLevel", "params": { "level": "verbose" } }))
+            .await;
+        assert_eq!(client.recv().await["error"]["code"], -32602);
+        client.send(json!({ "jsonrpc": "2.0", "id": 3, "method": "logging/setLevel", "params": {} })).await;
+        assert_eq!(client.recv().await["error"]["code"], -32602);
+        client.finish().await;
+    }
+
+    /// Campos de los errores `-32602` de una respuesta
+    fn invalid_fields(response: &Value) -> Vec<&str> {
+        assert_eq!(response["error"]["code"], -32602, "{}", response);
+        response["error"]["data"]["errors"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| e["field"].as_str().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn invalid_arguments_are_reported_per_field() {
+        let mut client = Client::start("server-invalid-arguments").await;
+        client.call(1, "mem_search", json!({ "query": "x", "limit": "5" })).await;
+        assert_eq!(invalid_fields(&client.recv().await), ["/limit"]);
+
+        client.call(2, "mem_search", json!({ "query": "x", "tag": 1 })).await;
+        assert_eq!(invalid_fields(&client.recv().await), ["/tag"]);
+        let entry = json!({ "knowledge_type": "decision", "title": "t", "content": "c", "summary": "s", "tags": ["ok", 1] });
+        client.call(6, "mem_save", entry).await;
+        assert_eq!(invalid_fields(&client.recv().await), ["/tags/1"]);
+
+        client.call(3, "mem_search", json!({ "query": "x", "limt": 5 })).await;
+        assert_eq!(invalid_fields(&client.recv().await), ["/limt"]);
+
+        // El esquema publicado incluye los tipos registrados en la base
+        let entry = json!({ "knowledge_type": "rumor", "title": "t", "content": "c", "summary": "s" });
+        client.call(4, "mem_save", entry).await;
+        assert_eq!(invalid_fields(&client.recv().await), ["/knowledge_type"]);
+
+        client.call(5, "mem_search", json!({ "query": "x", "limit": 5 })).await;
+        assert!(client.recv().await.get("result").is_some());
+        client.finish().await;
+    }
+
+    #[tokio::test]
+    async fn save_goes_to_the_chosen_store_and_search_spans_all() {
+        let mut client = Client::start_with("server-stores", |config, dir| {
+            let team = StoreConfig { database: Some(dir.join("team.db")), weight: 1.0 };
+            config.stores.insert("team".to_string(), team);
+        })
+        .await;
+        let entry = |title: &str| json!({ "knowledge_type": "decision", "title": title, "content": "sqlite", "summary": "s" });
+        client.call(1, "mem_save", entry("personal")).await;
+        assert_eq!(client.recv().await["result"]["store"], "default");
+        let mut team = entry("equipo");
+        team["store"] = json!("team");
+        client.call(2, "mem_save", team).await;
+        assert_eq!(client.recv().await["result"]["store"], "team");
+
+        client.call(3, "mem_search", json!({ "query": "sqlite" })).await;
+        let response = client.recv().await;
+        let mut found: Vec<(&str, &str)> = response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|e| (e["store"].as_str().unwrap(), e["title"].as_str().unwrap()))
+            .collect();
+        found.sort();
+        assert_eq!(found, [("default", "personal"), ("team", "equipo")]);
+
+        client.call(4, "mem_search", json!({ "query": "sqlite", "store": "team" })).await;
+        assert_eq!(client.recv().await["result"].as_array().unwrap().len(), 1);
+
+        client.call(5, "mem_save", json!({ "knowledge_type": "decision", "title": "t", "content": "c", "summary": "s", "store": "otro" })).await;
+        assert_eq!(invalid_fields(&client.recv().await), ["/store"]);
+        client.finish().await;
+    }
+
+    #[tokio::test]
+    async fn structured_fields_are_validated_rendered_and_searchable() {
+        let mut client = Client::start("server-fields").await;
+        let bugfix = |fields: Value| {
+            json!({ "knowledge_type": "bugfix", "title": "Timeouts", "content": "c", "summary": "s", "fields": fields })
+        };
+        client.call(1, "mem_save", bugfix(json!({ "symptom": ["lista"], "owner": "ana" }))).await;
+        let response = client.recv().await;
+        assert_eq!(invalid_fields(&response), ["/fields/owner", "/fields/symptom"]);
+        assert!(response["error"]["data"]["errors"][0]["message"].as_str().unwrap().contains("symptom, root_cause, fix"));
+
+        let fields = json!({ "fix": "", "root_cause": "Pool de conexiones agotado", "symptom": "Timeouts al pagar" });
+        client.call(3, "mem_save", bugfix(fields)).await;
+        let id = client.recv().await["result"]["id"].clone();
+
+        // En el orden del tipo, sin los campos vacíos
+        client.call(4, "mem_get_detail", json!({ "id": id })).await;
+        let detail = client.recv().await;
+        let names: Vec<&str> = detail["result"]["fields"].as_array().unwrap().iter().map(|f| f["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["symptom", "root_cause"]);
+        assert_eq!(detail["result"]["fields"][1]["description"], "Causa raíz");
+
+        client.call(5, "mem_search", json!({ "query": "pool", "field": "root_cause" })).await;
+        assert_eq!(client.recv().await["result"].as_array().unwrap().len(), 1);
+        client.call(6, "mem_search", json!({ "query": "pool", "field": "symptom" })).await;
+        assert!(client.recv().await["result"].as_array().unwrap().is_empty());
+        client.finish().await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn sessions_are_kept_where_they_save_with_the_project_of_the_roots() {
+        let mut client = Client::start_with("server-sessions", |config, dir| {
+            let team = StoreConfig { database: Some(dir.join("team.db")), weight: 1.0 };
+            config.stores.insert("team".to_string(), team);
+        })
+        .await;
+        let root = client._dir.join("app");
+        std::fs::create_dir(&root).unwrap();
+        let entry = |title: &str| json!({ "knowledge_type": "decision", "title": title, "content": "c", "summary": "s" });
+        let initialize = json!({ "clientInfo": { "name": "cursor" }, "capabilities": { "roots": {} } });
+        client.send(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": initialize })).await;
+        client.recv().await;
+
+        // Hasta que guarda algo, la sesión no está en ningún store
+        client.call(2, "mem_sessions", json!({})).await;
+        assert_eq!(client.recv().await["result"], json!([]));
+        client.call(3, "mem_save", entry("antes de las raíces")).await;
+        client.recv().await;
+        client.call(4, "mem_sessions", json!({})).await;
+        let sessions = client.recv().await["result"].clone();
+        assert_eq!(sessions.as_array().unwrap().len(), 1);
+        assert_eq!((&sessions[0]["client"], &sessions[0]["entries"]), (&json!("cursor"), &json!(1)));
+        assert_eq!((&sessions[0]["project_path"], &sessions[0]["current"]), (&Value::Null, &json!(true)));
+        let session_id = sessions[0]["id"].clone();
+
+        // Las raíces llegan después de `initialize`: la sesión toma su proyecto
+        client.send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await;
+        let request = client.recv().await;
+        assert_eq!(request["method"], "roots/list");
+        let roots = json!({ "roots": [{ "uri": format!("file://{}", root.display()) }] });
+        client.send(json!({ "jsonrpc": "2.0", "id": request["id"], "result": roots })).await;
+        let key = project::detect_project(&root).key;
+        let mut id = 5;
+        loop {
+            client.call(id, "mem_sessions", json!({})).await;
+            if client.recv().await["result"][0]["project_path"] == key.as_str() {
+                break;
+            }
+            assert!(id < 200, "la sesión no tomó el proyecto de las raíces");
+            id += 1;
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // Qué se aprendió en la sesión
+        client.call(id + 1, "mem_save", entry("después de las raíces")).await;
+        client.recv().await;
+        client.call(id + 2, "mem_search", json!({ "session_id": session_id })).await;
+        let response = client.recv().await;
+        let mut titles: Vec<&str> = response["result"].as_array().unwrap().iter().map(|e| e["title"].as_str().unwrap()).collect();
+        titles.sort();
+        assert_eq!(titles, ["antes de las raíces", "después de las raíces"]);
+
+        // El store del equipo no recibió nada de la sesión
+        client.call(id + 3, "mem_sessions", json!({ "store": "team" })).await;
+        assert_eq!(client.recv().await["result"], json!([]));
+        client.finish().await;
+    }
 }
@@ -3,61 +3,390 @@
 //! Reutiliza la lógica de memoria del proyecto de Agente Ofimático,
 //! adaptada para funcionar como biblioteca independiente.
 
-use rusqlite::{Connection, Result as SqlResult, params};
-use std::sync::Mutex;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, OptionalExtension, Result as SqlResult};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
 use chrono::Utc;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use serde_json::Map;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 
-/// Tipos de conocimiento que se pueden almacenar
-#[derive(Debug, Clone, Copy, PartialEq)]
-pub enum KnowledgeType {
-    Decision,
-    BugFix,
-    Pattern,
-    Configuration,
-    Context,
-    Summary,
+/// Tipos de conocimiento incluidos: (nombre, descripción)
+///
+/// Se registran en `knowledge_types` al crear la base; cada equipo puede agregar
+/// los suyos con `types add`.
+pub const BUILTIN_TYPES: [(&str, &str); 6] = [
+    ("decision", "Decisión de diseño o arquitectura y su motivo"),
+    ("bugfix", "Error resuelto: síntoma, causa y solución"),
+    ("pattern", "Patrón o convención del código"),
+    ("config", "Configuración, comandos o entorno"),
+    ("context", "Contexto del proyecto o del dominio"),
+    ("summary", "Resumen de una sesión o de un tema"),
+];
+
+/// Campos estructurados de los tipos incluidos: (tipo, campo, clase, descripción)
+const BUILTIN_FIELDS: [(&str, &str, FieldKind, &str); 13] = [
+    ("bugfix", "symptom", FieldKind::Text, "Cómo se manifestaba el error"),
+    ("bugfix", "root_cause", FieldKind::Text, "Causa raíz"),
+    ("bugfix", "fix", FieldKind::Text, "Cómo se resolvió"),
+    ("decision", "context", FieldKind::Text, "Situación que motivó la decisión"),
+    ("decision", "options", FieldKind::List, "Alternativas consideradas"),
+    ("decision", "rationale", FieldKind::Text, "Por qué se eligió esta opción"),
+    ("decision", "consequences", FieldKind::Text, "Consecuencias y compromisos aceptados"),
+    ("pattern", "when_to_use", FieldKind::Text, "Cuándo aplicar el patrón"),
+    ("pattern", "example", FieldKind::Text, "Ejemplo de uso"),
+    ("pattern", "avoid", FieldKind::Text, "Qué evitar o alternativas desaconsejadas"),
+    ("config", "commands", FieldKind::List, "Comandos relevantes"),
+    ("config", "environment", FieldKind::Text, "Variables o requisitos del entorno"),
+    ("config", "files", FieldKind::List, "Archivos de configuración implicados"),
+];
+
+/// Clase de valor de un campo estructurado
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldKind {
+    /// Texto
+    #[default]
+    Text,
+    /// Lista de textos
+    List,
 }
 
-impl KnowledgeType {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            KnowledgeType::Decision => "decision",
-            KnowledgeType::BugFix => "bugfix",
-            KnowledgeType::Pattern => "pattern",
-            KnowledgeType::Configuration => "config",
-            KnowledgeType::Context => "context",
-            KnowledgeType::Summary => "summary",
-        }
-    }
+/// Campo estructurado de un tipo de conocimiento
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldDef {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub kind: FieldKind,
+}
 
-    pub fn from_str(s: &str) -> Option<Self> {
-        match s {
-            "decision" => Some(KnowledgeType::Decision),
-            "bugfix" => Some(KnowledgeType::BugFix),
-            "pattern" => Some(KnowledgeType::Pattern),
-            "config" => Some(KnowledgeType::Configuration),
-            "context" => Some(KnowledgeType::Context),
-            "summary" => Some(KnowledgeType::Summary),
-            _ => None,
-        }
-    }
+/// Argumentos opcionales de `mem_save` que un tipo puede exigir
+pub const REQUIRABLE_FIELDS: [&str; 5] = ["tags", "file_paths", "git_commit", "branch", "project_path"];
+
+/// Tipo de conocimiento registrado en la base
+#[derive(Debug, Clone, Serialize)]
+pub struct KnowledgeTypeDef {
+    pub name: String,
+    pub description: String,
+    /// Argumentos opcionales de `mem_save` (o campos estructurados) que este tipo exige
+    pub required_fields: Vec<String>,
+    /// Campos estructurados de las entradas de este tipo
+    pub fields: Vec<FieldDef>,
+    /// Importancia de las entradas que no indican otra
+    pub default_importance: i64,
+    /// Uno de [`BUILTIN_TYPES`]: no se puede eliminar
+    pub builtin: bool,
 }
 
 /// Entrada de conocimiento almacenada
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KnowledgeEntry {
     pub id: i64,
+    /// Store al que pertenece la entrada (lo completa la búsqueda combinada)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub store: Option<String>,
     pub knowledge_type: String,
     pub title: String,
     pub content: String,
     pub summary: String,
     pub tags: Vec<String>,
     pub project_path: Option<String>,
+    /// Commit de HEAD al guardar la entrada
+    pub git_commit: Option<String>,
+    /// Rama activa al guardar la entrada
+    pub branch: Option<String>,
+    /// Archivos a los que se refiere la entrada (solo en el detalle)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub file_paths: Vec<FileRef>,
+    /// Campos estructurados según el tipo (solo en el detalle)
+    #[serde(default, skip_serializing_if = "Map::is_empty")]
+    pub fields: Map<String, serde_json::Value>,
+    /// El código referenciado cambió desde que se guardó la entrada (según la última verificación)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    /// Importancia de 1 (baja) a 5 (crítica)
+    #[serde(default = "default_importance")]
+    pub importance: i64,
+    /// Entrada fijada: se incluye siempre al exportar reglas
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// Fecha de vencimiento (Unix); vencida, la entrada deja de aparecer en las búsquedas
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    /// Sesión en la que se guardó (solo en el detalle)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
     pub created_at: i64,
     pub updated_at: i64,
+    /// Relevancia de 0 a 1 respecto del mejor resultado de la misma búsqueda (1 = el
+    /// mejor; no se compara entre búsquedas); sin valor si no hubo consulta de texto
     pub relevance_score: Option<f64>,
+    /// Componentes de `relevance_score` (solo en búsquedas de texto con `explain`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub explanation: Option<ScoreExplanation>,
+    /// Fragmento del contenido con las coincidencias resaltadas (solo en búsquedas de texto)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    /// Columnas del índice donde coincidió la consulta (solo en búsquedas de texto)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub matched_columns: Vec<String>,
+}
+
+/// Componentes de la relevancia de un resultado: `raw_score` es el producto de
+/// `bm25` por todos los factores, y `relevance_score` lo normaliza
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreExplanation {
+    /// Relevancia sin normalizar (negativa: más negativa = mejor); a diferencia de
+    /// `relevance_score`, se puede comparar entre búsquedas con los mismos pesos
+    pub raw_score: f64,
+    /// BM25 con los pesos por columna (negativo: más negativo = mejor)
+    pub bm25: f64,
+    /// Factor por aperturas con `mem_get_detail`
+    pub usage: f64,
+    /// Factor por valoraciones de `mem_feedback`
+    pub feedback: f64,
+    /// Factor por la fecha de la última actualización
+    pub recency: f64,
+    /// Factor del tipo de la entrada
+    pub knowledge_type: f64,
+    /// Peso del store
+    pub store: f64,
+}
+
+/// Archivo al que se refiere una entrada, opcionalmente acotado a un rango de líneas
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FileRef {
+    /// Ruta relativa a la raíz del proyecto
+    pub path: String,
+    /// Primera línea del rango (desde 1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1))]
+    pub start_line: Option<u32>,
+    /// Última línea del rango, inclusive (default: `start_line`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(range(min = 1))]
+    pub end_line: Option<u32>,
+    /// Símbolo (función, tipo, clave...) que debe seguir apareciendo en el archivo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symbol: Option<String>,
+    /// SHA-256 del rango (o del archivo completo) al guardar la entrada
+    #[serde(skip)]
+    #[schemars(skip)]
+    pub content_hash: Option<String>,
+}
+
+/// Datos de una entrada nueva
+#[derive(Debug, Clone)]
+pub struct NewKnowledge {
+    pub knowledge_type: String,
+    pub title: String,
+    pub content: String,
+    pub summary: String,
+    pub tags: Vec<String>,
+    pub project_path: Option<String>,
+    pub git_commit: Option<String>,
+    pub branch: Option<String>,
+    pub file_paths: Vec<FileRef>,
+    /// Campos estructurados según el tipo: texto o lista de textos
+    pub fields: Map<String, serde_json::Value>,
+    /// Importancia de 1 (baja) a 5 (crítica)
+    pub importance: i64,
+    pub pinned: bool,
+    /// Fecha de vencimiento (Unix)
+    pub expires_at: Option<i64>,
+    /// Sesión en la que se guarda (no se sincroniza: las sesiones son de cada base)
+    pub session_id: Option<String>,
+    /// UUID a usar (default: uno nuevo)
+    pub uuid: Option<String>,
+    /// Archivo del que proviene la entrada, relativo a la raíz del proyecto
+    pub source_path: Option<String>,
+    /// SHA-256 del archivo de origen, para saber si cambió
+    pub source_hash: Option<String>,
+}
+
+impl NewKnowledge {
+    /// Entrada con lo mínimo: el resto de los campos vacíos y la importancia por defecto
+    pub fn new(knowledge_type: &str, title: &str, content: &str) -> Self {
+        NewKnowledge {
+            knowledge_type: knowledge_type.to_string(),
+            title: title.to_string(),
+            content: content.to_string(),
+            summary: String::new(),
+            tags: Vec::new(),
+            project_path: None,
+            git_commit: None,
+            branch: None,
+            file_paths: Vec::new(),
+            fields: Map::new(),
+            importance: DEFAULT_IMPORTANCE,
+            pinned: false,
+            expires_at: None,
+            session_id: None,
+            uuid: None,
+            source_path: None,
+            source_hash: None,
+        }
+    }
+}
+
+/// Importancia de las entradas que no indican otra
+pub const DEFAULT_IMPORTANCE: i64 = 3;
+
+fn default_importance() -> i64 {
+    DEFAULT_IMPORTANCE
+}
+
+/// Versión de una escritura para la sincronización: (reloj Lamport, nodo)
+///
+/// Ordena todas las escrituras de forma total; a igual reloj decide el nodo.
+pub type Stamp = (i64, String);
+
+/// Operación del changelog de sincronización
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChangeOp {
+    /// `<nodo>:<reloj>`, único entre todas las bases
+    pub id: String,
+    pub node: String,
+    pub clock: i64,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Change {
+    /// Alta de una entrada o modificación de algunos de sus campos
+    Knowledge {
+        uuid: String,
+        fields: Map<String, serde_json::Value>,
+        /// Versión de cada campo que tenía quien escribió: si no coincide con la
+        /// local, las dos escrituras fueron concurrentes
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        base: BTreeMap<String, Stamp>,
+    },
+    /// Evento del timeline de una entrada
+    Timeline {
+        uuid: String,
+        knowledge: String,
+        event_type: String,
+        description: String,
+        timestamp: i64,
+    },
+    /// Borrado de una entrada (lápida): gana a las ediciones parciales; solo una
+    /// versión completa posterior (con `created_at`) vuelve a crear la entrada
+    Delete { uuid: String },
+    /// Enlace de la entrada `source` a la entrada `target`
+    Link {
+        source: String,
+        target: String,
+        relation: String,
+    },
+}
+
+/// Enlace de una entrada a otra
+#[derive(Debug, Clone, Serialize)]
+pub struct KnowledgeLink {
+    /// ID de la entrada enlazada
+    pub id: i64,
+    pub knowledge_type: String,
+    pub title: String,
+    pub relation: String,
+    /// Proyecto de la entrada enlazada, para filtrar por la política de acceso
+    #[serde(skip)]
+    pub project_path: Option<String>,
+}
+
+/// Resultado de aplicar una operación remota
+#[derive(Debug, Default)]
+pub struct Applied {
+    /// `false` si la operación ya se había aplicado
+    pub new: bool,
+    /// Campos en los que hubo escrituras concurrentes
+    pub conflicts: usize,
+}
+
+/// Columnas de `knowledge` que se sincronizan, además de `tags` y `file_paths`
+const SYNC_COLUMNS: [&str; 12] = [
+    "knowledge_type",
+    "title",
+    "content",
+    "summary",
+    "project_path",
+    "git_commit",
+    "branch",
+    "importance",
+    "pinned",
+    "fields",
+    "expires_at",
+    "created_at",
+];
+
+/// Eventos del timeline que solo tienen sentido en esta base y no se sincronizan
+const LOCAL_EVENTS: [&str; 5] = ["verified", "stale", "conflict", "archived", "feedback"];
+
+/// Aperturas (`mem_get_detail`) a partir de las cuales el uso ya no sube en el ranking
+const USAGE_SATURATION: i64 = 20;
+
+/// Sesión MCP: de `initialize` hasta que el cliente cierra la conexión
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// UUID de la sesión
+    pub id: String,
+    /// `clientInfo.name` del cliente
+    pub client: Option<String>,
+    /// Proyecto activo (al cerrar, el último que tuvo)
+    pub project_path: Option<String>,
+    pub started_at: i64,
+    /// Sin valor mientras la sesión sigue abierta (o si el servidor no cerró bien)
+    pub ended_at: Option<i64>,
+    /// Entradas guardadas en la sesión (solo en los listados)
+    #[serde(default)]
+    pub entries: i64,
+}
+
+/// Cómo se llegó a una entrada
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Apareció entre los resultados de `mem_search`
+    Search,
+    /// Se abrió con `mem_get_detail`
+    Detail,
+}
+
+/// Uso de una entrada en esta base: accesos y valoraciones de `mem_feedback`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct KnowledgeUsage {
+    pub search_hits: i64,
+    pub detail_views: i64,
+    pub useful: i64,
+    pub not_useful: i64,
+    pub last_accessed_at: Option<i64>,
+}
+
+/// Entrada del informe de uso
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageEntry {
+    pub id: i64,
+    pub knowledge_type: String,
+    pub title: String,
+    pub project_path: Option<String>,
+    pub created_at: i64,
+    #[serde(flatten)]
+    pub usage: KnowledgeUsage,
+}
+
+/// Informe de uso: las entradas más usadas y las que nunca se usaron
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageReport {
+    pub most_used: Vec<UsageEntry>,
+    /// Las más antiguas primero
+    pub never_used: Vec<UsageEntry>,
+    /// Total de entradas vigentes que nunca aparecieron en una búsqueda ni se abrieron
+    pub never_used_total: i64,
 }
 
 /// Timeline de una entrada
@@ -70,20 +399,256 @@ pub struct TimelineEntry {
     pub timestamp: i64,
 }
 
+/// Pesos del ranking: BM25 por columna del índice FTS5 (mayor = la coincidencia
+/// cuenta más) y cuánto suben el uso, las valoraciones, la recencia y el tipo de
+/// cada entrada
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RankingWeights {
+    pub title: f64,
+    pub summary: f64,
+    pub tags: f64,
+    pub content: f64,
+    /// Campos estructurados
+    pub fields: f64,
+    /// Aumento máximo de relevancia por aperturas con `mem_get_detail` (0.2 = hasta un 20%)
+    pub usage: f64,
+    /// Aumento (o descenso) máximo por valoraciones de `mem_feedback`
+    pub feedback: f64,
+    /// Aumento máximo para una entrada recién actualizada
+    pub recency: f64,
+    /// Días tras la última actualización en que el aumento por recencia cae a la mitad
+    pub recency_half_life_days: f64,
+    /// Multiplicador de relevancia por tipo (p. ej. `decision = 1.5`); 1 si no se indica
+    pub types: BTreeMap<String, f64>,
+}
+
+impl RankingWeights {
+    /// Comprueba que ningún peso pueda invertir el signo del BM25 ni dar un
+    /// resultado no numérico: todos finitos, los de columna no negativos, `usage`,
+    /// `feedback` y `recency` entre 0 y 1, y la vida media y los multiplicadores
+    /// de tipo mayores que 0. Devuelve el primer error, con el nombre de la clave.
+    pub fn validate(&self) -> Result<(), String> {
+        let columns = [
+            ("title", self.title),
+            ("summary", self.summary),
+            ("tags", self.tags),
+            ("content", self.content),
+            ("fields", self.fields),
+        ];
+        for (key, value) in columns {
+            if !value.is_finite() || value < 0.0 {
+                return Err(format!("{} debe ser un número mayor o igual que 0 (es {})", key, value));
+            }
+        }
+        for (key, value) in [("usage", self.usage), ("feedback", self.feedback), ("recency", self.recency)] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!("{} debe estar entre 0 y 1 (es {})", key, value));
+            }
+        }
+        if !self.recency_half_life_days.is_finite() || self.recency_half_life_days <= 0.0 {
+            return Err(format!(
+                "recency_half_life_days debe ser mayor que 0 (es {})",
+                self.recency_half_life_days
+            ));
+        }
+        for (name, factor) in &self.types {
+            if !factor.is_finite() || *factor <= 0.0 {
+                return Err(format!("types.{} debe ser mayor que 0 (es {})", name, factor));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Default for RankingWeights {
+    fn default() -> Self {
+        RankingWeights {
+            title: 1.0,
+            summary: 1.0,
+            tags: 1.0,
+            content: 1.0,
+            fields: 1.0,
+            usage: 0.2,
+            feedback: 0.3,
+            recency: 0.2,
+            recency_half_life_days: 30.0,
+            types: BTreeMap::new(),
+        }
+    }
+}
+
+/// Fragmento de contenido en los resultados de búsqueda (`snippet()` de FTS5)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SnippetOptions {
+    /// Marca antes de cada coincidencia
+    pub start: String,
+    /// Marca después de cada coincidencia
+    pub end: String,
+    /// Se agrega donde el fragmento corta el texto
+    pub ellipsis: String,
+    /// Largo del fragmento en palabras, hasta 64 (0 = sin fragmento)
+    pub tokens: u32,
+}
+
+impl Default for SnippetOptions {
+    fn default() -> Self {
+        SnippetOptions {
+            start: "**".to_string(),
+            end: "**".to_string(),
+            ellipsis: "…".to_string(),
+            tokens: 16,
+        }
+    }
+}
+
+/// Columnas de `knowledge_fts` en su orden de definición
+const FTS_COLUMNS: [&str; 5] = ["title", "content", "summary", "tags", "fields"];
+
+/// Opciones y filtros de `search_compact`
+#[derive(Debug, Clone)]
+pub struct SearchOptions {
+    pub limit: i32,
+    pub weights: RankingWeights,
+    pub snippet: SnippetOptions,
+    /// Devolver los componentes de la relevancia de cada resultado
+    pub explain: bool,
+    /// Descarta los resultados con relevancia normalizada menor (ver `Stores::search`)
+    pub min_score: Option<f64>,
+    /// Solo entradas de este proyecto (o de un subdirectorio) y entradas sin proyecto
+    pub project: Option<String>,
+    /// Si no está vacía, solo entradas con `project_path` bajo alguno de estos prefijos
+    pub allow_projects: Vec<String>,
+    /// Excluye entradas con `project_path` bajo alguno de estos prefijos
+    pub deny_projects: Vec<String>,
+    /// Solo entradas que referencian este archivo, o algún archivo bajo este directorio
+    pub file_path: Option<String>,
+    /// Busca la consulta solo en este campo estructurado (sin consulta: entradas que lo tienen)
+    pub field: Option<String>,
+    /// Solo entradas con este tag o uno de sus descendientes (`lang` incluye `lang/rust`)
+    pub tag: Option<String>,
+    /// Solo entradas guardadas en esta sesión
+    pub session: Option<String>,
+}
+
+impl SearchOptions {
+    pub fn new(limit: i32) -> Self {
+        SearchOptions {
+            limit,
+            weights: RankingWeights::default(),
+            snippet: SnippetOptions::default(),
+            explain: false,
+            min_score: None,
+            project: None,
+            allow_projects: Vec::new(),
+            deny_projects: Vec::new(),
+            file_path: None,
+            field: None,
+            tag: None,
+            session: None,
+        }
+    }
+}
+
+/// Criterios para elegir las entradas de `export-rules`
+///
+/// Las entradas fijadas se incluyen siempre que sean del proyecto, aunque no
+/// cumplan `types` ni `min_importance`.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    /// Entradas de este proyecto (o de un subdirectorio) y entradas sin proyecto
+    pub project: Option<String>,
+    /// Solo estos tipos (vacío = todos)
+    pub types: Vec<String>,
+    pub min_importance: Option<i64>,
+    /// Solo entradas fijadas
+    pub pinned_only: bool,
+    /// Excluye las entradas importadas de este archivo (`source_path` `<archivo>#...`)
+    pub exclude_source: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Conexiones de solo lectura de cada base
+const READERS: usize = 4;
+
+/// Versión del esquema que deja `init_schema` (`PRAGMA user_version`). Súbala al
+/// agregar tablas o columnas: `open_read_only` rechaza las bases anteriores.
+const SCHEMA_VERSION: i64 = 1;
+
+/// Espera máxima por una base bloqueada por otro proceso (p. ej. `sync` o `types add`)
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub struct Memory {
+    /// Conexión de escritura: las escrituras se hacen de a una
     conn: Mutex<Connection>,
+    /// Conexiones para consultas: con WAL leen la última versión confirmada sin
+    /// esperar a que termine una escritura en curso. Vacío si la base no admite WAL.
+    readers: Vec<Mutex<Connection>>,
 }
 
 impl Memory {
     pub fn new(path: &Path) -> SqlResult<Self> {
         let conn = Connection::open(path)?;
-        let memory = Memory {
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // Necesario para que ON DELETE CASCADE borre timeline y archivos de una entrada
+        conn.pragma_update(None, "foreign_keys", true)?;
+        let journal_mode: String = conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get(0))?;
+        let mut memory = Memory {
             conn: Mutex::new(conn),
+            readers: Vec::new(),
         };
         memory.init_schema()?;
+        if journal_mode.eq_ignore_ascii_case("wal") {
+            for _ in 0..READERS {
+                let reader = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+                reader.busy_timeout(BUSY_TIMEOUT)?;
+                memory.readers.push(Mutex::new(reader));
+            }
+        }
         Ok(memory)
     }
 
+    /// Abre una base existente solo para consultas (p. ej. desde `IDE_Memory_Stats`)
+    ///
+    /// No crea la base ni migra su esquema: si no existe o la dejó una versión
+    /// anterior del servidor, lo explica en el error.
+    #[allow(dead_code)] // solo la usa `IDE_Memory_Stats`
+    pub fn open_read_only(path: &Path) -> anyhow::Result<Self> {
+        if !path.is_file() {
+            anyhow::bail!("No existe la base de conocimiento {:?}", path);
+        }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < SCHEMA_VERSION {
+            anyhow::bail!(
+                "La base {:?} tiene un esquema anterior (versión {}, se requiere {}): ábrala una vez con el servidor para actualizarla",
+                path,
+                version,
+                SCHEMA_VERSION
+            );
+        }
+        Ok(Memory {
+            conn: Mutex::new(conn),
+            readers: Vec::new(),
+        })
+    }
+
+    /// Conexión para una consulta: la primera de lectura libre, o la de escritura
+    /// si la base no tiene conexiones de lectura
+    fn reader(&self) -> MutexGuard<'_, Connection> {
+        for reader in &self.readers {
+            if let Ok(conn) = reader.try_lock() {
+                return conn;
+            }
+        }
+        match self.readers.first() {
+            Some(reader) => reader.lock().unwrap(),
+            None => self.conn.lock().unwrap(),
+        }
+    }
+
     fn init_schema(&self) -> SqlResult<()> {
         let conn = self.conn.lock().unwrap();
         
@@ -103,19 +668,111 @@ impl Memory {
             [],
         )?;
 
-        // Tabla FTS5 para búsqueda rápida
+        // Columnas agregadas después de la versión inicial
+        add_column(&conn, "knowledge", "git_commit", "TEXT")?;
+        add_column(&conn, "knowledge", "branch", "TEXT")?;
+        add_column(&conn, "knowledge", "stale", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(&conn, "knowledge", "verified_at", "INTEGER")?;
+        add_column(&conn, "knowledge", "uuid", "TEXT")?;
+        add_column(&conn, "knowledge", "source_path", "TEXT")?;
+        add_column(&conn, "knowledge", "importance", "INTEGER NOT NULL DEFAULT 3")?;
+        add_column(&conn, "knowledge", "pinned", "INTEGER NOT NULL DEFAULT 0")?;
+        add_column(&conn, "knowledge", "fields", "TEXT")?;
+        add_column(&conn, "knowledge", "source_hash", "TEXT")?;
+        add_column(&conn, "knowledge", "expires_at", "INTEGER")?;
+        // Cuándo la limpieza archivó la entrada vencida (local, no se sincroniza)
+        add_column(&conn, "knowledge", "archived_at", "INTEGER")?;
+        add_column(&conn, "knowledge", "session_id", "TEXT")?;
+
+        // Sesiones MCP, para saber qué se aprendió en cada una
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                client TEXT,
+                project_path TEXT,
+                started_at INTEGER NOT NULL,
+                ended_at INTEGER
+            )",
+            [],
+        )?;
+
+        // Uso de cada entrada; solo de esta base, no se sincroniza
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS knowledge_usage (
+                knowledge_id INTEGER PRIMARY KEY,
+                search_hits INTEGER NOT NULL DEFAULT 0,
+                detail_views INTEGER NOT NULL DEFAULT 0,
+                useful INTEGER NOT NULL DEFAULT 0,
+                not_useful INTEGER NOT NULL DEFAULT 0,
+                last_accessed_at INTEGER,
+                FOREIGN KEY (knowledge_id) REFERENCES knowledge(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Archivos referenciados por cada entrada
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS knowledge_files (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                knowledge_id INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                start_line INTEGER,
+                end_line INTEGER,
+                FOREIGN KEY (knowledge_id) REFERENCES knowledge(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+        add_column(&conn, "knowledge_files", "symbol", "TEXT")?;
+        add_column(&conn, "knowledge_files", "content_hash", "TEXT")?;
+
+        // Tabla FTS5 para búsqueda rápida. Las versiones anteriores no indexaban
+        // los campos estructurados: se recrea y se reconstruye al final
+        let fts_outdated = conn
+            .prepare("SELECT 1 FROM pragma_table_info('knowledge_fts') WHERE name = 'title'")?
+            .exists([])?
+            && !conn
+                .prepare("SELECT 1 FROM pragma_table_info('knowledge_fts') WHERE name = 'fields'")?
+                .exists([])?;
+        if fts_outdated {
+            conn.execute("DROP TABLE knowledge_fts", [])?;
+        }
         conn.execute(
             "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_fts USING fts5(
                 title,
                 content,
                 summary,
                 tags,
+                fields,
                 content_rowid=id,
                 content='knowledge'
             )",
             [],
         )?;
 
+        // Cada campo estructurado por separado, para buscar en uno concreto
+        conn.execute(
+            "CREATE VIRTUAL TABLE IF NOT EXISTS knowledge_fields_fts USING fts5(
+                value,
+                knowledge_id UNINDEXED,
+                name UNINDEXED
+            )",
+            [],
+        )?;
+
+        // Tags normalizados; `knowledge.tags` conserva la lista para el índice FTS y la sincronización
+        let backfill_tags = !conn
+            .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'knowledge_tags'")?
+            .exists([])?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS knowledge_tags (
+                knowledge_id INTEGER NOT NULL,
+                tag TEXT NOT NULL,
+                PRIMARY KEY (knowledge_id, tag),
+                FOREIGN KEY (knowledge_id) REFERENCES knowledge(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
         // Tabla de timeline
         conn.execute(
             "CREATE TABLE IF NOT EXISTS knowledge_timeline (
@@ -129,6 +786,70 @@ impl Memory {
             [],
         )?;
 
+        add_column(&conn, "knowledge_timeline", "uuid", "TEXT")?;
+
+        // Sincronización: estado local (nodo, reloj), changelog y versión de cada campo
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sync_state (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS changelog (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                op_id TEXT NOT NULL UNIQUE,
+                node TEXT NOT NULL,
+                data TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS field_versions (
+                uuid TEXT NOT NULL,
+                field TEXT NOT NULL,
+                clock INTEGER NOT NULL,
+                node TEXT NOT NULL,
+                PRIMARY KEY (uuid, field)
+            )",
+            [],
+        )?;
+
+        // Entradas borradas, con la versión del borrado
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tombstones (
+                uuid TEXT PRIMARY KEY,
+                clock INTEGER NOT NULL,
+                node TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Enlaces entre entradas (por ejemplo, de un resumen de sesión a lo que tocó)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS knowledge_links (
+                source_id INTEGER NOT NULL,
+                target_id INTEGER NOT NULL,
+                relation TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY (source_id, target_id, relation),
+                FOREIGN KEY (source_id) REFERENCES knowledge(id) ON DELETE CASCADE,
+                FOREIGN KEY (target_id) REFERENCES knowledge(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        // Raíz en esta máquina de cada proyecto: `project_path` es una clave
+        // normalizada, no siempre una ruta utilizable (no se sincroniza)
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS project_dirs (
+                project_path TEXT PRIMARY KEY,
+                dir TEXT NOT NULL
+            )",
+            [],
+        )?;
+
         // Índices
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_knowledge_type ON knowledge(knowledge_type)",
@@ -146,127 +867,352 @@ impl Memory {
             "CREATE INDEX IF NOT EXISTS idx_timeline_knowledge ON knowledge_timeline(knowledge_id, timestamp DESC)",
             [],
         )?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_knowledge_uuid ON knowledge(uuid)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_timeline_uuid ON knowledge_timeline(uuid)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_knowledge_source ON knowledge(project_path, source_path)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_files_path ON knowledge_files(path)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_files_knowledge ON knowledge_files(knowledge_id)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_tags_tag ON knowledge_tags(tag)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_knowledge_expires ON knowledge(expires_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_knowledge_session ON knowledge(session_id)",
+            [],
+        )?;
 
         // Triggers para mantener FTS5 sincronizado
+        conn.execute("DROP TRIGGER IF EXISTS knowledge_fts_insert", [])?;
         conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS knowledge_fts_insert AFTER INSERT ON knowledge BEGIN
-                INSERT INTO knowledge_fts(rowid, title, content, summary, tags)
-                VALUES (new.id, new.title, new.content, new.summary, new.tags);
+            "CREATE TRIGGER knowledge_fts_insert AFTER INSERT ON knowledge BEGIN
+                INSERT INTO knowledge_fts(rowid, title, content, summary, tags, fields)
+                VALUES (new.id, new.title, new.content, new.summary, new.tags, new.fields);
             END",
             [],
         )?;
 
+        // En una tabla FTS5 de contenido externo, borrar requiere los valores
+        // anteriores (comando 'delete'). Las versiones anteriores de estos triggers
+        // no lo hacían, así que se recrean siempre.
+        conn.execute("DROP TRIGGER IF EXISTS knowledge_fts_update", [])?;
         conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS knowledge_fts_update AFTER UPDATE ON knowledge BEGIN
-                UPDATE knowledge_fts SET
-                    title = new.title,
-                    content = new.content,
-                    summary = new.summary,
-                    tags = new.tags
-                WHERE rowid = new.id;
+            "CREATE TRIGGER knowledge_fts_update AFTER UPDATE OF title, content, summary, tags, fields ON knowledge BEGIN
+                INSERT INTO knowledge_fts(knowledge_fts, rowid, title, content, summary, tags, fields)
+                VALUES ('delete', old.id, old.title, old.content, old.summary, old.tags, old.fields);
+                INSERT INTO knowledge_fts(rowid, title, content, summary, tags, fields)
+                VALUES (new.id, new.title, new.content, new.summary, new.tags, new.fields);
             END",
             [],
         )?;
 
+        conn.execute("DROP TRIGGER IF EXISTS knowledge_fts_delete", [])?;
         conn.execute(
-            "CREATE TRIGGER IF NOT EXISTS knowledge_fts_delete AFTER DELETE ON knowledge BEGIN
-                DELETE FROM knowledge_fts WHERE rowid = old.id;
+            "CREATE TRIGGER knowledge_fts_delete AFTER DELETE ON knowledge BEGIN
+                INSERT INTO knowledge_fts(knowledge_fts, rowid, title, content, summary, tags, fields)
+                VALUES ('delete', old.id, old.title, old.content, old.summary, old.tags, old.fields);
+                DELETE FROM knowledge_fields_fts WHERE knowledge_id = old.id;
             END",
             [],
         )?;
+        if fts_outdated {
+            conn.execute("INSERT INTO knowledge_fts(knowledge_fts) VALUES('rebuild')", [])?;
+        }
+
+        // Tipos de conocimiento: los incluidos más los definidos por el equipo
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS knowledge_types (
+                name TEXT PRIMARY KEY,
+                description TEXT NOT NULL,
+                required_fields TEXT NOT NULL DEFAULT '[]',
+                default_importance INTEGER NOT NULL DEFAULT 3,
+                builtin INTEGER NOT NULL DEFAULT 0
+            )",
+            [],
+        )?;
+        // Los campos de los tipos incluidos se cargan al crear la tabla o la columna
+        let seed_fields = add_column(&conn, "knowledge_types", "fields", "TEXT NOT NULL DEFAULT '[]'")?;
+        for (name, description) in BUILTIN_TYPES {
+            let fields: Vec<FieldDef> = BUILTIN_FIELDS
+                .iter()
+                .filter(|(type_name, ..)| *type_name == name)
+                .map(|(_, field, kind, description)| FieldDef {
+                    name: field.to_string(),
+                    description: description.to_string(),
+                    kind: *kind,
+                })
+                .collect();
+            let fields = to_json(&fields)?;
+            conn.execute(
+                "INSERT OR IGNORE INTO knowledge_types (name, description, fields, builtin) VALUES (?1, ?2, ?3, 1)",
+                params![name, description, fields],
+            )?;
+            if seed_fields {
+                conn.execute(
+                    "UPDATE knowledge_types SET fields = ?1 WHERE name = ?2 AND builtin = 1",
+                    params![fields, name],
+                )?;
+            }
+        }
 
+        // Identidad de esta base para la sincronización
+        if sync_value(&conn, "node")?.is_none() {
+            set_sync_value(&conn, "node", &uuid::Uuid::new_v4().simple().to_string())?;
+        }
+        backfill_uuids(&conn)?;
+        if backfill_tags {
+            normalize_existing_tags(&conn)?;
+        }
+
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION)?;
         Ok(())
     }
 
-    pub fn save_knowledge(
-        &self,
-        knowledge_type: KnowledgeType,
-        title: &str,
-        content: &str,
-        summary: &str,
-        tags: &[String],
-        project_path: Option<&str>,
-    ) -> SqlResult<i64> {
-        let conn = self.conn.lock().unwrap();
+    pub fn save_knowledge(&self, entry: &NewKnowledge) -> SqlResult<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
         let now = Utc::now().timestamp();
-        let tags_json = serde_json::to_string(tags).unwrap_or_else(|_| "[]".to_string());
+        let tags = normalize_tags(&entry.tags);
+        let tags_json = serde_json::to_string(&tags).unwrap_or_else(|_| "[]".to_string());
 
-        conn.execute(
+        let uuid = entry.uuid.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
+        tx.execute(
             "INSERT INTO knowledge 
-            (knowledge_type, title, content, summary, tags, project_path, created_at, updated_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (knowledge_type, title, content, summary, tags, project_path, git_commit, branch, created_at, updated_at,
+             uuid, source_path, source_hash, importance, pinned, fields, expires_at, session_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
             params![
-                knowledge_type.as_str(),
-                title,
-                content,
-                summary,
+                entry.knowledge_type,
+                entry.title,
+                entry.content,
+                entry.summary,
                 tags_json,
-                project_path,
+                entry.project_path,
+                entry.git_commit,
+                entry.branch,
+                now,
                 now,
-                now
+                uuid,
+                entry.source_path,
+                entry.source_hash,
+                entry.importance,
+                entry.pinned,
+                fields_json(&entry.fields)?,
+                entry.expires_at,
+                entry.session_id
             ],
         )?;
 
-        let id = conn.last_insert_rowid();
-        
-        conn.execute(
-            "INSERT INTO knowledge_timeline (knowledge_id, event_type, description, timestamp)
-            VALUES (?1, 'created', 'Entrada creada', ?2)",
-            params![id, now],
-        )?;
+        let id = tx.last_insert_rowid();
+        index_fields(&tx, id, &entry.fields)?;
+        index_tags(&tx, id, &tags)?;
+
+        for file in &entry.file_paths {
+            tx.execute(
+                "INSERT INTO knowledge_files (knowledge_id, path, start_line, end_line, symbol, content_hash)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![id, file.path, file.start_line, file.end_line, file.symbol, file.content_hash],
+            )?;
+        }
+
+        // Guardar de nuevo una entrada borrada (el mismo archivo espejo, por ejemplo)
+        // la revive también en las demás bases: su versión completa es posterior
+        tx.execute("DELETE FROM tombstones WHERE uuid = ?1", params![uuid])?;
+        log_knowledge(&tx, id, None)?;
+        add_event(&tx, id, "created", "Entrada creada", now)?;
 
+        tx.commit()?;
         Ok(id)
     }
 
-    pub fn search_compact(&self, query: &str, limit: i32) -> SqlResult<Vec<KnowledgeEntry>> {
-        let conn = self.conn.lock().unwrap();
+    /// Búsqueda compacta (sin contenido). Con `query` vacía no se usa el índice
+    /// full-text: se devuelven las entradas que cumplen los filtros, más recientes primero.
+    pub fn search_compact(&self, query: &str, options: &SearchOptions) -> SqlResult<Vec<KnowledgeEntry>> {
+        let conn = self.reader();
         let mut results = Vec::new();
+        let query = fts_query(query);
+        let full_text = !query.is_empty();
 
-        let mut stmt = conn.prepare(
-            "SELECT k.id, k.knowledge_type, k.title, k.summary, k.tags, k.project_path, 
+        // Filtros opcionales: se agregan como condiciones con parámetros posicionales
+        let mut conditions = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+        match (full_text, &options.field) {
+            (true, Some(field)) => {
+                conditions.push("knowledge_fields_fts MATCH ? AND ff.name = ?".to_string());
+                values.push(Value::Text(query.clone()));
+                values.push(Value::Text(field.clone()));
+            }
+            (true, None) => {
+                conditions.push("knowledge_fts MATCH ?".to_string());
+                values.push(Value::Text(query.clone()));
+            }
+            (false, Some(field)) => {
+                conditions.push(
+                    "EXISTS (SELECT 1 FROM knowledge_fields_fts ff WHERE ff.knowledge_id = k.id AND ff.name = ?)"
+                        .to_string(),
+                );
+                values.push(Value::Text(field.clone()));
+            }
+            (false, None) => {}
+        }
+
+        scope_conditions(options, &mut conditions, &mut values);
+        if let Some(ref tag) = options.tag {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM knowledge_tags t WHERE t.knowledge_id = k.id
+                         AND (t.tag = ? OR substr(t.tag, 1, length(?)) = ?))"
+                    .to_string(),
+            );
+            let tag = normalize_tag(tag);
+            let under = format!("{}/", tag);
+            values.push(Value::Text(tag));
+            values.push(Value::Text(under.clone()));
+            values.push(Value::Text(under));
+        }
+        if let Some(ref session) = options.session {
+            conditions.push("k.session_id = ?".to_string());
+            values.push(Value::Text(session.clone()));
+        }
+        if let Some(ref file_path) = options.file_path {
+            conditions.push(
+                "EXISTS (SELECT 1 FROM knowledge_files f WHERE f.knowledge_id = k.id
+                         AND (f.path = ? OR substr(f.path, 1, length(?)) = ?))"
+                    .to_string(),
+            );
+            let under = format!("{}/", file_path.trim_end_matches('/'));
+            values.push(Value::Text(file_path.clone()));
+            values.push(Value::Text(under.clone()));
+            values.push(Value::Text(under));
+        }
+        if conditions.is_empty() {
+            conditions.push("1".to_string());
+        }
+        values.push(Value::Integer(options.limit as i64));
+
+        let (from, order) = if full_text && options.field.is_some() {
+            (
+                "knowledge_fields_fts ff JOIN knowledge k ON ff.knowledge_id = k.id
+                 LEFT JOIN knowledge_usage u ON u.knowledge_id = k.id",
+                "score",
+            )
+        } else if full_text {
+            (
+                "knowledge_fts JOIN knowledge k ON knowledge_fts.rowid = k.id
+                 LEFT JOIN knowledge_usage u ON u.knowledge_id = k.id",
+                "score",
+            )
+        } else {
+            ("knowledge k", "k.updated_at DESC, k.id DESC")
+        };
+        // Los términos del ranking aparecen dos veces (el producto y cada componente);
+        // sus parámetros van antes que los de las condiciones, en el orden del SQL
+        let mut all_values = Vec::new();
+        let (score, components) = if full_text {
+            let score = ranking_terms(options, &mut all_values).join(" * ");
+            (score, ranking_terms(options, &mut all_values).join(", "))
+        } else {
+            ("NULL".to_string(), ["NULL"; 5].join(", "))
+        };
+        all_values.extend(values);
+        let sql = format!(
+            "SELECT k.id, k.knowledge_type, k.title, k.summary, k.tags, k.project_path,
                     k.created_at, k.updated_at,
-                    bm25(knowledge_fts) as score
-             FROM knowledge_fts
-             JOIN knowledge k ON knowledge_fts.rowid = k.id
-             WHERE knowledge_fts MATCH ?1
-             ORDER BY score
-             LIMIT ?2"
-        )?;
+                    {} as score, k.git_commit, k.branch, k.stale, k.importance, k.pinned, k.expires_at,
+                    {}
+             FROM {}
+             WHERE {}
+             ORDER BY {}
+             LIMIT ?",
+            score,
+            components,
+            from,
+            conditions.join(" AND "),
+            order
+        );
+        let mut stmt = conn.prepare(&sql)?;
 
-        let rows = stmt.query_map(params![query, limit], |row| {
+        let rows = stmt.query_map(params_from_iter(all_values), |row| {
             let tags_json: String = row.get(4)?;
             let tags: Vec<String> = serde_json::from_str(&tags_json).unwrap_or_default();
             
             Ok(KnowledgeEntry {
                 id: row.get(0)?,
+                store: None,
                 knowledge_type: row.get(1)?,
                 title: row.get(2)?,
                 content: String::new(),
                 summary: row.get(3)?,
                 tags,
                 project_path: row.get(5)?,
+                git_commit: row.get(9)?,
+                branch: row.get(10)?,
+                file_paths: Vec::new(),
+                fields: Map::new(),
+                stale: row.get(11)?,
+                importance: row.get(12)?,
+                pinned: row.get(13)?,
+                expires_at: row.get(14)?,
+                session_id: None,
                 created_at: row.get(6)?,
                 updated_at: row.get(7)?,
-                relevance_score: Some(row.get(8)?),
+                relevance_score: row.get(8)?,
+                explanation: match row.get::<_, Option<f64>>(15)? {
+                    Some(bm25) if options.explain => Some(ScoreExplanation {
+                        raw_score: row.get(8)?,
+                        bm25,
+                        usage: row.get(16)?,
+                        feedback: row.get(17)?,
+                        recency: row.get(18)?,
+                        knowledge_type: row.get(19)?,
+                        store: 1.0,
+                    }),
+                    _ => None,
+                },
+                snippet: None,
+                matched_columns: Vec::new(),
             })
         })?;
 
         for row in rows {
             results.push(row?);
         }
+        if full_text {
+            for entry in &mut results {
+                match_details(&conn, &query, options, entry)?;
+            }
+        }
 
         Ok(results)
     }
 
     pub fn get_timeline(&self, knowledge_id: i64) -> SqlResult<Vec<TimelineEntry>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
         let mut results = Vec::new();
 
         let mut stmt = conn.prepare(
             "SELECT id, knowledge_id, event_type, description, timestamp
              FROM knowledge_timeline
              WHERE knowledge_id = ?1
-             ORDER BY timestamp DESC"
+             ORDER BY timestamp DESC, id DESC"
         )?;
 
         let rows = stmt.query_map(params![knowledge_id], |row| {
@@ -287,11 +1233,12 @@ impl Memory {
     }
 
     pub fn get_detail(&self, knowledge_id: i64) -> SqlResult<Option<KnowledgeEntry>> {
-        let conn = self.conn.lock().unwrap();
+        let conn = self.reader();
 
         let mut stmt = conn.prepare(
             "SELECT id, knowledge_type, title, content, summary, tags, project_path, 
-                    created_at, updated_at
+                    created_at, updated_at, git_commit, branch, stale, importance, pinned, fields, expires_at,
+                    session_id
              FROM knowledge
              WHERE id = ?1"
         )?;
@@ -302,22 +1249,1835 @@ impl Memory {
             
             Ok(KnowledgeEntry {
                 id: row.get(0)?,
+                store: None,
                 knowledge_type: row.get(1)?,
                 title: row.get(2)?,
                 content: row.get(3)?,
                 summary: row.get(4)?,
                 tags,
                 project_path: row.get(6)?,
+                git_commit: row.get(9)?,
+                branch: row.get(10)?,
+                file_paths: Vec::new(),
+                fields: row
+                    .get::<_, Option<String>>(14)?
+                    .and_then(|f| serde_json::from_str(&f).ok())
+                    .unwrap_or_default(),
+                stale: row.get(11)?,
+                importance: row.get(12)?,
+                pinned: row.get(13)?,
+                expires_at: row.get(15)?,
+                session_id: row.get(16)?,
                 created_at: row.get(7)?,
                 updated_at: row.get(8)?,
                 relevance_score: None,
+                explanation: None,
+                snippet: None,
+                matched_columns: Vec::new(),
             })
         })?;
 
-        if let Some(row) = rows.next() {
-            Ok(Some(row?))
-        } else {
-            Ok(None)
+        let Some(row) = rows.next() else {
+            return Ok(None);
+        };
+        let mut entry = row?;
+
+        let mut stmt = conn.prepare(
+            "SELECT path, start_line, end_line, symbol, content_hash FROM knowledge_files
+             WHERE knowledge_id = ?1
+             ORDER BY id"
+        )?;
+        let files = stmt.query_map(params![knowledge_id], |row| {
+            Ok(FileRef {
+                path: row.get(0)?,
+                start_line: row.get(1)?,
+                end_line: row.get(2)?,
+                symbol: row.get(3)?,
+                content_hash: row.get(4)?,
+            })
+        })?;
+        for file in files {
+            entry.file_paths.push(file?);
         }
+
+        Ok(Some(entry))
+    }
+
+    /// Entradas completas para exportar como reglas, las fijadas y más importantes primero
+    ///
+    /// Las entradas posiblemente obsoletas no se exportan.
+    pub fn export_entries(&self, filter: &ExportFilter) -> SqlResult<Vec<KnowledgeEntry>> {
+        let ids: Vec<i64> = {
+            let conn = self.reader();
+            let mut conditions = vec!["k.stale = 0".to_string(), "(k.expires_at IS NULL OR k.expires_at > ?)".to_string()];
+            let mut values: Vec<Value> = vec![Value::Integer(Utc::now().timestamp())];
+            if let Some(ref project) = filter.project {
+                conditions.push(
+                    "(k.project_path IS NULL OR k.project_path = ? OR substr(k.project_path, 1, length(?)) = ?)"
+                        .to_string(),
+                );
+                let under = format!("{}/", project.trim_end_matches('/'));
+                values.push(Value::Text(project.clone()));
+                values.push(Value::Text(under.clone()));
+                values.push(Value::Text(under));
+            }
+            if let Some(ref source) = filter.exclude_source {
+                conditions.push("(k.source_path IS NULL OR substr(k.source_path, 1, length(?)) != ?)".to_string());
+                let prefix = format!("{}#", source);
+                values.push(Value::Text(prefix.clone()));
+                values.push(Value::Text(prefix));
+            }
+
+            // Criterios que las entradas fijadas no necesitan cumplir
+            let mut criteria = Vec::new();
+            if filter.pinned_only {
+                criteria.push("0".to_string());
+            }
+            if !filter.types.is_empty() {
+                criteria.push(format!("k.knowledge_type IN ({})", vec!["?"; filter.types.len()].join(", ")));
+                values.extend(filter.types.iter().cloned().map(Value::Text));
+            }
+            if let Some(min) = filter.min_importance {
+                criteria.push("k.importance >= ?".to_string());
+                values.push(Value::Integer(min));
+            }
+            if !criteria.is_empty() {
+                conditions.push(format!("(k.pinned = 1 OR ({}))", criteria.join(" AND ")));
+            }
+            values.push(Value::Integer(filter.limit.map_or(-1, |l| l as i64)));
+
+            let sql = format!(
+                "SELECT k.id FROM knowledge k WHERE {}
+                 ORDER BY k.pinned DESC, k.importance DESC, k.updated_at DESC, k.id
+                 LIMIT ?",
+                conditions.join(" AND ")
+            );
+            let mut stmt = conn.prepare(&sql)?;
+            let rows = stmt.query_map(params_from_iter(values), |row| row.get(0))?;
+            rows.collect::<SqlResult<_>>()?
+        };
+
+        let mut entries = Vec::new();
+        for id in ids {
+            entries.extend(self.get_detail(id)?);
+        }
+        Ok(entries)
+    }
+
+    /// Anota la raíz en esta máquina del proyecto `project_path`
+    pub fn remember_project_dir(&self, project_path: &str, dir: &Path) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO project_dirs (project_path, dir) VALUES (?1, ?2)
+             ON CONFLICT(project_path) DO UPDATE SET dir = excluded.dir",
+            params![project_path, dir.to_string_lossy()],
+        )?;
+        Ok(())
+    }
+
+    /// Raíz en esta máquina del proyecto `project_path`, si se conoce
+    pub fn project_dir(&self, project_path: &str) -> SqlResult<Option<PathBuf>> {
+        let conn = self.reader();
+        conn.query_row(
+            "SELECT dir FROM project_dirs WHERE project_path = ?1",
+            params![project_path],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map(|dir| dir.map(PathBuf::from))
+    }
+
+    /// IDs de las entradas que referencian archivos, opcionalmente solo de un proyecto
+    /// (o de sus subdirectorios)
+    pub fn entries_with_files(&self, project: Option<&str>) -> SqlResult<Vec<i64>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT k.id FROM knowledge k
+             WHERE EXISTS (SELECT 1 FROM knowledge_files f WHERE f.knowledge_id = k.id)
+               AND (?1 IS NULL OR k.project_path = ?1 OR substr(k.project_path, 1, length(?2)) = ?2)
+             ORDER BY k.id"
+        )?;
+        let under = project.map(|p| format!("{}/", p.trim_end_matches('/')));
+        let rows = stmt.query_map(params![project, under], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Guarda el resultado de verificar una entrada contra el código actual
+    ///
+    /// Registra un evento `verified` o `stale` en el timeline solo si es la primera
+    /// verificación o si el estado cambió. Devuelve si se registró el evento.
+    pub fn record_verification(&self, knowledge_id: i64, stale: bool, description: &str) -> SqlResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().timestamp();
+
+        let (was_stale, verified_at): (bool, Option<i64>) = tx.query_row(
+            "SELECT stale, verified_at FROM knowledge WHERE id = ?1",
+            params![knowledge_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        tx.execute(
+            "UPDATE knowledge SET stale = ?1, verified_at = ?2 WHERE id = ?3",
+            params![stale, now, knowledge_id],
+        )?;
+
+        let changed = verified_at.is_none() || was_stale != stale;
+        if changed {
+            add_event(&tx, knowledge_id, if stale { "stale" } else { "verified" }, description, now)?;
+        }
+
+        tx.commit()?;
+        Ok(changed)
+    }
+
+    /// Entradas de un proyecto cuyo archivo de origen está bajo `prefix`, como
+    /// (ruta de origen, ID, hash del origen)
+    pub fn sources_under(&self, project: &str, prefix: &str) -> SqlResult<Vec<(String, i64, Option<String>)>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT source_path, id, source_hash FROM knowledge
+             WHERE project_path = ?1 AND substr(source_path, 1, length(?2)) = ?2"
+        )?;
+        let rows = stmt.query_map(params![project, prefix], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+        rows.collect()
+    }
+
+    /// Reemplaza el contenido de una entrada existente
+    ///
+    /// Solo se escriben (y registran para sincronizar) los campos que cambiaron.
+    /// `description` se registra como evento `updated`. Devuelve si hubo cambios.
+    pub fn update_knowledge(&self, id: i64, entry: &NewKnowledge, description: &str) -> SqlResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().timestamp();
+
+        let current = knowledge_fields(&tx, id)?;
+        let mut changed = Vec::new();
+        for (field, value) in new_fields(entry) {
+            if current.get(&field) != Some(&value) {
+                apply_field(&tx, id, &field, &value)?;
+                changed.push(field);
+            }
+        }
+        tx.execute(
+            "UPDATE knowledge SET source_path = ?1, source_hash = ?2 WHERE id = ?3",
+            params![entry.source_path, entry.source_hash, id],
+        )?;
+        if changed.is_empty() {
+            tx.commit()?;
+            return Ok(false);
+        }
+
+        tx.execute("UPDATE knowledge SET updated_at = ?1 WHERE id = ?2", params![now, id])?;
+        let changed: Vec<&str> = changed.iter().map(String::as_str).collect();
+        log_knowledge(&tx, id, Some(&changed))?;
+        add_event(&tx, id, "updated", description, now)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Fija o suelta una entrada y cambia su importancia
+    ///
+    /// Devuelve `None` si la entrada no existe y si no, si hubo cambios.
+    pub fn set_priority(&self, id: i64, pinned: Option<bool>, importance: Option<i64>) -> SqlResult<Option<bool>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let current: Option<(bool, i64)> = tx
+            .query_row("SELECT pinned, importance FROM knowledge WHERE id = ?1", params![id], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .optional()?;
+        let Some((was_pinned, was_importance)) = current else {
+            return Ok(None);
+        };
+
+        let mut changed = Vec::new();
+        let mut descriptions = Vec::new();
+        if let Some(pinned) = pinned.filter(|p| *p != was_pinned) {
+            tx.execute("UPDATE knowledge SET pinned = ?1 WHERE id = ?2", params![pinned, id])?;
+            changed.push("pinned");
+            descriptions.push(if pinned { "Fijada".to_string() } else { "Ya no está fijada".to_string() });
+        }
+        if let Some(importance) = importance.filter(|i| *i != was_importance) {
+            tx.execute("UPDATE knowledge SET importance = ?1 WHERE id = ?2", params![importance, id])?;
+            changed.push("importance");
+            descriptions.push(format!("Importancia {} → {}", was_importance, importance));
+        }
+        if changed.is_empty() {
+            return Ok(Some(false));
+        }
+
+        log_knowledge(&tx, id, Some(&changed))?;
+        add_event(&tx, id, "updated", &descriptions.join("; "), Utc::now().timestamp())?;
+        tx.commit()?;
+        Ok(Some(true))
+    }
+
+    /// Elimina una entrada con su timeline, archivos referenciados y enlaces
+    ///
+    /// El borrado se registra en el changelog, así llega también a las demás bases.
+    pub fn delete_knowledge(&self, id: i64) -> SqlResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        delete_entry(&tx, id)?;
+        tx.commit()
+    }
+
+    /// Enlaza la entrada `source_id` con `target_id`; `false` si el enlace ya existía
+    pub fn link(&self, source_id: i64, target_id: i64, relation: &str) -> SqlResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let added = tx.execute(
+            "INSERT OR IGNORE INTO knowledge_links (source_id, target_id, relation, created_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![source_id, target_id, relation, Utc::now().timestamp()],
+        )? > 0;
+        if added {
+            let uuid = |id: i64| tx.query_row("SELECT uuid FROM knowledge WHERE id = ?1", params![id], |row| row.get(0));
+            let (source, target) = (uuid(source_id)?, uuid(target_id)?);
+            log_change(&tx, Change::Link { source, target, relation: relation.to_string() })?;
+        }
+        tx.commit()?;
+        Ok(added)
+    }
+
+    /// Enlaces que salen de una entrada, en el orden en que se crearon
+    pub fn links(&self, knowledge_id: i64) -> SqlResult<Vec<KnowledgeLink>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT k.id, k.knowledge_type, k.title, l.relation, k.project_path FROM knowledge_links l
+             JOIN knowledge k ON k.id = l.target_id
+             WHERE l.source_id = ?1 ORDER BY l.created_at, l.rowid"
+        )?;
+        let rows = stmt.query_map(params![knowledge_id], |row| {
+            Ok(KnowledgeLink {
+                id: row.get(0)?,
+                knowledge_type: row.get(1)?,
+                title: row.get(2)?,
+                relation: row.get(3)?,
+                project_path: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Registra la sesión en esta base si todavía no está
+    ///
+    /// Se llama al guardar la primera entrada de la sesión en el store: las sesiones
+    /// que no guardaron nada en una base no quedan en ella.
+    pub fn ensure_session(&self, session: &SessionInfo) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO sessions (id, client, project_path, started_at) VALUES (?1, ?2, ?3, ?4)",
+            params![session.id, session.client, session.project_path, session.started_at],
+        )?;
+        Ok(())
+    }
+
+    /// Registra el fin de una sesión y su último proyecto activo
+    pub fn end_session(&self, id: &str, project_path: Option<&str>, ended_at: i64) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sessions SET ended_at = ?1, project_path = COALESCE(?2, project_path) WHERE id = ?3",
+            params![ended_at, project_path, id],
+        )?;
+        Ok(())
+    }
+
+    /// Cambia el proyecto de una sesión (el cliente informó sus raíces)
+    pub fn update_session_project(&self, id: &str, project_path: &str) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE sessions SET project_path = ?1 WHERE id = ?2", params![project_path, id])?;
+        Ok(())
+    }
+
+    /// Sesiones iniciadas desde `since`, más recientes primero, con cuántas entradas
+    /// se guardaron en cada una
+    pub fn sessions(&self, since: Option<i64>, client: Option<&str>, limit: i64) -> SqlResult<Vec<SessionInfo>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT s.id, s.client, s.project_path, s.started_at, s.ended_at,
+                    (SELECT COUNT(*) FROM knowledge k WHERE k.session_id = s.id)
+             FROM sessions s
+             WHERE (?1 IS NULL OR s.started_at >= ?1) AND (?2 IS NULL OR s.client = ?2)
+             ORDER BY s.started_at DESC
+             LIMIT ?3"
+        )?;
+        let rows = stmt.query_map(params![since, client, limit], |row| {
+            Ok(SessionInfo {
+                id: row.get(0)?,
+                client: row.get(1)?,
+                project_path: row.get(2)?,
+                started_at: row.get(3)?,
+                ended_at: row.get(4)?,
+                entries: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Cuenta un acceso a cada una de las entradas
+    pub fn record_access(&self, ids: &[i64], access: Access) -> SqlResult<()> {
+        let column = match access {
+            Access::Search => "search_hits",
+            Access::Detail => "detail_views",
+        };
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let now = Utc::now().timestamp();
+        for id in ids {
+            tx.execute(
+                &format!(
+                    "INSERT INTO knowledge_usage (knowledge_id, {0}, last_accessed_at)
+                     SELECT id, 1, ?2 FROM knowledge WHERE id = ?1
+                     ON CONFLICT(knowledge_id) DO UPDATE SET {0} = {0} + 1, last_accessed_at = excluded.last_accessed_at",
+                    column
+                ),
+                params![id, now],
+            )?;
+        }
+        tx.commit()
+    }
+
+    /// Registra una valoración de `mem_feedback` con un evento `feedback` en el
+    /// timeline. Devuelve el uso resultante, o `None` si la entrada no existe.
+    pub fn record_feedback(&self, id: i64, useful: bool) -> SqlResult<Option<KnowledgeUsage>> {
+        let column = if useful { "useful" } else { "not_useful" };
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let inserted = tx.execute(
+            &format!(
+                "INSERT INTO knowledge_usage (knowledge_id, {0})
+                 SELECT id, 1 FROM knowledge WHERE id = ?1
+                 ON CONFLICT(knowledge_id) DO UPDATE SET {0} = {0} + 1",
+                column
+            ),
+            params![id],
+        )?;
+        if inserted == 0 {
+            return Ok(None);
+        }
+        let description = if useful { "Valorada como útil" } else { "Valorada como no útil" };
+        add_event(&tx, id, "feedback", description, Utc::now().timestamp())?;
+        let usage = tx.query_row(
+            "SELECT search_hits, detail_views, useful, not_useful, last_accessed_at
+             FROM knowledge_usage WHERE knowledge_id = ?1",
+            params![id],
+            |row| {
+                Ok(KnowledgeUsage {
+                    search_hits: row.get(0)?,
+                    detail_views: row.get(1)?,
+                    useful: row.get(2)?,
+                    not_useful: row.get(3)?,
+                    last_accessed_at: row.get(4)?,
+                })
+            },
+        )?;
+        tx.commit()?;
+        Ok(Some(usage))
+    }
+
+    /// Informe de uso de las entradas vigentes (sin vencer)
+    ///
+    /// Las más usadas se ordenan por aperturas y valoraciones útiles, luego por
+    /// apariciones en búsquedas; las nunca usadas, de la más antigua a la más nueva.
+    pub fn usage_report(&self, limit: i64) -> SqlResult<UsageReport> {
+        let conn = self.reader();
+        let now = Utc::now().timestamp();
+        let query = |condition: &str, order: &str| -> SqlResult<Vec<UsageEntry>> {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT k.id, k.knowledge_type, k.title, k.project_path, k.created_at,
+                        COALESCE(u.search_hits, 0), COALESCE(u.detail_views, 0),
+                        COALESCE(u.useful, 0), COALESCE(u.not_useful, 0), u.last_accessed_at
+                 FROM knowledge k LEFT JOIN knowledge_usage u ON u.knowledge_id = k.id
+                 WHERE (k.expires_at IS NULL OR k.expires_at > ?1) AND {}
+                 ORDER BY {}
+                 LIMIT ?2",
+                condition, order
+            ))?;
+            let rows = stmt.query_map(params![now, limit], |row| {
+                Ok(UsageEntry {
+                    id: row.get(0)?,
+                    knowledge_type: row.get(1)?,
+                    title: row.get(2)?,
+                    project_path: row.get(3)?,
+                    created_at: row.get(4)?,
+                    usage: KnowledgeUsage {
+                        search_hits: row.get(5)?,
+                        detail_views: row.get(6)?,
+                        useful: row.get(7)?,
+                        not_useful: row.get(8)?,
+                        last_accessed_at: row.get(9)?,
+                    },
+                })
+            })?;
+            rows.collect()
+        };
+        let never = "COALESCE(u.search_hits, 0) + COALESCE(u.detail_views, 0) = 0";
+        let most_used = query(
+            &format!("NOT ({})", never),
+            "COALESCE(u.detail_views, 0) + COALESCE(u.useful, 0) DESC, COALESCE(u.search_hits, 0) DESC, k.id",
+        )?;
+        let never_used = query(never, "k.created_at, k.id")?;
+        let never_used_total = conn.query_row(
+            &format!(
+                "SELECT COUNT(*) FROM knowledge k LEFT JOIN knowledge_usage u ON u.knowledge_id = k.id
+                 WHERE (k.expires_at IS NULL OR k.expires_at > ?1) AND {}",
+                never
+            ),
+            params![now],
+            |row| row.get(0),
+        )?;
+        Ok(UsageReport {
+            most_used,
+            never_used,
+            never_used_total,
+        })
+    }
+
+    /// Procesa las entradas vencidas a la fecha `now`
+    ///
+    /// Con `purge` las elimina; si no, las archiva: quedan en la base (fuera de las
+    /// búsquedas) con un evento `archived` en su timeline. Cada entrada se archiva
+    /// una sola vez. Devuelve los IDs procesados.
+    pub fn expire_entries(&self, now: i64, purge: bool) -> SqlResult<Vec<i64>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let sql = if purge {
+            "SELECT id, expires_at FROM knowledge WHERE expires_at <= ?1 ORDER BY id"
+        } else {
+            "SELECT id, expires_at FROM knowledge WHERE expires_at <= ?1 AND archived_at IS NULL ORDER BY id"
+        };
+        let expired: Vec<(i64, i64)> = tx
+            .prepare(sql)?
+            .query_map(params![now], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqlResult<_>>()?;
+
+        for &(id, expires_at) in &expired {
+            if purge {
+                delete_entry(&tx, id)?;
+            } else {
+                tx.execute("UPDATE knowledge SET archived_at = ?1 WHERE id = ?2", params![now, id])?;
+                let date = chrono::DateTime::from_timestamp(expires_at, 0).unwrap_or_default();
+                add_event(
+                    &tx,
+                    id,
+                    "archived",
+                    &format!("Entrada archivada: venció el {}", date.format("%Y-%m-%d %H:%M UTC")),
+                    now,
+                )?;
+            }
+        }
+        tx.commit()?;
+        Ok(expired.into_iter().map(|(id, _)| id).collect())
+    }
+
+    /// Tipos de conocimiento registrados: primero los incluidos, después por nombre
+    pub fn knowledge_types(&self) -> SqlResult<Vec<KnowledgeTypeDef>> {
+        let conn = self.reader();
+        let mut stmt = conn.prepare(
+            "SELECT name, description, required_fields, default_importance, builtin, fields FROM knowledge_types
+             ORDER BY builtin DESC, CASE WHEN builtin = 1 THEN rowid END, name"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let required: String = row.get(2)?;
+            let fields: String = row.get(5)?;
+            Ok(KnowledgeTypeDef {
+                name: row.get(0)?,
+                description: row.get(1)?,
+                required_fields: serde_json::from_str(&required).unwrap_or_default(),
+                default_importance: row.get(3)?,
+                builtin: row.get(4)?,
+                fields: serde_json::from_str(&fields).unwrap_or_default(),
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn knowledge_type(&self, name: &str) -> SqlResult<Option<KnowledgeTypeDef>> {
+        Ok(self.knowledge_types()?.into_iter().find(|t| t.name == name))
+    }
+
+    /// Registra un tipo o reemplaza su definición (un tipo incluido sigue siéndolo)
+    pub fn define_type(&self, def: &KnowledgeTypeDef) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO knowledge_types (name, description, required_fields, default_importance, fields)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT(name) DO UPDATE SET description = excluded.description,
+                 required_fields = excluded.required_fields, default_importance = excluded.default_importance,
+                 fields = excluded.fields",
+            params![
+                def.name,
+                def.description,
+                to_json(&def.required_fields)?,
+                def.default_importance,
+                to_json(&def.fields)?
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Número de entradas de un tipo
+    pub fn type_usage(&self, name: &str) -> SqlResult<usize> {
+        let conn = self.reader();
+        let count: i64 =
+            conn.query_row("SELECT COUNT(*) FROM knowledge WHERE knowledge_type = ?1", params![name], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Elimina un tipo definido por el equipo; devuelve si existía
+    pub fn remove_type(&self, name: &str) -> SqlResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let removed = conn.execute("DELETE FROM knowledge_types WHERE name = ?1 AND builtin = 0", params![name])?;
+        Ok(removed > 0)
+    }
+
+    /// Pares (tag, ID de entrada) de las entradas que cumplen los filtros de proyecto
+    /// y acceso de `options`, opcionalmente solo bajo el tag `prefix`
+    pub fn tag_assignments(&self, options: &SearchOptions, prefix: Option<&str>) -> SqlResult<Vec<(String, i64)>> {
+        let conn = self.reader();
+        let mut conditions = vec!["1".to_string()];
+        let mut values = Vec::new();
+        scope_conditions(options, &mut conditions, &mut values);
+        if let Some(prefix) = prefix.map(normalize_tag) {
+            conditions.push("(t.tag = ? OR substr(t.tag, 1, length(?)) = ?)".to_string());
+            let under = format!("{}/", prefix);
+            values.push(Value::Text(prefix));
+            values.push(Value::Text(under.clone()));
+            values.push(Value::Text(under));
+        }
+        let sql = format!(
+            "SELECT t.tag, t.knowledge_id FROM knowledge_tags t JOIN knowledge k ON k.id = t.knowledge_id
+             WHERE {} ORDER BY t.tag",
+            conditions.join(" AND ")
+        );
+        let mut stmt = conn.prepare(&sql)?;
+        let rows = stmt.query_map(params_from_iter(values), |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Entradas con el tag `tag` o un descendiente suyo, como (ID, proyecto)
+    pub fn entries_with_tag(&self, tag: &str) -> SqlResult<Vec<(i64, Option<String>)>> {
+        let conn = self.reader();
+        let tag = normalize_tag(tag);
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT k.id, k.project_path FROM knowledge_tags t JOIN knowledge k ON k.id = t.knowledge_id
+             WHERE t.tag = ?1 OR substr(t.tag, 1, length(?2)) = ?2
+             ORDER BY k.id"
+        )?;
+        let rows = stmt.query_map(params![tag, format!("{}/", tag)], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Reemplaza el tag `from` (y sus descendientes, `from/x` pasa a `to/x`) por `to`
+    /// en una entrada; si ya tenía `to`, los dos se fusionan. Los tags que ya son
+    /// `to` o están debajo no se tocan (si `to` está debajo de `from`, renombrarlos
+    /// otra vez daría `to/.../to`). Devuelve si cambió.
+    pub fn rename_tag(&self, id: i64, from: &str, to: &str) -> SqlResult<bool> {
+        let (from, to) = (normalize_tag(from), normalize_tag(to));
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let json: Option<String> = tx
+            .query_row("SELECT tags FROM knowledge WHERE id = ?1", params![id], |row| row.get(0))
+            .optional()?
+            .flatten();
+        let tags: Vec<String> = json.as_deref().and_then(|t| serde_json::from_str(t).ok()).unwrap_or_default();
+        let under_to = format!("{}/", to);
+        let renamed: Vec<String> = tags
+            .iter()
+            .map(|tag| match tag.strip_prefix(&from) {
+                _ if *tag == to || tag.starts_with(&under_to) => tag.clone(),
+                Some("") => to.clone(),
+                Some(rest) if rest.starts_with('/') => format!("{}{}", to, rest),
+                _ => tag.clone(),
+            })
+            .collect();
+        let renamed = normalize_tags(&renamed);
+        if renamed == tags {
+            return Ok(false);
+        }
+
+        let now = Utc::now().timestamp();
+        tx.execute(
+            "UPDATE knowledge SET tags = ?1, updated_at = ?2 WHERE id = ?3",
+            params![to_json(&renamed)?, now, id],
+        )?;
+        index_tags(&tx, id, &renamed)?;
+        log_knowledge(&tx, id, Some(&["tags"]))?;
+        add_event(&tx, id, "updated", &format!("Tag '{}' renombrado a '{}'", from, to), now)?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Número de entradas almacenadas
+    pub fn count(&self) -> SqlResult<usize> {
+        let conn = self.reader();
+        let count: i64 = conn.query_row("SELECT COUNT(*) FROM knowledge", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// Identificador de esta base en la sincronización
+    pub fn node_id(&self) -> SqlResult<String> {
+        let conn = self.reader();
+        Ok(sync_value(&conn, "node")?.unwrap_or_default())
+    }
+
+    /// Valor guardado por la sincronización (marcas de lo ya exportado/importado)
+    pub fn sync_state(&self, key: &str) -> SqlResult<Option<String>> {
+        let conn = self.reader();
+        sync_value(&conn, key)
+    }
+
+    pub fn set_sync_state(&self, key: &str, value: &str) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        set_sync_value(&conn, key, value)
+    }
+
+    /// Operaciones escritas por esta base con `seq` mayor que `after`, como
+    /// pares (seq, JSON de la operación)
+    pub fn local_changes_after(&self, after: i64) -> SqlResult<Vec<(i64, String)>> {
+        let conn = self.reader();
+        let node = sync_value(&conn, "node")?.unwrap_or_default();
+        let mut stmt = conn.prepare(
+            "SELECT seq, data FROM changelog WHERE node = ?1 AND seq > ?2 ORDER BY seq"
+        )?;
+        let rows = stmt.query_map(params![node, after], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Aplica una operación de otra base
+    ///
+    /// Cada campo conserva la escritura con mayor versión (last-writer-wins). Si
+    /// la escritura remota no partió de la versión local (ediciones concurrentes)
+    /// y los valores difieren, se registra un evento `conflict` en el timeline
+    /// con el valor descartado.
+    pub fn apply_change(&self, op: &ChangeOp) -> SqlResult<Applied> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let seen = tx
+            .prepare("SELECT 1 FROM changelog WHERE op_id = ?1")?
+            .exists(params![op.id])?;
+        if seen {
+            return Ok(Applied::default());
+        }
+
+        // Reloj Lamport: toda escritura local posterior queda por delante de esta
+        let clock = sync_value(&tx, "clock")?.and_then(|c| c.parse().ok()).unwrap_or(0i64);
+        set_sync_value(&tx, "clock", &clock.max(op.clock).to_string())?;
+
+        let stamp: Stamp = (op.clock, op.node.clone());
+        let now = Utc::now().timestamp();
+        let mut conflicts = 0;
+        match &op.change {
+            Change::Knowledge { uuid, fields, base } => {
+                let existing: Option<i64> = tx
+                    .query_row("SELECT id FROM knowledge WHERE uuid = ?1", params![uuid], |row| row.get(0))
+                    .optional()?;
+                // Una entrada borrada ignora las ediciones parciales; solo la revive
+                // una versión completa posterior al borrado
+                let deleted = match (existing, tombstone(&tx, uuid)?) {
+                    (None, Some(deleted)) => !fields.contains_key("created_at") || stamp < deleted,
+                    _ => false,
+                };
+                match existing {
+                    None if deleted => {}
+                    None => {
+                        tx.execute("DELETE FROM tombstones WHERE uuid = ?1", params![uuid])?;
+                        let text = |field: &str| fields.get(field).and_then(|v| v.as_str()).unwrap_or_default();
+                        let created_at = fields.get("created_at").and_then(|v| v.as_i64()).unwrap_or(now);
+                        // Un solo INSERT con el texto completo para que el trigger lo indexe bien
+                        tx.execute(
+                            "INSERT INTO knowledge
+                            (uuid, knowledge_type, title, content, summary, tags, created_at, updated_at)
+                            VALUES (?1, ?2, ?3, ?4, ?5, '[]', ?6, ?7)",
+                            params![
+                                uuid,
+                                text("knowledge_type"),
+                                text("title"),
+                                text("content"),
+                                text("summary"),
+                                created_at,
+                                now
+                            ],
+                        )?;
+                        let id = tx.last_insert_rowid();
+                        for (field, value) in fields {
+                            apply_field(&tx, id, field, value)?;
+                            set_field_version(&tx, uuid, field, &stamp)?;
+                        }
+                    }
+                    Some(id) => {
+                        let current = knowledge_fields(&tx, id)?;
+                        let mut changed = false;
+                        for (field, value) in fields {
+                            let local = field_version(&tx, uuid, field)?;
+                            let remote_wins = local.as_ref().is_none_or(|l| stamp > *l);
+                            let concurrent = match local {
+                                None => false,
+                                Some(ref l) if remote_wins => base.get(field) != Some(l),
+                                Some(_) => true,
+                            };
+                            let local_value = current.get(field).unwrap_or(&serde_json::Value::Null);
+                            if concurrent && local_value != value {
+                                conflicts += 1;
+                                let (kept, discarded) = if remote_wins {
+                                    (value, local_value)
+                                } else {
+                                    (local_value, value)
+                                };
+                                let description = format!(
+                                    "Conflicto en '{}' con el nodo {}: se conservó {} y se descartó {}",
+                                    field,
+                                    op.node,
+                                    preview(kept),
+                                    preview(discarded)
+                                );
+                                add_event(&tx, id, "conflict", &description, now)?;
+                            }
+                            if remote_wins {
+                                apply_field(&tx, id, field, value)?;
+                                set_field_version(&tx, uuid, field, &stamp)?;
+                                changed = true;
+                            }
+                        }
+                        if changed {
+                            tx.execute("UPDATE knowledge SET updated_at = ?1 WHERE id = ?2", params![now, id])?;
+                        }
+                    }
+                }
+            }
+            Change::Timeline { uuid, knowledge, event_type, description, timestamp } => {
+                let knowledge_id: Option<i64> = tx
+                    .query_row("SELECT id FROM knowledge WHERE uuid = ?1", params![knowledge], |row| row.get(0))
+                    .optional()?;
+                // Si la entrada ya no existe aquí el evento no tiene a qué asociarse
+                if let Some(knowledge_id) = knowledge_id {
+                    tx.execute(
+                        "INSERT OR IGNORE INTO knowledge_timeline (uuid, knowledge_id, event_type, description, timestamp)
+                        VALUES (?1, ?2, ?3, ?4, ?5)",
+                        params![uuid, knowledge_id, event_type, description, timestamp],
+                    )?;
+                }
+            }
+            Change::Delete { uuid } => {
+                let existing: Option<i64> = tx
+                    .query_row("SELECT id FROM knowledge WHERE uuid = ?1", params![uuid], |row| row.get(0))
+                    .optional()?;
+                // Sobrevive al borrado solo una entrada creada de nuevo después de él
+                let recreated = field_version(&tx, uuid, "created_at")?.is_some_and(|created| created > stamp);
+                if !(existing.is_some() && recreated) {
+                    if tombstone(&tx, uuid)?.is_none_or(|deleted| stamp > deleted) {
+                        set_tombstone(&tx, uuid, &stamp)?;
+                    }
+                    tx.execute("DELETE FROM field_versions WHERE uuid = ?1", params![uuid])?;
+                    tx.execute("DELETE FROM knowledge WHERE uuid = ?1", params![uuid])?;
+                }
+            }
+            Change::Link { source, target, relation } => {
+                let id = |uuid: &str| -> SqlResult<Option<i64>> {
+                    tx.query_row("SELECT id FROM knowledge WHERE uuid = ?1", params![uuid], |row| row.get(0))
+                        .optional()
+                };
+                // Si alguna de las dos entradas se borró aquí, el enlace se descarta
+                if let (Some(source_id), Some(target_id)) = (id(source)?, id(target)?) {
+                    tx.execute(
+                        "INSERT OR IGNORE INTO knowledge_links (source_id, target_id, relation, created_at)
+                         VALUES (?1, ?2, ?3, ?4)",
+                        params![source_id, target_id, relation, now],
+                    )?;
+                }
+            }
+        }
+
+        tx.execute(
+            "INSERT INTO changelog (op_id, node, data) VALUES (?1, ?2, ?3)",
+            params![op.id, op.node, to_json(op)?],
+        )?;
+        tx.commit()?;
+        Ok(Applied { new: true, conflicts })
+    }
+
+    /// Reconstruye el índice FTS5 por lotes dentro de una transacción
+    ///
+    /// `on_batch(procesadas, total)` se invoca tras cada lote; si devuelve `false`
+    /// se hace rollback y el índice queda como estaba. Devuelve las entradas
+    /// reindexadas, o `None` si se abortó.
+    pub fn rebuild_index<F>(&self, batch_size: usize, mut on_batch: F) -> SqlResult<Option<usize>>
+    where
+        F: FnMut(usize, usize) -> bool,
+    {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let total: i64 = tx.query_row("SELECT COUNT(*) FROM knowledge", [], |row| row.get(0))?;
+        tx.execute("INSERT INTO knowledge_fts(knowledge_fts) VALUES('delete-all')", [])?;
+        tx.execute("DELETE FROM knowledge_fields_fts", [])?;
+
+        let mut done = 0usize;
+        let mut last_id = 0i64;
+        loop {
+            let batch_end: Option<i64> = tx.query_row(
+                "SELECT MAX(id) FROM (SELECT id FROM knowledge WHERE id > ?1 ORDER BY id LIMIT ?2)",
+                params![last_id, batch_size as i64],
+                |row| row.get(0),
+            )?;
+            let Some(batch_end) = batch_end else {
+                break;
+            };
+
+            done += tx.execute(
+                "INSERT INTO knowledge_fts(rowid, title, content, summary, tags, fields)
+                 SELECT id, title, content, summary, tags, fields FROM knowledge
+                 WHERE id > ?1 AND id <= ?2",
+                params![last_id, batch_end],
+            )?;
+            let with_fields: Vec<(i64, String)> = tx
+                .prepare("SELECT id, fields FROM knowledge WHERE id > ?1 AND id <= ?2 AND fields IS NOT NULL")?
+                .query_map(params![last_id, batch_end], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<SqlResult<_>>()?;
+            for (id, fields) in with_fields {
+                index_fields(&tx, id, &serde_json::from_str(&fields).unwrap_or_default())?;
+            }
+            last_id = batch_end;
+
+            if !on_batch(done, total as usize) {
+                // Al soltar `tx` sin commit se hace rollback
+                return Ok(None);
+            }
+        }
+
+        tx.commit()?;
+        Ok(Some(done))
+    }
+}
+
+/// Filtros de proyecto y de política de acceso de `options` sobre `knowledge k`,
+/// más la exclusión de las entradas vencidas
+///
+/// Compara prefijos exactos (LIKE interpretaría '%' y '_' en las rutas).
+fn scope_conditions(options: &SearchOptions, conditions: &mut Vec<String>, values: &mut Vec<Value>) {
+    conditions.push("(k.expires_at IS NULL OR k.expires_at > ?)".to_string());
+    values.push(Value::Integer(Utc::now().timestamp()));
+    if let Some(ref project) = options.project {
+        conditions.push(format!("(k.project_path IS NULL OR {})", under_project(project, values)));
+    }
+    if !options.allow_projects.is_empty() {
+        let any: Vec<&str> = options.allow_projects.iter().map(|p| under_project(p, values)).collect();
+        conditions.push(format!("({})", any.join(" OR ")));
+    }
+    for prefix in &options.deny_projects {
+        conditions.push(format!("(k.project_path IS NULL OR NOT {})", under_project(prefix, values)));
+    }
+}
+
+/// Condición "`project_path` es `prefix` o está debajo", con sus parámetros
+///
+/// Solo cuenta como debajo a partir de un separador: `/work/app` incluye
+/// `/work/app/api` pero no `/work/app-secrets`.
+fn under_project(prefix: &str, values: &mut Vec<Value>) -> &'static str {
+    let under = format!("{}/", prefix.trim_end_matches('/'));
+    values.push(Value::Text(prefix.to_string()));
+    values.push(Value::Text(under.clone()));
+    values.push(Value::Text(under));
+    "(k.project_path = ? OR substr(k.project_path, 1, length(?)) = ?)"
+}
+
+/// Términos de la relevancia de cada resultado, como expresiones SQL sobre `k`
+/// (knowledge) y `u` (knowledge_usage): el BM25 y los factores que lo multiplican
+/// (uso, valoraciones, recencia y tipo). Los pesos van como parámetros, que se
+/// agregan a `values` en el orden en que aparecen.
+///
+/// bm25() recibe un peso por columna en el orden de definición de knowledge_fts.
+/// Es negativo (más negativo = mejor) y los factores son siempre positivos (ver
+/// [`RankingWeights::validate`]): mayores que 1 hacen subir una entrada y menores
+/// que 1, bajar. La recencia decae de forma hiperbólica con los días desde la
+/// última actualización: vale `1 + recency` al actualizar y `1 + recency / 2`
+/// pasada la vida media.
+fn ranking_terms(options: &SearchOptions, values: &mut Vec<Value>) -> [String; 5] {
+    let w = &options.weights;
+    let bm25 = if options.field.is_some() {
+        "bm25(knowledge_fields_fts)".to_string()
+    } else {
+        values.extend([w.title, w.content, w.summary, w.tags, w.fields].map(Value::Real));
+        "bm25(knowledge_fts, ?, ?, ?, ?, ?)".to_string()
+    };
+    values.push(Value::Real(w.usage));
+    let usage = format!(
+        "(1.0 + ? * MIN(COALESCE(u.detail_views, 0), {}) / {}.0)",
+        USAGE_SATURATION, USAGE_SATURATION
+    );
+    values.push(Value::Real(w.feedback));
+    let feedback = "(1.0 + ? * (COALESCE(u.useful, 0) - COALESCE(u.not_useful, 0))
+                / (COALESCE(u.useful, 0) + COALESCE(u.not_useful, 0) + 1.0))"
+        .to_string();
+    values.extend([
+        Value::Real(w.recency),
+        Value::Real(w.recency_half_life_days),
+        Value::Real(w.recency_half_life_days),
+        Value::Integer(Utc::now().timestamp()),
+    ]);
+    let recency = "(1.0 + ? * ? / (? + MAX(? - k.updated_at, 0) / 86400.0))".to_string();
+    let knowledge_type = if w.types.is_empty() {
+        "1.0".to_string()
+    } else {
+        for (name, factor) in &w.types {
+            values.push(Value::Text(name.clone()));
+            values.push(Value::Real(*factor));
+        }
+        format!("(CASE k.knowledge_type {} ELSE 1.0 END)", vec!["WHEN ? THEN ?"; w.types.len()].join(" "))
+    };
+    [bm25, usage, feedback, recency, knowledge_type]
+}
+
+/// Convierte la consulta del usuario en una consulta FTS5 que no puede fallar por
+/// sintaxis: cada palabra va entre comillas como frase (`C++`, `foo-bar` o `NOT` se
+/// buscan tal cual) y todas deben aparecer. Un `*` al final de una palabra se
+/// conserva como búsqueda por prefijo.
+fn fts_query(query: &str) -> String {
+    query
+        .split_whitespace()
+        .filter_map(|word| {
+            let term = word.trim_end_matches('*');
+            if term.is_empty() {
+                return None;
+            }
+            let prefix = if term.len() < word.len() { "*" } else { "" };
+            Some(format!("\"{}\"{}", term.replace('"', "\"\""), prefix))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Completa un resultado de búsqueda de texto con el fragmento de contenido que
+/// coincidió y las columnas donde coincidió la consulta
+///
+/// Para saber si una columna coincide se le pide a `snippet()` un fragmento de una
+/// palabra con marcas de control: si trae la marca, la columna tiene coincidencias.
+fn match_details(conn: &Connection, query: &str, options: &SearchOptions, entry: &mut KnowledgeEntry) -> SqlResult<()> {
+    let snippet = &options.snippet;
+    let tokens = snippet.tokens.min(64);
+    if let Some(ref field) = options.field {
+        // Búsqueda en un campo: el fragmento sale del valor del campo
+        entry.matched_columns = vec![format!("fields.{}", field)];
+        if tokens > 0 {
+            entry.snippet = conn
+                .query_row(
+                    "SELECT snippet(knowledge_fields_fts, 0, ?1, ?2, ?3, ?4) FROM knowledge_fields_fts
+                     WHERE knowledge_fields_fts MATCH ?5 AND knowledge_id = ?6 AND name = ?7",
+                    params![snippet.start, snippet.end, snippet.ellipsis, tokens, query, entry.id, field],
+                    |row| row.get(0),
+                )
+                .optional()?;
+        }
+        return Ok(());
+    }
+
+    let probes: Vec<String> = (0..FTS_COLUMNS.len())
+        .map(|i| format!("COALESCE(instr(snippet(knowledge_fts, {}, char(1), char(2), '', 1), char(1)), 0) > 0", i))
+        .collect();
+    let (matched, content): (Vec<bool>, Option<String>) = conn.query_row(
+        &format!(
+            "SELECT {}, snippet(knowledge_fts, 1, ?1, ?2, ?3, ?4) FROM knowledge_fts
+             WHERE knowledge_fts MATCH ?5 AND rowid = ?6",
+            probes.join(", ")
+        ),
+        params![snippet.start, snippet.end, snippet.ellipsis, tokens.max(1), query, entry.id],
+        |row| {
+            let matched = (0..FTS_COLUMNS.len()).map(|i| row.get(i)).collect::<SqlResult<_>>()?;
+            Ok((matched, row.get(FTS_COLUMNS.len())?))
+        },
+    )?;
+    entry.matched_columns = FTS_COLUMNS
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| **matched)
+        .map(|(column, _)| column.to_string())
+        .collect();
+    // Sin coincidencias en el contenido, el resumen ya dice más que su comienzo
+    if tokens > 0 && matched[1] {
+        entry.snippet = content;
+    }
+    Ok(())
+}
+
+/// Forma canónica de un tag: minúsculas, espacios como `-` y niveles separados por `/`
+///
+/// `" Lang / Rust Async "` queda como `lang/rust-async`.
+pub fn normalize_tag(tag: &str) -> String {
+    tag.split('/')
+        .map(|level| level.split_whitespace().collect::<Vec<_>>().join("-").to_lowercase())
+        .filter(|level| !level.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Tags normalizados, sin vacíos ni repetidos, en el orden original
+pub fn normalize_tags(tags: &[String]) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for tag in tags.iter().map(|t| normalize_tag(t)) {
+        if !tag.is_empty() && !normalized.contains(&tag) {
+            normalized.push(tag);
+        }
+    }
+    normalized
+}
+
+/// Reemplaza las filas de `knowledge_tags` de una entrada
+fn index_tags(conn: &Connection, id: i64, tags: &[String]) -> SqlResult<()> {
+    conn.execute("DELETE FROM knowledge_tags WHERE knowledge_id = ?1", params![id])?;
+    for tag in tags {
+        conn.execute(
+            "INSERT OR IGNORE INTO knowledge_tags (knowledge_id, tag) VALUES (?1, ?2)",
+            params![id, tag],
+        )?;
+    }
+    Ok(())
+}
+
+/// Normaliza los tags de las entradas creadas antes de `knowledge_tags` y llena la tabla
+///
+/// Es local a esta base: cada máquina normaliza sus entradas al actualizarse.
+fn normalize_existing_tags(conn: &Connection) -> SqlResult<()> {
+    let rows: Vec<(i64, Option<String>)> = conn
+        .prepare("SELECT id, tags FROM knowledge")?
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<SqlResult<_>>()?;
+    for (id, json) in rows {
+        let tags: Vec<String> = json.as_deref().and_then(|t| serde_json::from_str(t).ok()).unwrap_or_default();
+        let normalized = normalize_tags(&tags);
+        if normalized != tags {
+            conn.execute("UPDATE knowledge SET tags = ?1 WHERE id = ?2", params![to_json(&normalized)?, id])?;
+        }
+        index_tags(conn, id, &normalized)?;
+    }
+    Ok(())
+}
+
+/// Agrega una columna si la tabla aún no la tiene (bases creadas por versiones anteriores);
+/// devuelve si la agregó
+fn add_column(conn: &Connection, table: &str, column: &str, definition: &str) -> SqlResult<bool> {
+    let exists = conn
+        .prepare(&format!("SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1", table))?
+        .exists(params![column])?;
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(!exists)
+}
+
+/// Campos estructurados como se guardan en `knowledge.fields` (`NULL` si no hay)
+fn fields_json(fields: &Map<String, serde_json::Value>) -> SqlResult<Option<String>> {
+    if fields.is_empty() {
+        Ok(None)
+    } else {
+        to_json(fields).map(Some)
+    }
+}
+
+/// Reemplaza los campos estructurados de una entrada en `knowledge_fields_fts`
+fn index_fields(conn: &Connection, id: i64, fields: &Map<String, serde_json::Value>) -> SqlResult<()> {
+    conn.execute("DELETE FROM knowledge_fields_fts WHERE knowledge_id = ?1", params![id])?;
+    for (name, value) in fields {
+        let text = match value {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Array(items) => items
+                .iter()
+                .map(|item| item.as_str().map_or_else(|| item.to_string(), str::to_string))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            other => other.to_string(),
+        };
+        conn.execute(
+            "INSERT INTO knowledge_fields_fts (value, knowledge_id, name) VALUES (?1, ?2, ?3)",
+            params![text, id, name],
+        )?;
+    }
+    Ok(())
+}
+
+fn sync_value(conn: &Connection, key: &str) -> SqlResult<Option<String>> {
+    conn.query_row("SELECT value FROM sync_state WHERE key = ?1", params![key], |row| row.get(0))
+        .optional()
+}
+
+fn set_sync_value(conn: &Connection, key: &str, value: &str) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO sync_state (key, value) VALUES (?1, ?2)
+         ON CONFLICT(key) DO UPDATE SET value = excluded.value",
+        params![key, value],
+    )?;
+    Ok(())
+}
+
+fn to_json<T: Serialize>(value: &T) -> SqlResult<String> {
+    serde_json::to_string(value).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Agrega una operación local al changelog con la siguiente versión de esta base
+fn log_change(conn: &Connection, change: Change) -> SqlResult<Stamp> {
+    let node = sync_value(conn, "node")?.unwrap_or_default();
+    let clock = sync_value(conn, "clock")?.and_then(|c| c.parse().ok()).unwrap_or(0i64) + 1;
+    set_sync_value(conn, "clock", &clock.to_string())?;
+
+    let op = ChangeOp {
+        id: format!("{}:{}", node, clock),
+        node: node.clone(),
+        clock,
+        change,
+    };
+    conn.execute(
+        "INSERT INTO changelog (op_id, node, data) VALUES (?1, ?2, ?3)",
+        params![op.id, op.node, to_json(&op)?],
+    )?;
+    Ok((clock, node))
+}
+
+/// Registra en el changelog los campos de una entrada (todos, o solo `only`)
+fn log_knowledge(conn: &Connection, id: i64, only: Option<&[&str]>) -> SqlResult<()> {
+    let uuid: String = conn.query_row("SELECT uuid FROM knowledge WHERE id = ?1", params![id], |row| row.get(0))?;
+    let mut fields = knowledge_fields(conn, id)?;
+    if let Some(only) = only {
+        fields.retain(|field, _| only.contains(&field.as_str()));
+    }
+
+    let mut base = BTreeMap::new();
+    for field in fields.keys() {
+        if let Some(version) = field_version(conn, &uuid, field)? {
+            base.insert(field.clone(), version);
+        }
+    }
+
+    let names: Vec<String> = fields.keys().cloned().collect();
+    let stamp = log_change(conn, Change::Knowledge { uuid: uuid.clone(), fields, base })?;
+    for field in names {
+        set_field_version(conn, &uuid, &field, &stamp)?;
+    }
+    Ok(())
+}
+
+/// Agrega un evento al timeline; salvo los eventos locales, también al changelog
+fn add_event(conn: &Connection, knowledge_id: i64, event_type: &str, description: &str, timestamp: i64) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO knowledge_timeline (uuid, knowledge_id, event_type, description, timestamp)
+        VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![uuid::Uuid::new_v4().to_string(), knowledge_id, event_type, description, timestamp],
+    )?;
+    log_event(conn, conn.last_insert_rowid())
+}
+
+fn log_event(conn: &Connection, event_id: i64) -> SqlResult<()> {
+    let (uuid, knowledge, event_type, description, timestamp): (String, String, String, String, i64) = conn.query_row(
+        "SELECT t.uuid, k.uuid, t.event_type, t.description, t.timestamp
+         FROM knowledge_timeline t JOIN knowledge k ON k.id = t.knowledge_id
+         WHERE t.id = ?1",
+        params![event_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+    )?;
+    if !LOCAL_EVENTS.contains(&event_type.as_str()) {
+        log_change(conn, Change::Timeline { uuid, knowledge, event_type, description, timestamp })?;
+    }
+    Ok(())
+}
+
+/// Asigna UUID a las filas creadas antes de la sincronización y las registra en
+/// el changelog para que también se exporten
+fn backfill_uuids(conn: &Connection) -> SqlResult<()> {
+    let ids = |sql: &str| -> SqlResult<Vec<i64>> {
+        let mut stmt = conn.prepare(sql)?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    };
+    let entries = ids("SELECT id FROM knowledge WHERE uuid IS NULL ORDER BY id")?;
+    let events = ids("SELECT id FROM knowledge_timeline WHERE uuid IS NULL ORDER BY id")?;
+    if entries.is_empty() && events.is_empty() {
+        return Ok(());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    for id in entries {
+        tx.execute(
+            "UPDATE knowledge SET uuid = ?1 WHERE id = ?2",
+            params![uuid::Uuid::new_v4().to_string(), id],
+        )?;
+        log_knowledge(&tx, id, None)?;
+    }
+    for id in events {
+        tx.execute(
+            "UPDATE knowledge_timeline SET uuid = ?1 WHERE id = ?2",
+            params![uuid::Uuid::new_v4().to_string(), id],
+        )?;
+        log_event(&tx, id)?;
+    }
+    tx.commit()
+}
+
+/// Elimina una entrada y registra su lápida, así el borrado llega a las demás bases
+fn delete_entry(conn: &Connection, id: i64) -> SqlResult<()> {
+    let uuid: Option<String> = conn
+        .query_row("SELECT uuid FROM knowledge WHERE id = ?1", params![id], |row| row.get(0))
+        .optional()?
+        .flatten();
+    if let Some(uuid) = uuid {
+        let stamp = log_change(conn, Change::Delete { uuid: uuid.clone() })?;
+        set_tombstone(conn, &uuid, &stamp)?;
+        conn.execute("DELETE FROM field_versions WHERE uuid = ?1", params![uuid])?;
+    }
+    conn.execute("DELETE FROM knowledge WHERE id = ?1", params![id])?;
+    Ok(())
+}
+
+fn tombstone(conn: &Connection, uuid: &str) -> SqlResult<Option<Stamp>> {
+    conn.query_row(
+        "SELECT clock, node FROM tombstones WHERE uuid = ?1",
+        params![uuid],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+fn set_tombstone(conn: &Connection, uuid: &str, stamp: &Stamp) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO tombstones (uuid, clock, node) VALUES (?1, ?2, ?3)
+         ON CONFLICT(uuid) DO UPDATE SET clock = excluded.clock, node = excluded.node",
+        params![uuid, stamp.0, stamp.1],
+    )?;
+    Ok(())
+}
+
+fn field_version(conn: &Connection, uuid: &str, field: &str) -> SqlResult<Option<Stamp>> {
+    conn.query_row(
+        "SELECT clock, node FROM field_versions WHERE uuid = ?1 AND field = ?2",
+        params![uuid, field],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+}
+
+fn set_field_version(conn: &Connection, uuid: &str, field: &str, stamp: &Stamp) -> SqlResult<()> {
+    conn.execute(
+        "INSERT INTO field_versions (uuid, field, clock, node) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(uuid, field) DO UPDATE SET clock = excluded.clock, node = excluded.node",
+        params![uuid, field, stamp.0, stamp.1],
+    )?;
+    Ok(())
+}
+
+/// Campos sincronizables de una entrada, como JSON
+fn knowledge_fields(conn: &Connection, id: i64) -> SqlResult<Map<String, serde_json::Value>> {
+    let mut fields = Map::new();
+    let sql = format!("SELECT {}, tags FROM knowledge WHERE id = ?1", SYNC_COLUMNS.join(", "));
+    conn.query_row(&sql, params![id], |row| {
+        for (i, column) in SYNC_COLUMNS.iter().enumerate() {
+            let value = match row.get::<_, Value>(i)? {
+                Value::Integer(n) => serde_json::Value::from(n),
+                Value::Real(f) => serde_json::Value::from(f),
+                Value::Text(t) => serde_json::Value::from(t),
+                Value::Null | Value::Blob(_) => serde_json::Value::Null,
+            };
+            fields.insert(column.to_string(), value);
+        }
+        let tags: Option<String> = row.get(SYNC_COLUMNS.len())?;
+        let tags: Vec<String> = tags.and_then(|t| serde_json::from_str(&t).ok()).unwrap_or_default();
+        fields.insert("tags".to_string(), serde_json::Value::from(tags));
+        Ok(())
+    })?;
+
+    let mut stmt = conn.prepare(
+        "SELECT path, start_line, end_line, symbol, content_hash FROM knowledge_files
+         WHERE knowledge_id = ?1 ORDER BY id"
+    )?;
+    let files = stmt.query_map(params![id], |row| {
+        Ok(serde_json::json!({
+            "path": row.get::<_, String>(0)?,
+            "start_line": row.get::<_, Option<u32>>(1)?,
+            "end_line": row.get::<_, Option<u32>>(2)?,
+            "symbol": row.get::<_, Option<String>>(3)?,
+            "content_hash": row.get::<_, Option<String>>(4)?,
+        }))
+    })?;
+    let files = files.collect::<SqlResult<Vec<_>>>()?;
+    fields.insert("file_paths".to_string(), serde_json::Value::from(files));
+    Ok(fields)
+}
+
+/// Campos sincronizables de una entrada nueva, con la misma forma que `knowledge_fields`
+/// (sin `created_at`)
+fn new_fields(entry: &NewKnowledge) -> Map<String, serde_json::Value> {
+    let files: Vec<serde_json::Value> = entry
+        .file_paths
+        .iter()
+        .map(|file| {
+            serde_json::json!({
+                "path": file.path,
+                "start_line": file.start_line,
+                "end_line": file.end_line,
+                "symbol": file.symbol,
+                "content_hash": file.content_hash,
+            })
+        })
+        .collect();
+    let serde_json::Value::Object(fields) = serde_json::json!({
+        "knowledge_type": entry.knowledge_type,
+        "title": entry.title,
+        "content": entry.content,
+        "summary": entry.summary,
+        "project_path": entry.project_path,
+        "git_commit": entry.git_commit,
+        "branch": entry.branch,
+        "importance": entry.importance,
+        "pinned": entry.pinned as i64,
+        "fields": fields_json(&entry.fields).ok().flatten(),
+        "expires_at": entry.expires_at,
+        "tags": normalize_tags(&entry.tags),
+        "file_paths": files,
+    }) else {
+        unreachable!("json! de un objeto siempre es un objeto")
+    };
+    fields
+}
+
+/// Escribe un campo sincronizable recibido de otra base (los desconocidos se ignoran)
+fn apply_field(conn: &Connection, id: i64, field: &str, value: &serde_json::Value) -> SqlResult<()> {
+    match field {
+        "tags" => {
+            let tags: Vec<String> = serde_json::from_value(value.clone()).unwrap_or_default();
+            let tags = normalize_tags(&tags);
+            conn.execute("UPDATE knowledge SET tags = ?1 WHERE id = ?2", params![to_json(&tags)?, id])?;
+            index_tags(conn, id, &tags)?;
+        }
+        "file_paths" => {
+            conn.execute("DELETE FROM knowledge_files WHERE knowledge_id = ?1", params![id])?;
+            for file in value.as_array().into_iter().flatten() {
+                let text = |key: &str| file.get(key).and_then(|v| v.as_str());
+                let line = |key: &str| file.get(key).and_then(|v| v.as_u64());
+                conn.execute(
+                    "INSERT INTO knowledge_files (knowledge_id, path, start_line, end_line, symbol, content_hash)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        id,
+                        text("path").unwrap_or_default(),
+                        line("start_line"),
+                        line("end_line"),
+                        text("symbol"),
+                        text("content_hash")
+                    ],
+                )?;
+            }
+        }
+        "expires_at" => {
+            // Con otra fecha la entrada vuelve a estar vigente o la limpieza la procesa de nuevo
+            conn.execute(
+                "UPDATE knowledge SET expires_at = ?1, archived_at = NULL WHERE id = ?2",
+                params![value.as_i64(), id],
+            )?;
+        }
+        "fields" => {
+            let text = value.as_str();
+            conn.execute("UPDATE knowledge SET fields = ?1 WHERE id = ?2", params![text, id])?;
+            let fields = text.and_then(|t| serde_json::from_str(t).ok()).unwrap_or_default();
+            index_fields(conn, id, &fields)?;
+        }
+        column if SYNC_COLUMNS.contains(&column) => {
+            let value = match value {
+                serde_json::Value::Null => Value::Null,
+                serde_json::Value::Bool(b) => Value::Integer(*b as i64),
+                serde_json::Value::Number(n) => n.as_i64().map(Value::Integer).unwrap_or(Value::Real(n.as_f64().unwrap_or_default())),
+                serde_json::Value::String(s) => Value::Text(s.clone()),
+                other => Value::Text(other.to_string()),
+            };
+            conn.execute(&format!("UPDATE knowledge SET {} = ?1 WHERE id = ?2", column), params![value, id])?;
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Valor abreviado para describir un conflicto
+fn preview(value: &serde_json::Value) -> String {
+    let text = match value {
+        serde_json::Value::String(s) => format!("«{}»", s),
+        other => other.to_string(),
+    };
+    if text.chars().count() > 80 {
+        format!("{}…", text.chars().take(80).collect::<String>())
+    } else {
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// Base nueva en un directorio temporal propio de la prueba
+    fn memory(name: &str) -> (TempDir, Memory) {
+        let dir = TempDir::new(name);
+        let memory = Memory::new(&dir.join("memory.db")).unwrap();
+        (dir, memory)
+    }
+
+    fn entry(title: &str, project: Option<&str>, tags: &[&str]) -> NewKnowledge {
+        NewKnowledge {
+            tags: tags.iter().map(|t| t.to_string()).collect(),
+            project_path: project.map(str::to_string),
+            ..NewKnowledge::new("decision", title, "contenido")
+        }
+    }
+
+    fn titles(memory: &Memory, query: &str, options: &SearchOptions) -> Vec<String> {
+        let mut titles: Vec<String> =
+            memory.search_compact(query, options).unwrap().into_iter().map(|e| e.title).collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn project_prefixes_stop_at_path_boundary() {
+        let (_dir, memory) = memory("project-prefixes");
+        memory.save_knowledge(&entry("app", Some("/work/app"), &[])).unwrap();
+        memory.save_knowledge(&entry("api", Some("/work/app/api"), &[])).unwrap();
+        memory.save_knowledge(&entry("secrets", Some("/work/app-secrets"), &[])).unwrap();
+        memory.save_knowledge(&entry("global", None, &[])).unwrap();
+
+        let mut options = SearchOptions::new(10);
+        options.allow_projects = vec!["/work/app".to_string()];
+        assert_eq!(titles(&memory, "", &options), ["api", "app"]);
+        assert_eq!(titles(&memory, "contenido", &options), ["api", "app"]);
+
+        let mut options = SearchOptions::new(10);
+        options.deny_projects = vec!["/work/app".to_string()];
+        assert_eq!(titles(&memory, "", &options), ["global", "secrets"]);
+    }
+
+    fn tags(memory: &Memory, id: i64) -> Vec<String> {
+        let mut tags = memory.get_detail(id).unwrap().unwrap().tags;
+        tags.sort();
+        tags
+    }
+
+    #[test]
+    fn rename_tag_moves_descendants_and_merges() {
+        let (_dir, memory) = memory("rename-tag");
+        let id = memory.save_knowledge(&entry("a", None, &["lang", "lang/rust", "langs", "db"])).unwrap();
+
+        assert!(memory.rename_tag(id, "lang", "language").unwrap());
+        assert_eq!(tags(&memory, id), ["db", "langs", "language", "language/rust"]);
+        let timeline = memory.get_timeline(id).unwrap();
+        assert!(timeline.iter().any(|e| e.description == "Tag 'lang' renombrado a 'language'"));
+
+        // El índice de tags sigue al renombrado
+        let mut options = SearchOptions::new(10);
+        options.tag = Some("language".to_string());
+        assert_eq!(titles(&memory, "", &options), ["a"]);
+        options.tag = Some("lang".to_string());
+        assert!(titles(&memory, "", &options).is_empty());
+
+        // Fusionar con un tag que ya tiene
+        assert!(memory.rename_tag(id, "db", "langs").unwrap());
+        assert_eq!(tags(&memory, id), ["langs", "language", "language/rust"]);
+
+        assert!(!memory.rename_tag(id, "missing", "other").unwrap());
+    }
+
+    #[test]
+    fn rename_tag_into_descendant_keeps_existing_descendant() {
+        let (_dir, memory) = memory("rename-tag-descendant");
+        let id = memory.save_knowledge(&entry("a", None, &["lang", "lang/rust", "lang/go"])).unwrap();
+
+        assert!(memory.rename_tag(id, "lang", "lang/rust").unwrap());
+        assert_eq!(tags(&memory, id), ["lang/rust", "lang/rust/go"]);
+    }
+
+    #[test]
+    fn reads_do_not_wait_for_a_write_in_progress() {
+        let (_dir, memory) = memory("reads-during-write");
+        memory.save_knowledge(&entry("a", None, &[])).unwrap();
+        memory.save_knowledge(&entry("b", None, &[])).unwrap();
+
+        // Con la conexión de escritura tomada y el índice a medio reconstruir, las
+        // búsquedas ven la última versión confirmada
+        let mut seen = Vec::new();
+        memory
+            .rebuild_index(1, |_, _| {
+                seen.push(titles(&memory, "contenido", &SearchOptions::new(10)));
+                true
+            })
+            .unwrap();
+        assert_eq!(seen, [["a", "b"], ["a", "b"]]);
+    }
+
+    #[test]
+    fn cancelled_rebuild_leaves_the_index_as_it_was() {
+        let (_dir, memory) = memory("rebuild-cancel");
+        for title in ["a", "b", "c"] {
+            memory.save_knowledge(&entry(title, None, &[])).unwrap();
+        }
+
+        let mut batches = 0;
+        let done = memory
+            .rebuild_index(1, |_, _| {
+                batches += 1;
+                batches < 2
+            })
+            .unwrap();
+        assert_eq!(done, None);
+        assert_eq!(titles(&memory, "contenido", &SearchOptions::new(10)), ["a", "b", "c"]);
+
+        assert_eq!(memory.rebuild_index(2, |_, _| true).unwrap(), Some(3));
+        assert_eq!(titles(&memory, "contenido", &SearchOptions::new(10)), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn git_data_and_file_ranges_round_trip() {
+        let (_dir, memory) = memory("git-data");
+        let file = FileRef {
+            path: "src/foo.rs".to_string(),
+            start_line: Some(10),
+            end_line: Some(20),
+            symbol: Some("fn parse".to_string()),
+            content_hash: Some("abc".to_string()),
+        };
+        let id = memory
+            .save_knowledge(&NewKnowledge {
+                git_commit: Some("0123abcd".to_string()),
+                branch: Some("main".to_string()),
+                file_paths: vec![file.clone(), FileRef { path: "README.md".to_string(), ..file.clone() }],
+                ..NewKnowledge::new("bugfix", "parse", "contenido")
+            })
+            .unwrap();
+
+        let detail = memory.get_detail(id).unwrap().unwrap();
+        assert_eq!(detail.git_commit.as_deref(), Some("0123abcd"));
+        assert_eq!(detail.branch.as_deref(), Some("main"));
+        assert_eq!(detail.file_paths.len(), 2);
+        assert_eq!(detail.file_paths[0].path, "src/foo.rs");
+        assert_eq!((detail.file_paths[0].start_line, detail.file_paths[0].end_line), (Some(10), Some(20)));
+        assert_eq!(detail.file_paths[0].symbol.as_deref(), Some("fn parse"));
+        assert_eq!(detail.file_paths[0].content_hash.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn file_filter_matches_the_file_and_what_is_below() {
+        let (_dir, memory) = memory("file-filter");
+        for (title, path) in [("foo", "src/foo.rs"), ("dir", "src/foo/mod.rs"), ("foobar", "src/foobar.rs")] {
+            memory
+                .save_knowledge(&NewKnowledge {
+                    file_paths: vec![FileRef {
+                        path: path.to_string(),
+                        start_line: None,
+                        end_line: None,
+                        symbol: None,
+                        content_hash: None,
+                    }],
+                    ..NewKnowledge::new("decision", title, "contenido")
+                })
+                .unwrap();
+        }
+        memory.save_knowledge(&entry("none", None, &[])).unwrap();
+
+        let mut options = SearchOptions::new(10);
+        options.file_path = Some("src/foo.rs".to_string());
+        assert_eq!(titles(&memory, "", &options), ["foo"]);
+        assert_eq!(titles(&memory, "contenido", &options), ["foo"]);
+
+        options.file_path = Some("src/foo/".to_string());
+        assert_eq!(titles(&memory, "", &options), ["dir"]);
+        options.file_path = Some("src".to_string());
+        assert_eq!(titles(&memory, "", &options), ["dir", "foo", "foobar"]);
+    }
+
+    #[test]
+    fn read_only_open_neither_creates_nor_migrates() {
+        let dir = TempDir::new("read-only");
+        let missing = dir.join("falta.db");
+        let Err(error) = Memory::open_read_only(&missing) else { panic!("abrió una base que no existe") };
+        assert!(error.to_string().starts_with("No existe"), "{}", error);
+        assert!(!missing.exists());
+
+        // Una base de una versión anterior se rechaza sin tocarla
+        let old = dir.join("vieja.db");
+        Connection::open(&old).unwrap().execute("CREATE TABLE knowledge (id INTEGER PRIMARY KEY)", []).unwrap();
+        let Err(error) = Memory::open_read_only(&old) else { panic!("abrió una base anterior") };
+        assert!(error.to_string().contains("esquema anterior"), "{}", error);
+        let tables: i64 = Connection::open(&old)
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(tables, 1);
+
+        // Una base actual se lee pero no se escribe
+        let path = dir.join("memory.db");
+        Memory::new(&path).unwrap().save_knowledge(&entry("a", None, &[])).unwrap();
+        let memory = Memory::open_read_only(&path).unwrap();
+        assert_eq!(memory.usage_report(10).unwrap().never_used_total, 1);
+        assert!(memory.save_knowledge(&entry("b", None, &[])).is_err());
+    }
+
+    #[test]
+    fn usage_and_feedback_move_entries_up() {
+        let (_dir, memory) = memory("ranking-usage");
+        let a = memory.save_knowledge(&entry("a", None, &[])).unwrap();
+        let b = memory.save_knowledge(&entry("b", None, &[])).unwrap();
+        let ranked = |memory: &Memory| -> Vec<String> {
+            memory.search_compact("contenido", &SearchOptions::new(10)).unwrap().into_iter().map(|e| e.title).collect()
+        };
+
+        memory.record_access(&[b, b, b], Access::Detail).unwrap();
+        assert_eq!(ranked(&memory), ["b", "a"]);
+
+        // Las valoraciones pesan más que las aperturas
+        memory.record_feedback(b, false).unwrap();
+        memory.record_feedback(b, false).unwrap();
+        memory.record_feedback(a, true).unwrap();
+        assert_eq!(ranked(&memory), ["a", "b"]);
+        assert!(memory.record_feedback(999, true).unwrap().is_none());
+    }
+
+    #[test]
+    fn usage_report_lists_the_most_and_never_used() {
+        let (_dir, memory) = memory("usage-report");
+        let searched = memory.save_knowledge(&entry("buscada", None, &[])).unwrap();
+        let opened = memory.save_knowledge(&entry("abierta", None, &[])).unwrap();
+        memory.save_knowledge(&entry("olvidada", None, &[])).unwrap();
+        memory.save_knowledge(&entry("otra olvidada", None, &[])).unwrap();
+        let expired = NewKnowledge { expires_at: Some(1), ..entry("vencida", None, &[]) };
+        memory.save_knowledge(&expired).unwrap();
+        memory.record_access(&[searched, opened], Access::Search).unwrap();
+        memory.record_access(&[opened], Access::Detail).unwrap();
+
+        let report = memory.usage_report(10).unwrap();
+        let titles = |entries: &[UsageEntry]| entries.iter().map(|e| e.title.clone()).collect::<Vec<_>>();
+        assert_eq!(titles(&report.most_used), ["abierta", "buscada"]);
+        assert_eq!(titles(&report.never_used), ["olvidada", "otra olvidada"]);
+        assert_eq!(report.never_used_total, 2);
+
+        // El límite recorta las listas, no el total
+        let report = memory.usage_report(1).unwrap();
+        assert_eq!((titles(&report.never_used), report.never_used_total), (vec!["olvidada".to_string()], 2));
+    }
+
+    #[test]
+    fn search_results_show_where_the_query_matched() {
+        let (_dir, memory) = memory("snippets");
+        let content = "Al arrancar el servicio abre muchas conexiones a la base y con carga alta el pool se agota; \
+                       la solución fue limitar los trabajadores y devolver cada conexión al terminar la consulta.";
+        let pool = NewKnowledge {
+            summary: "Resumen del incidente".to_string(),
+            tags: vec!["pool".to_string()],
+            ..NewKnowledge::new("decision", "Pool de conexiones", content)
+        };
+        memory.save_knowledge(&pool).unwrap();
+        let mut fields = Map::new();
+        fields.insert("root_cause".to_string(), "Pool agotado por una fuga de conexiones".into());
+        let bug = NewKnowledge { fields, ..NewKnowledge::new("bugfix", "Timeouts", "sin detalles") };
+        memory.save_knowledge(&bug).unwrap();
+        let search = |query: &str, options: &SearchOptions| -> Vec<(String, Option<String>, Vec<String>)> {
+            let mut results: Vec<_> = memory
+                .search_compact(query, options)
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.title, e.snippet, e.matched_columns))
+                .collect();
+            results.sort();
+            results
+        };
+
+        // Fragmento del contenido alrededor de la coincidencia, con las marcas
+        let results = search("pool", &SearchOptions::new(10));
+        let (title, snippet, columns) = &results[0];
+        assert_eq!(title, "Pool de conexiones");
+        let snippet = snippet.as_deref().unwrap();
+        assert!(snippet.contains("el **pool**") && snippet.ends_with('…'), "{}", snippet);
+        assert!(snippet.split_whitespace().count() <= 16, "{}", snippet);
+        assert_eq!(columns, &["title", "content", "tags"]);
+        assert_eq!((&results[1].1, &results[1].2), (&None, &vec!["fields".to_string()]));
+
+        // Sin coincidencias en el contenido no hay fragmento: el resumen alcanza
+        assert_eq!(search("incidente", &SearchOptions::new(10)), [("Pool de conexiones".to_string(), None, vec!["summary".to_string()])]);
+
+        // Marcas y largo configurables; con 0 palabras solo se informan las columnas
+        let mut options = SearchOptions::new(10);
+        options.snippet = SnippetOptions { start: "<b>".to_string(), end: "</b>".to_string(), ellipsis: "...".to_string(), tokens: 4 };
+        let snippet = search("agota", &options)[0].1.clone().unwrap();
+        assert!(snippet.contains("<b>agota</b>") && snippet.split_whitespace().count() <= 4, "{}", snippet);
+        options.snippet.tokens = 0;
+        assert_eq!(search("agota", &options), [("Pool de conexiones".to_string(), None, vec!["content".to_string()])]);
+
+        // En la búsqueda por campo, el fragmento sale del valor del campo
+        let mut options = SearchOptions::new(10);
+        options.field = Some("root_cause".to_string());
+        let results = search("fuga", &options);
+        assert_eq!(results[0].1.as_deref(), Some("Pool agotado por una **fuga** de conexiones"));
+        assert_eq!(results[0].2, ["fields.root_cause"]);
+    }
+
+    /// Títulos en el orden de la búsqueda
+    fn ranked(memory: &Memory, query: &str, options: &SearchOptions) -> Vec<String> {
+        memory.search_compact(query, options).unwrap().into_iter().map(|e| e.title).collect()
+    }
+
+    #[test]
+    fn column_weights_decide_which_match_counts_more() {
+        let (_dir, memory) = memory("ranking-columns");
+        memory.save_knowledge(&NewKnowledge::new("decision", "Cache de consultas", "texto sin relación")).unwrap();
+        memory.save_knowledge(&NewKnowledge::new("decision", "Otra cosa", "la cache de consultas vive en memoria")).unwrap();
+
+        let mut options = SearchOptions::new(10);
+        options.weights.title = 10.0;
+        assert_eq!(ranked(&memory, "cache", &options), ["Cache de consultas", "Otra cosa"]);
+        options.weights.title = 1.0;
+        options.weights.content = 10.0;
+        assert_eq!(ranked(&memory, "cache", &options), ["Otra cosa", "Cache de consultas"]);
+    }
+
+    #[test]
+    fn recency_and_type_boosts_reorder_equal_matches() {
+        let (_dir, memory) = memory("ranking-boosts");
+        let old = memory.save_knowledge(&NewKnowledge::new("bugfix", "a", "contenido")).unwrap();
+        memory.save_knowledge(&NewKnowledge::new("decision", "b", "contenido")).unwrap();
+        let year_ago = Utc::now().timestamp() - 365 * 86_400;
+        memory.conn.lock().unwrap().execute("UPDATE knowledge SET updated_at = ?1 WHERE id = ?2", params![year_ago, old]).unwrap();
+
+        let mut options = SearchOptions::new(10);
+        assert_eq!(ranked(&memory, "contenido", &options), ["b", "a"]);
+        options.weights.types.insert("bugfix".to_string(), 2.0);
+        assert_eq!(ranked(&memory, "contenido", &options), ["a", "b"]);
+
+        // Cada componente queda a la vista; su producto es la relevancia sin normalizar
+        options.explain = true;
+        let results = memory.search_compact("contenido", &options).unwrap();
+        let explanation = |i: usize| results[i].explanation.clone().unwrap();
+        let (a, b) = (explanation(0), explanation(1));
+        assert!(a.bm25 < 0.0);
+        assert_eq!((a.knowledge_type, b.knowledge_type, a.usage, a.feedback), (2.0, 1.0, 1.0, 1.0));
+        assert!(b.recency > a.recency && a.recency > 1.0, "{:?} {:?}", a, b);
+        let product = a.bm25 * a.usage * a.feedback * a.recency * a.knowledge_type;
+        assert!((a.raw_score - product).abs() < 1e-9 && Some(a.raw_score) == results[0].relevance_score);
+
+        // Sin `explain`, o sin consulta de texto, no hay explicación
+        options.explain = false;
+        assert!(memory.search_compact("contenido", &options).unwrap()[0].explanation.is_none());
+        options.explain = true;
+        assert!(memory.search_compact("", &options).unwrap().iter().all(|e| e.explanation.is_none()));
+    }
+
+    #[test]
+    fn ranking_weights_that_would_flip_the_order_are_rejected() {
+        assert!(RankingWeights::default().validate().is_ok());
+        let invalid = [
+            RankingWeights { content: -1.0, ..Default::default() },
+            RankingWeights { usage: 1.5, ..Default::default() },
+            RankingWeights { recency: f64::NAN, ..Default::default() },
+            RankingWeights { recency_half_life_days: 0.0, ..Default::default() },
+            RankingWeights { types: BTreeMap::from([("decision".to_string(), 0.0)]), ..Default::default() },
+        ];
+        let keys: Vec<String> = invalid
+            .iter()
+            .map(|w| w.validate().unwrap_err().split(' ').next().unwrap().to_string())
+            .collect();
+        assert_eq!(keys, ["content", "usage", "recency", "recency_half_life_days", "types.decision"]);
     }
 }
@@ -8,6 +8,7 @@ use serde::{Deserialize, Serialize};
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequestMetrics {
     pub id: i64,
@@ -18,6 +19,7 @@ pub struct RequestMetrics {
     pub success: bool,
     pub error_message: Option<String>,
     pub timestamp: i64,
+    pub session_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +33,18 @@ pub struct ToolUsageStats {
     pub last_called: Option<i64>,
 }
 
+/// Uso por cliente (`clientInfo.name` de sus sesiones)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientUsageStats {
+    pub client: String,
+    pub sessions: i64,
+    pub total_requests: i64,
+    pub error_count: i64,
+    pub tool_calls: i64,
+    pub avg_response_time_ms: f64,
+    pub last_seen: Option<i64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServerStats {
     pub total_requests: i64,
@@ -38,6 +52,8 @@ pub struct ServerStats {
     pub avg_response_time_ms: f64,
     pub uptime_seconds: i64,
     pub tool_stats: Vec<ToolUsageStats>,
+    #[serde(default)]
+    pub client_stats: Vec<ClientUsageStats>,
 }
 
 pub struct Metrics {
@@ -48,6 +64,9 @@ pub struct Metrics {
 impl Metrics {
     pub fn new(db_path: &std::path::Path) -> SqlResult<Self> {
         let conn = Connection::open(db_path)?;
+        // Cada solicitud escribe una fila: con WAL `IDE_Memory_Stats` puede leer mientras tanto
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.query_row("PRAGMA journal_mode = WAL", [], |_| Ok(()))?;
         let metrics = Metrics {
             conn: Mutex::new(conn),
             start_time: Instant::now(),
@@ -74,7 +93,31 @@ impl Metrics {
             [],
         )?;
 
+        // Sesión de cada solicitud (las anteriores a las sesiones quedan sin valor)
+        let has_session = conn
+            .prepare("SELECT 1 FROM pragma_table_info('request_metrics') WHERE name = 'session_id'")?
+            .exists([])?;
+        if !has_session {
+            conn.execute("ALTER TABLE request_metrics ADD COLUMN session_id TEXT", [])?;
+        }
+
+        // Sesiones MCP: cliente, proyecto, inicio y fin
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id TEXT PRIMARY KEY,
+                client TEXT,
+                project_path TEXT,
+                started_at INTEGER NOT NULL,
+                ended_at INTEGER
+            )",
+            [],
+        )?;
+
         // Índices para consultas rápidas
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_metrics_session ON request_metrics(session_id)",
+            [],
+        )?;
         conn.execute(
             "CREATE INDEX IF NOT EXISTS idx_metrics_timestamp ON request_metrics(timestamp DESC)",
             [],
@@ -91,8 +134,11 @@ impl Metrics {
         Ok(())
     }
 
+    /// Registra una solicitud de la sesión `session_id` (sin valor si llegó antes de `initialize`)
+    #[allow(clippy::too_many_arguments)]
     pub fn record_request(
         &self,
+        session_id: Option<&str>,
         method: &str,
         tool_name: Option<&str>,
         response_time: Duration,
@@ -105,8 +151,8 @@ impl Metrics {
 
         conn.execute(
             "INSERT INTO request_metrics 
-            (method, tool_name, response_time_ms, response_size_bytes, success, error_message, timestamp)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (method, tool_name, response_time_ms, response_size_bytes, success, error_message, timestamp, session_id)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
             params![
                 method,
                 tool_name,
@@ -114,13 +160,51 @@ impl Metrics {
                 response_size as i64,
                 if success { 1 } else { 0 },
                 error_message,
-                timestamp
+                timestamp,
+                session_id
             ],
         )?;
 
         Ok(())
     }
 
+    /// Registra el inicio de una sesión
+    pub fn start_session(&self, id: &str, client: Option<&str>, project_path: Option<&str>, started_at: i64) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR IGNORE INTO sessions (id, client, project_path, started_at) VALUES (?1, ?2, ?3, ?4)",
+            params![id, client, project_path, started_at],
+        )?;
+        Ok(())
+    }
+
+    /// Registra el fin de una sesión y su último proyecto activo
+    pub fn end_session(&self, id: &str, project_path: Option<&str>, ended_at: i64) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE sessions SET ended_at = ?1, project_path = COALESCE(?2, project_path) WHERE id = ?3",
+            params![ended_at, project_path, id],
+        )?;
+        Ok(())
+    }
+
+    /// Cambia el proyecto de una sesión (el cliente informó sus raíces)
+    pub fn update_session_project(&self, id: &str, project_path: &str) -> SqlResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("UPDATE sessions SET project_path = ?1 WHERE id = ?2", params![project_path, id])?;
+        Ok(())
+    }
+
+    /// Elimina las métricas con más de `days` días; devuelve cuántas se borraron
+    pub fn purge_older_than(&self, days: u32) -> SqlResult<usize> {
+        let conn = self.conn.lock().unwrap();
+        let cutoff = Utc::now().timestamp() - i64::from(days) * 86_400;
+        conn.execute(
+            "DELETE FROM request_metrics WHERE timestamp < ?1",
+            params![cutoff],
+        )
+    }
+
     pub fn get_tool_stats(&self, tool_name: &str) -> SqlResult<ToolUsageStats> {
         let conn = self.conn.lock().unwrap();
 
@@ -152,35 +236,20 @@ impl Metrics {
     }
 
     pub fn get_server_stats(&self) -> SqlResult<ServerStats> {
-        let conn = self.conn.lock().unwrap();
-
-        // Estadísticas generales
-        let mut stmt = conn.prepare(
-            "SELECT 
-                COUNT(*) as total_requests,
-                SUM(CASE WHEN success = 0 THEN 1 ELSE 0 END) as total_errors,
-                AVG(response_time_ms) as avg_response_time_ms
-             FROM request_metrics"
-        )?;
-
-        let (total_requests, total_errors, avg_response_time_ms) = stmt.query_row([], |row| {
-            Ok((
-                row.get::<_, i64>(0)?,
-                row.get::<_, i64>(1)?,
-                row.get::<_, f64>(2).unwrap_or(0.0),
-            ))
-        })?;
+        let (total_requests, total_errors, avg_response_time_ms, tool_names, client_stats) = {
+            let conn = self.conn.lock().unwrap();
+            let (requests, errors, avg) = Self::totals(&conn)?;
+            let mut stmt = conn.prepare(
+                "SELECT DISTINCT tool_name FROM request_metrics WHERE tool_name IS NOT NULL"
+            )?;
+            let tool_names: Vec<String> = stmt
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<_, _>>()?;
+            (requests, errors, avg, tool_names, Self::client_stats(&conn)?)
+        };
 
-        // Estadísticas por herramienta
+        // get_tool_stats toma la conexión por su cuenta
         let mut tool_stats = Vec::new();
-        let mut stmt = conn.prepare(
-            "SELECT DISTINCT tool_name FROM request_metrics WHERE tool_name IS NOT NULL"
-        )?;
-
-        let tool_names: Vec<String> = stmt
-            .query_map([], |row| Ok(row.get::<_, String>(0)?))?
-            .collect::<Result<_, _>>()?;
-
         for tool_name in tool_names {
             if let Ok(stats) = self.get_tool_stats(&tool_name) {
                 tool_stats.push(stats);
@@ -193,16 +262,65 @@ impl Metrics {
             avg_response_time_ms,
             uptime_seconds: self.start_time.elapsed().as_secs() as i64,
             tool_stats,
+            client_stats,
+        })
+    }
+
+    /// Uso por cliente, a partir de las sesiones registradas
+    fn client_stats(conn: &Connection) -> SqlResult<Vec<ClientUsageStats>> {
+        let mut stmt = conn.prepare(
+            "SELECT COALESCE(s.client, 'desconocido') AS client,
+                    COUNT(DISTINCT s.id),
+                    COUNT(r.id),
+                    COALESCE(SUM(CASE WHEN r.success = 0 THEN 1 ELSE 0 END), 0),
+                    COALESCE(SUM(CASE WHEN r.tool_name IS NOT NULL THEN 1 ELSE 0 END), 0),
+                    AVG(r.response_time_ms),
+                    MAX(COALESCE(r.timestamp, s.started_at))
+             FROM sessions s LEFT JOIN request_metrics r ON r.session_id = s.id
+             GROUP BY client
+             ORDER BY COUNT(r.id) DESC"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(ClientUsageStats {
+                client: row.get(0)?,
+                sessions: row.get(1)?,
+                total_requests: row.get(2)?,
+                error_count: row.get(3)?,
+                tool_calls: row.get(4)?,
+                avg_response_time_ms: row.get::<_, Option<f64>>(5)?.unwrap_or(0.0),
+                last_seen: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Total de solicitudes, errores y tiempo promedio de respuesta
+    fn totals(conn: &Connection) -> SqlResult<(i64, i64, f64)> {
+        let mut stmt = conn.prepare(
+            "SELECT 
+                COUNT(*) as total_requests,
+                SUM(CASE WHEN success = 0 THEN 1 ELSE 0 END) as total_errors,
+                AVG(response_time_ms) as avg_response_time_ms
+             FROM request_metrics"
+        )?;
+
+        stmt.query_row([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                row.get::<_, f64>(2).unwrap_or(0.0),
+            ))
         })
     }
 
+    #[allow(dead_code)]
     pub fn get_recent_requests(&self, limit: i32) -> SqlResult<Vec<RequestMetrics>> {
         let conn = self.conn.lock().unwrap();
         let mut results = Vec::new();
 
         let mut stmt = conn.prepare(
             "SELECT id, method, tool_name, response_time_ms, response_size_bytes, 
-                    success, error_message, timestamp
+                    success, error_message, timestamp, session_id
              FROM request_metrics
              ORDER BY timestamp DESC
              LIMIT ?1"
@@ -219,6 +337,7 @@ impl Metrics {
                 success: success_int == 1,
                 error_message: row.get(6)?,
                 timestamp: row.get(7)?,
+                session_id: row.get(8)?,
             })
         })?;
 
@@ -229,8 +348,59 @@ impl Metrics {
         Ok(results)
     }
 
+    #[allow(dead_code)]
     pub fn export_stats_json(&self) -> Result<String, Box<dyn std::error::Error>> {
         let stats = self.get_server_stats()?;
         Ok(serde_json::to_string_pretty(&stats)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn requests_count_for_the_client_of_their_session() {
+        let dir = TempDir::new("metrics-clients");
+        let metrics = Metrics::new(&dir.join("metrics.db")).unwrap();
+        let ms = Duration::from_millis(10);
+        metrics.record_request(None, "initialize", None, ms, 2, true, None).unwrap();
+        metrics.start_session("a", Some("cursor"), None, 100).unwrap();
+        metrics.start_session("b", Some("claude"), None, 100).unwrap();
+        // Dos sesiones a la vez: cada solicitud cuenta para la suya
+        metrics.record_request(Some("a"), "tools/call", Some("mem_search"), ms, 10, true, None).unwrap();
+        metrics.record_request(Some("b"), "tools/call", Some("mem_save"), ms, 10, false, Some("error")).unwrap();
+        metrics.record_request(Some("a"), "ping", None, ms, 2, true, None).unwrap();
+        metrics.start_session("c", Some("cursor"), None, 200).unwrap();
+
+        let stats = metrics.get_server_stats().unwrap();
+        assert_eq!(stats.total_requests, 4);
+        let clients: Vec<(&str, i64, i64, i64, i64)> = stats
+            .client_stats
+            .iter()
+            .map(|c| (c.client.as_str(), c.sessions, c.total_requests, c.error_count, c.tool_calls))
+            .collect();
+        assert_eq!(clients, [("cursor", 2, 2, 0, 1), ("claude", 1, 1, 1, 1)]);
+
+        let sessions: Vec<Option<String>> = metrics.get_recent_requests(10).unwrap().into_iter().map(|r| r.session_id).collect();
+        let mut sessions: Vec<Option<&str>> = sessions.iter().map(Option::as_deref).collect();
+        sessions.sort();
+        assert_eq!(sessions, [None, Some("a"), Some("a"), Some("b")]);
+    }
+
+    #[test]
+    fn session_project_can_be_set_after_it_starts() {
+        let dir = TempDir::new("metrics-project");
+        let metrics = Metrics::new(&dir.join("metrics.db")).unwrap();
+        metrics.start_session("a", Some("cursor"), None, 100).unwrap();
+        metrics.update_session_project("a", "/work/app").unwrap();
+        metrics.end_session("a", None, 200).unwrap();
+
+        let conn = metrics.conn.lock().unwrap();
+        let (project, ended): (Option<String>, Option<i64>) = conn
+            .query_row("SELECT project_path, ended_at FROM sessions WHERE id = 'a'", [], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap();
+        assert_eq!((project.as_deref(), ended), (Some("/work/app"), Some(200)));
+    }
+}
@@ -0,0 +1,355 @@
+//! Memoria del equipo versionada en el repositorio (modo espejo)
+//!
+//! Cada archivo `<proyecto>/.ide-memory/*.md` es una entrada: los metadatos van
+//! en un front matter YAML y el cuerpo Markdown es el contenido. Así las
+//! decisiones del proyecto pasan por code review como cualquier otro cambio.
+//! El servidor revisa el directorio periódicamente y refleja altas, cambios y
+//! borrados en la memoria; `mem_save` escribe las entradas nuevas del proyecto
+//! como archivos en ese mismo directorio.
+
+use crate::config::{Config, MirrorConfig};
+use crate::import::{self, ImportReport, Source};
+use crate::memory::{FileRef, Memory, NewKnowledge, DEFAULT_IMPORTANCE};
+use crate::mcp_server::ServerState;
+use crate::project::Project;
+use crate::retention;
+use crate::verify;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Evita que la revisión periódica vea un archivo recién escrito por `mem_save`
+/// antes de que su entrada exista (o al revés)
+static MIRROR_LOCK: Mutex<()> = Mutex::new(());
+
+/// Metadatos de una entrada en el front matter
+#[derive(Debug, Serialize, Deserialize)]
+struct FrontMatter {
+    /// UUID de la entrada; si falta se deriva de la ruta del archivo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(rename = "type")]
+    knowledge_type: String,
+    title: String,
+    /// Si falta, se usa el primer párrafo del cuerpo
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    summary: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    files: Vec<FileRef>,
+    /// Campos estructurados según el tipo
+    #[serde(default, skip_serializing_if = "serde_json::Map::is_empty")]
+    fields: serde_json::Map<String, serde_json::Value>,
+    #[serde(default = "default_importance", skip_serializing_if = "is_default_importance")]
+    importance: i64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pinned: bool,
+    /// Fecha de vencimiento: RFC 3339 o `AAAA-MM-DD`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_at: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git_commit: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+}
+
+fn default_importance() -> i64 {
+    DEFAULT_IMPORTANCE
+}
+
+fn is_default_importance(importance: &i64) -> bool {
+    *importance == DEFAULT_IMPORTANCE
+}
+
+/// Revisa periódicamente el directorio espejo del proyecto activo
+pub async fn watch(state: Arc<ServerState>) {
+    let config = &state.config.mirror;
+    let Some(store) = state.stores.get(Some(mirror_store(&state.config))) else {
+        tracing::warn!(store = mirror_store(&state.config), "Store del modo espejo desconocido");
+        return;
+    };
+    let memory = store.memory.clone();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.poll_seconds.max(1)));
+    loop {
+        interval.tick().await;
+        let Some(project) = state.active_project() else {
+            continue;
+        };
+        let memory = memory.clone();
+        let config = config.clone();
+        let result = tokio::task::spawn_blocking(move || scan(&memory, &project, &config)).await;
+        match result {
+            Ok(Ok(report)) if !report.is_empty() => tracing::info!(
+                added = report.added,
+                updated = report.updated,
+                removed = report.removed,
+                "Directorio espejo sincronizado"
+            ),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => tracing::warn!(error = %e, "Error revisando el directorio espejo"),
+            Err(e) => tracing::warn!(error = %e, "Error revisando el directorio espejo"),
+        }
+    }
+}
+
+/// Store donde se indexa el directorio espejo
+pub fn mirror_store(config: &Config) -> &str {
+    config.mirror.store.as_deref().unwrap_or(&config.default_store)
+}
+
+/// Refleja en la memoria el estado actual de `<project>/<dir>/*.md`
+pub fn scan(memory: &Memory, project: &Project, config: &MirrorConfig) -> anyhow::Result<ImportReport> {
+    let _guard = MIRROR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let prefix = format!("{}/", config.dir.trim_end_matches('/'));
+    let dir = project.dir.join(&config.dir);
+    let mut files: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .map(|entries| entries.filter_map(|e| e.ok().map(|e| e.path())).collect())
+        .unwrap_or_default();
+    files.retain(|path| path.extension().is_some_and(|ext| ext == "md"));
+    files.sort();
+
+    let mut texts = Vec::new();
+    let mut sources = Vec::new();
+    for path in files {
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let text = std::fs::read_to_string(&path)?;
+        sources.push(Source {
+            path: format!("{}{}", prefix, name),
+            hash: verify::hash_text(&text),
+        });
+        texts.push((path, text));
+    }
+
+    memory.remember_project_dir(&project.key, &project.dir)?;
+    import::reconcile(memory, &project.key, &prefix, &sources, |i, existing| {
+        let (path, text) = &texts[i];
+        let Some((front, body)) = parse(text) else {
+            tracing::warn!(file = %path.display(), "Archivo del directorio espejo sin front matter válido");
+            return Ok(None);
+        };
+        let source = &sources[i];
+        to_entry(memory, project, &source.path, front, body, source.hash.clone(), existing).map(Some)
+    })
+}
+
+fn to_entry(
+    memory: &Memory,
+    project: &Project,
+    source_path: &str,
+    front: FrontMatter,
+    body: &str,
+    hash: String,
+    existing: Option<i64>,
+) -> anyhow::Result<NewKnowledge> {
+    // El hash de los rangos referenciados se toma la primera vez; al editar el
+    // archivo se conserva para no ocultar que el código cambió desde entonces
+    let previous: Vec<FileRef> = match existing {
+        Some(id) => memory.get_detail(id)?.map(|e| e.file_paths).unwrap_or_default(),
+        None => Vec::new(),
+    };
+    let file_paths = front
+        .files
+        .into_iter()
+        .map(|mut file| {
+            file.content_hash = previous
+                .iter()
+                .find(|p| p.path == file.path && p.start_line == file.start_line && p.end_line == file.end_line)
+                .and_then(|p| p.content_hash.clone())
+                .or_else(|| verify::fingerprint(&project.dir, &file));
+            file
+        })
+        .collect();
+
+    let content = body.trim().to_string();
+    let summary = if front.summary.is_empty() {
+        content.split("\n\n").next().unwrap_or_default().trim().to_string()
+    } else {
+        front.summary
+    };
+    let expires_at = front.expires_at.as_deref().and_then(|text| {
+        let expiry = retention::parse_expiry(text);
+        if expiry.is_none() {
+            tracing::warn!(file = source_path, expires_at = text, "Fecha de vencimiento inválida, se ignora");
+        }
+        expiry
+    });
+    Ok(NewKnowledge {
+        knowledge_type: front.knowledge_type,
+        title: front.title,
+        content,
+        summary,
+        tags: front.tags,
+        project_path: Some(project.key.clone()),
+        git_commit: front.git_commit,
+        branch: front.branch,
+        file_paths,
+        fields: front.fields,
+        importance: front.importance.clamp(1, 5),
+        pinned: front.pinned,
+        expires_at,
+        session_id: None,
+        // Sin `id`, el UUID se deriva de la ruta para que todas las máquinas que
+        // indexan el mismo repositorio usen el mismo
+        uuid: Some(front.id.unwrap_or_else(|| uuid_for(source_path))),
+        source_path: Some(source_path.to_string()),
+        source_hash: Some(hash),
+    })
+}
+
+/// UUID estable derivado de la ruta del archivo dentro del proyecto
+fn uuid_for(source_path: &str) -> String {
+    let hash = verify::hash_text(&format!("ide-memory:{}", source_path));
+    let mut bytes = [0u8; 16];
+    for (i, byte) in bytes.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16).unwrap_or_default();
+    }
+    uuid::Builder::from_custom_bytes(bytes).into_uuid().to_string()
+}
+
+/// Separa el front matter YAML (`---` ... `---`) del cuerpo
+fn parse(text: &str) -> Option<(FrontMatter, &str)> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let rest = text.strip_prefix("---")?.trim_start_matches([' ', '\t']);
+    let rest = rest.strip_prefix("\r\n").or_else(|| rest.strip_prefix('\n'))?;
+    let end = rest.find("\n---")?;
+    let front = serde_yaml::from_str(&rest[..end]).ok()?;
+    let body = rest[end + 4..].split_once('\n').map_or("", |(_, body)| body);
+    Some((front, body))
+}
+
+/// Guarda una entrada nueva y la escribe como archivo del directorio espejo
+///
+/// El nombre se deriva del título (`<titulo>.md`, con sufijo `-2`, `-3`... si ya
+/// existe). La entrada guarda la ruta y el hash del archivo, así la revisión
+/// periódica la reconoce y no la duplica.
+pub fn save_entry(memory: &Memory, project: &Project, config: &MirrorConfig, mut entry: NewKnowledge) -> anyhow::Result<i64> {
+    let _guard = MIRROR_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = project.dir.join(&config.dir);
+    std::fs::create_dir_all(&dir)?;
+
+    let uuid = uuid::Uuid::new_v4().to_string();
+    let front = FrontMatter {
+        id: Some(uuid.clone()),
+        knowledge_type: entry.knowledge_type.clone(),
+        title: entry.title.clone(),
+        summary: entry.summary.clone(),
+        tags: entry.tags.clone(),
+        files: entry.file_paths.clone(),
+        fields: entry.fields.clone(),
+        importance: entry.importance,
+        pinned: entry.pinned,
+        expires_at: entry.expires_at.map(retention::format_expiry),
+        git_commit: entry.git_commit.clone(),
+        branch: entry.branch.clone(),
+    };
+    let text = format!("---\n{}---\n\n{}\n", serde_yaml::to_string(&front)?, entry.content.trim_end());
+
+    let slug = import::slug(&entry.title);
+    let name = (1..)
+        .map(|n| if n == 1 { format!("{}.md", slug) } else { format!("{}-{}.md", slug, n) })
+        .find(|name| !dir.join(name).exists())
+        .unwrap_or_default();
+    std::fs::write(dir.join(&name), &text)?;
+
+    entry.uuid = Some(uuid);
+    entry.source_path = Some(format!("{}/{}", config.dir.trim_end_matches('/'), name));
+    entry.source_hash = Some(verify::hash_text(&text));
+    Ok(memory.save_knowledge(&entry)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    fn project(name: &str) -> (TempDir, Project, Memory) {
+        let dir = TempDir::new(name);
+        std::fs::create_dir(dir.join("repo")).unwrap();
+        let project = Project::at(&dir.join("repo"));
+        let memory = Memory::new(&dir.join("memory.db")).unwrap();
+        (dir, project, memory)
+    }
+
+    fn write(project: &Project, name: &str, text: &str) {
+        let dir = project.dir.join(".ide-memory");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join(name), text).unwrap();
+    }
+
+    #[test]
+    fn front_matter_is_split_from_the_body() {
+        let text = "\u{feff}---\r\ntype: decision\r\ntitle: SQLite\r\ntags: [db]\r\n---\r\n\r\nUsamos SQLite.\r\n";
+        let (front, body) = parse(text).unwrap();
+        assert_eq!((front.knowledge_type.as_str(), front.title.as_str()), ("decision", "SQLite"));
+        assert_eq!(front.tags, ["db"]);
+        assert_eq!(front.importance, DEFAULT_IMPORTANCE);
+        assert_eq!(body.trim(), "Usamos SQLite.");
+
+        assert!(parse("# Sin front matter\n").is_none());
+        assert!(parse("---\ntitle: falta el tipo\n---\n").is_none());
+    }
+
+    #[test]
+    fn scan_reflects_added_changed_and_removed_files() {
+        let (_dir, project, memory) = project("mirror-scan");
+        let config = MirrorConfig::default();
+        write(&project, "sqlite.md", "---\ntype: decision\ntitle: SQLite\n---\n\nUsamos SQLite.\n\nPor ser embebida.\n");
+        write(&project, "roto.md", "sin front matter\n");
+        write(&project, "notas.txt", "no es markdown\n");
+
+        let report = scan(&memory, &project, &config).unwrap();
+        assert_eq!((report.added, report.updated, report.removed), (1, 0, 0));
+        let entry = &memory.export_entries(&Default::default()).unwrap()[0];
+        assert_eq!(entry.summary, "Usamos SQLite.");
+        assert_eq!(entry.project_path.as_deref(), Some(project.key.as_str()));
+        assert_eq!(memory.project_dir(&project.key).unwrap().as_deref(), Some(project.dir.as_path()));
+        // Sin `id`, el UUID sale de la ruta: es el mismo en todas las máquinas
+        let uuid = uuid_for(".ide-memory/sqlite.md");
+        assert!(memory.local_changes_after(0).unwrap().iter().any(|(_, op)| op.contains(&uuid)));
+        assert_ne!(uuid, uuid_for(".ide-memory/otra.md"));
+
+        assert!(scan(&memory, &project, &config).unwrap().is_empty());
+
+        write(&project, "sqlite.md", "---\ntype: decision\ntitle: SQLite en WAL\n---\n\nUsamos SQLite.\n");
+        let report = scan(&memory, &project, &config).unwrap();
+        assert_eq!((report.added, report.updated, report.removed), (0, 1, 0));
+        let id = memory.export_entries(&Default::default()).unwrap()[0].id;
+        assert_eq!(memory.get_detail(id).unwrap().unwrap().title, "SQLite en WAL");
+
+        std::fs::remove_file(project.dir.join(".ide-memory/sqlite.md")).unwrap();
+        let report = scan(&memory, &project, &config).unwrap();
+        assert_eq!((report.added, report.updated, report.removed), (0, 0, 1));
+        assert_eq!(memory.count().unwrap(), 0);
+    }
+
+    #[test]
+    fn saved_entries_are_written_as_files_with_unique_names() {
+        let (_dir, project, memory) = project("mirror-save");
+        let config = MirrorConfig::default();
+        let entry = NewKnowledge {
+            summary: "resumen".to_string(),
+            project_path: Some(project.key.clone()),
+            ..NewKnowledge::new("decision", "Capa de cache", "Redis delante de la API")
+        };
+        let first = save_entry(&memory, &project, &config, entry.clone()).unwrap();
+        let second = save_entry(&memory, &project, &config, entry).unwrap();
+        assert_ne!(first, second);
+
+        let text = std::fs::read_to_string(project.dir.join(".ide-memory/capa-de-cache.md")).unwrap();
+        let (front, body) = parse(&text).unwrap();
+        assert!(front.id.is_some());
+        assert_eq!(front.title, "Capa de cache");
+        assert_eq!(body.trim(), "Redis delante de la API");
+        assert!(project.dir.join(".ide-memory/capa-de-cache-2.md").is_file());
+
+        // La revisión periódica reconoce los archivos que escribió `mem_save`
+        let report = scan(&memory, &project, &config).unwrap();
+        assert!(report.is_empty());
+        assert_eq!(report.unchanged, 2);
+        assert_eq!(memory.count().unwrap(), 2);
+    }
+}
@@ -0,0 +1,208 @@
+//! Detección del proyecto activo
+//!
+//! El proyecto se infiere de las raíces que declara el cliente (`roots/list`) o,
+//! si no las declara, del directorio de trabajo del servidor. En ambos casos se
+//! sube hasta la raíz del repositorio git si la hay, y la ruta se normaliza para
+//! que el mismo proyecto siempre se guarde igual.
+
+use std::path::{Path, PathBuf};
+
+/// Convierte una URI `file://` en ruta local
+pub fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let rest = uri.strip_prefix("file://")?;
+    // file://host/ruta: solo se admite host vacío o localhost
+    let rest = rest.strip_prefix("localhost").unwrap_or(rest);
+    let decoded = percent_decode(rest);
+    // file:///C:/ruta -> C:/ruta
+    let bytes = decoded.as_bytes();
+    if bytes.len() >= 3 && bytes[0] == b'/' && bytes[1].is_ascii_alphabetic() && bytes[2] == b':' {
+        return Some(PathBuf::from(&decoded[1..]));
+    }
+    Some(PathBuf::from(decoded))
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = |b: u8| (b as char).to_digit(16);
+            if let (Some(hi), Some(lo)) = (hex(bytes[i + 1]), hex(bytes[i + 2])) {
+                out.push((hi * 16 + lo) as u8);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Raíz del repositorio git que contiene `path`, si la hay
+pub fn find_git_root(path: &Path) -> Option<PathBuf> {
+    path.ancestors()
+        .find(|dir| dir.join(".git").exists())
+        .map(Path::to_path_buf)
+}
+
+/// Proyecto: su raíz en esta máquina y la clave con la que se guarda
+///
+/// La clave (`project_path` de las entradas) está normalizada para que el mismo
+/// proyecto visto desde Windows o desde WSL coincida, pero no es una ruta
+/// utilizable (`/mnt/c/Work/App` queda como `c:/work/app`): todo acceso al disco
+/// usa `dir`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    pub key: String,
+    pub dir: PathBuf,
+}
+
+impl Project {
+    /// Proyecto con raíz en `dir`, con los symlinks resueltos para que la clave no
+    /// dependa del camino por el que se llegó
+    pub fn at(dir: &Path) -> Project {
+        let dir = std::fs::canonicalize(dir).map_or_else(|_| dir.to_path_buf(), strip_verbatim);
+        Project {
+            key: normalize_path(&dir.to_string_lossy()),
+            dir,
+        }
+    }
+}
+
+/// Quita el prefijo `\\?\` que agrega `canonicalize` en Windows (git no lo entiende)
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(|p| p.strip_prefix(r"\\?\")) {
+        Some(rest) if rest.as_bytes().get(1) == Some(&b':') => PathBuf::from(rest),
+        _ => path,
+    }
+}
+
+/// Proyecto al que pertenece `path`: la raíz git si está en un repositorio,
+/// si no el propio directorio
+pub fn detect_project(path: &Path) -> Project {
+    let resolved = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let root = find_git_root(&resolved).unwrap_or(resolved);
+    Project::at(&root)
+}
+
+/// ¿Es `path` igual a `prefix` o está debajo? Solo a partir de un separador:
+/// `/work/app` incluye `/work/app/api` pero no `/work/app-secrets`.
+pub fn path_under(path: &str, prefix: &str) -> bool {
+    path == prefix || path.starts_with(&format!("{}/", prefix.trim_end_matches('/')))
+}
+
+/// Normaliza una ruta de proyecto para compararla y guardarla siempre igual
+///
+/// - Separadores `/` y sin barra final
+/// - Prefijo `\\?\` de Windows eliminado
+/// - Rutas de Windows y de WSL (`C:\x`, `c:/x`, `/mnt/c/x`) unificadas como `c:/x`
+///   en minúsculas, ya que NTFS no distingue mayúsculas
+pub fn normalize_path(path: &str) -> String {
+    let p = unify_path(path);
+    if is_windows_path(&p) {
+        p.to_lowercase()
+    } else {
+        p
+    }
+}
+
+/// `normalize_path` sin pasar a minúsculas
+fn unify_path(path: &str) -> String {
+    let mut p = path.trim().replace('\\', "/");
+    if let Some(rest) = p.strip_prefix("//?/") {
+        p = rest.to_string();
+    }
+
+    // /mnt/c/... (WSL) -> c:/...
+    if let Some(rest) = p.strip_prefix("/mnt/") {
+        let mut chars = rest.chars();
+        if let (Some(drive), next) = (chars.next(), chars.next()) {
+            if drive.is_ascii_alphabetic() && matches!(next, None | Some('/')) {
+                p = format!("{}:/{}", drive, chars.as_str());
+            }
+        }
+    }
+
+    let is_windows = is_windows_path(&p);
+    while p.len() > 1 && p.ends_with('/') && !(is_windows && p.len() == 3) {
+        p.pop();
+    }
+    p
+}
+
+fn is_windows_path(p: &str) -> bool {
+    p.len() >= 2 && p.as_bytes()[0].is_ascii_alphabetic() && p.as_bytes()[1] == b':'
+}
+
+/// Ruta de un archivo tal como se guarda en una entrada: relativa a la raíz del
+/// proyecto si está dentro de él, con separadores `/` y sin `./` inicial
+///
+/// Solo la comparación con el proyecto ignora mayúsculas; la ruta guardada
+/// conserva las del archivo.
+pub fn relative_to_project(path: &str, project: Option<&Project>) -> String {
+    let p = path.trim().replace('\\', "/");
+    let p = p.trim_start_matches("./");
+    let is_absolute = p.starts_with('/') || is_windows_path(p);
+    if !is_absolute {
+        return p.trim_end_matches('/').to_string();
+    }
+    // Los symlinks se resuelven igual que en la raíz del proyecto
+    let p = std::fs::canonicalize(p)
+        .map(|resolved| strip_verbatim(resolved).to_string_lossy().into_owned())
+        .unwrap_or_else(|_| p.to_string());
+    let p = unify_path(&p);
+    if let Some(project) = project {
+        if normalize_path(&p) != project.key && path_under(&normalize_path(&p), &project.key) {
+            let depth = project.key.trim_end_matches('/').split('/').count();
+            return p.split('/').skip(depth).collect::<Vec<_>>().join("/");
+        }
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    #[test]
+    fn windows_and_wsl_paths_share_a_key() {
+        assert_eq!(normalize_path("/mnt/c/Work/App"), "c:/work/app");
+        assert_eq!(normalize_path(r"C:\Work\App\"), "c:/work/app");
+        assert_eq!(normalize_path(r"\\?\C:\Work\App"), "c:/work/app");
+        assert_eq!(normalize_path("C:/"), "c:/");
+        // Fuera de Windows las mayúsculas cuentan
+        assert_eq!(normalize_path("/home/Ana/App/"), "/home/Ana/App");
+    }
+
+    #[test]
+    fn file_paths_keep_their_case() {
+        let project = Project {
+            key: normalize_path("/mnt/c/Work/App"),
+            dir: PathBuf::from("/mnt/c/Work/App"),
+        };
+        assert_eq!(relative_to_project(r"C:\work\app\Src\Lib.rs", Some(&project)), "Src/Lib.rs");
+        assert_eq!(relative_to_project("/mnt/c/Work/App/Src/Lib.rs", Some(&project)), "Src/Lib.rs");
+        assert_eq!(relative_to_project("./Src/Lib.rs", Some(&project)), "Src/Lib.rs");
+        // Fuera del proyecto la ruta queda absoluta, con sus mayúsculas
+        assert_eq!(relative_to_project("/mnt/c/Work/App-Secrets/Key.rs", Some(&project)), "c:/Work/App-Secrets/Key.rs");
+        assert_eq!(relative_to_project("/mnt/d/Other/X.rs", None), "d:/Other/X.rs");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_resolve_to_the_same_project() {
+        let dir = TempDir::new("project-symlink");
+        std::fs::create_dir(dir.join("real")).unwrap();
+        std::fs::write(dir.join("real/lib.rs"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("real"), dir.join("link")).unwrap();
+
+        let project = Project::at(&dir.join("link"));
+        assert_eq!(project, Project::at(&dir.join("real")));
+        assert!(project.dir.is_dir());
+        let file = dir.join("link/lib.rs");
+        assert_eq!(relative_to_project(&file.to_string_lossy(), Some(&project)), "lib.rs");
+    }
+}
@@ -0,0 +1,169 @@
+//! Vencimiento de entradas y limpieza periódica
+//!
+//! Una entrada puede tener fecha de vencimiento (`expires_at`), indicada al
+//! guardarla o derivada del TTL de su tipo (`[retention.ttl_days]`). Vencida, deja
+//! de aparecer en las búsquedas; la limpieza (`gc` o la tarea periódica del
+//! servidor) después la archiva o la elimina según `retention.expired`.
+
+use crate::config::{ExpiredAction, RetentionConfig};
+use crate::mcp_server::ServerState;
+use crate::stores::Store;
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Resultado de la limpieza en un store
+#[derive(Debug, Serialize)]
+pub struct GcReport {
+    pub store: String,
+    /// IDs de las entradas archivadas
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub archived: Vec<i64>,
+    /// IDs de las entradas eliminadas
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub purged: Vec<i64>,
+}
+
+/// Archiva o elimina las entradas vencidas de cada store
+pub fn collect<'a>(stores: impl IntoIterator<Item = &'a Store>, action: ExpiredAction) -> anyhow::Result<Vec<GcReport>> {
+    let now = Utc::now().timestamp();
+    let purge = action == ExpiredAction::Purge;
+    let mut reports = Vec::new();
+    for store in stores {
+        let ids = store.memory.expire_entries(now, purge)?;
+        let (archived, purged) = if purge { (Vec::new(), ids) } else { (ids, Vec::new()) };
+        reports.push(GcReport {
+            store: store.name.clone(),
+            archived,
+            purged,
+        });
+    }
+    Ok(reports)
+}
+
+/// Limpieza periódica mientras el servidor está en marcha
+pub async fn watch(state: Arc<ServerState>) {
+    let config = &state.config.retention;
+    let mut interval = tokio::time::interval(Duration::from_secs(config.gc_interval_minutes.max(1) * 60));
+    loop {
+        interval.tick().await;
+        let stores = state.stores.clone();
+        let action = config.expired;
+        let result = tokio::task::spawn_blocking(move || collect(stores.iter(), action)).await;
+        match result {
+            Ok(Ok(reports)) => {
+                for report in reports.iter().filter(|r| !r.archived.is_empty() || !r.purged.is_empty()) {
+                    tracing::info!(
+                        store = report.store,
+                        archived = ?report.archived,
+                        purged = ?report.purged,
+                        "Entradas vencidas procesadas"
+                    );
+                }
+            }
+            Ok(Err(e)) => tracing::warn!(error = %e, "Error procesando entradas vencidas"),
+            Err(e) => tracing::warn!(error = %e, "Error procesando entradas vencidas"),
+        }
+    }
+}
+
+/// Vencimiento por defecto de una entrada nueva del tipo indicado, según su TTL
+pub fn default_expiry(config: &RetentionConfig, knowledge_type: &str) -> Option<i64> {
+    config.ttl_days.get(knowledge_type).copied().map(expiry_in_days)
+}
+
+/// Vencimiento dentro de `days` días a partir de ahora
+pub fn expiry_in_days(days: u32) -> i64 {
+    Utc::now().timestamp() + i64::from(days) * 86_400
+}
+
+/// Interpreta una fecha de vencimiento: RFC 3339 (`2026-12-31T18:00:00Z`) o
+/// solo la fecha (`2026-12-31`, a las 00:00 UTC)
+pub fn parse_expiry(text: &str) -> Option<i64> {
+    let text = text.trim();
+    DateTime::parse_from_rfc3339(text)
+        .map(|date| date.timestamp())
+        .or_else(|_| NaiveDate::parse_from_str(text, "%Y-%m-%d").map(|date| date.and_time(Default::default()).and_utc().timestamp()))
+        .ok()
+}
+
+/// Fecha de vencimiento en RFC 3339, como la acepta [`parse_expiry`]
+pub fn format_expiry(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{Memory, NewKnowledge, SearchOptions};
+    use crate::testing::TempDir;
+
+    #[test]
+    fn expiry_accepts_rfc3339_or_a_date() {
+        let midnight = parse_expiry("2026-12-31T00:00:00Z").unwrap();
+        assert_eq!(parse_expiry(" 2026-12-31 "), Some(midnight));
+        assert_eq!(parse_expiry("2026-12-31T01:00:00+01:00"), Some(midnight));
+        assert_eq!(parse_expiry(&format_expiry(midnight)), Some(midnight));
+        assert_eq!(parse_expiry("31/12/2026"), None);
+        assert_eq!(parse_expiry("mañana"), None);
+    }
+
+    #[test]
+    fn default_expiry_follows_the_type_ttl() {
+        let mut config = RetentionConfig::default();
+        config.ttl_days.insert("context".to_string(), 14);
+        let expiry = default_expiry(&config, "context").unwrap();
+        let expected = Utc::now().timestamp() + 14 * 86_400;
+        assert!((expiry - expected).abs() <= 1, "{} != {}", expiry, expected);
+        assert_eq!(default_expiry(&config, "decision"), None);
+    }
+
+    fn titles(memory: &Memory) -> Vec<String> {
+        let mut titles: Vec<String> = memory
+            .search_compact("contenido", &SearchOptions::new(10))
+            .unwrap()
+            .into_iter()
+            .map(|e| e.title)
+            .collect();
+        titles.sort();
+        titles
+    }
+
+    #[test]
+    fn expired_entries_leave_search_and_are_archived_once_or_purged() {
+        let dir = TempDir::new("retention-gc");
+        let store = Store {
+            name: "default".to_string(),
+            memory: Arc::new(Memory::new(&dir.join("memory.db")).unwrap()),
+            weight: 1.0,
+        };
+        let save = |title: &str, expires_at: Option<i64>| {
+            let entry = NewKnowledge {
+                expires_at,
+                ..NewKnowledge::new("context", title, "contenido")
+            };
+            store.memory.save_knowledge(&entry).unwrap()
+        };
+        let expired = save("vencida", Some(Utc::now().timestamp() - 60));
+        save("vigente", Some(expiry_in_days(7)));
+        save("permanente", None);
+        assert_eq!(titles(&store.memory), ["permanente", "vigente"]);
+
+        let reports = collect([&store], ExpiredAction::Archive).unwrap();
+        assert_eq!(reports[0].archived, [expired]);
+        assert!(reports[0].purged.is_empty());
+        let events: Vec<String> = store.memory.get_timeline(expired).unwrap().into_iter().map(|e| e.event_type).collect();
+        assert!(events.contains(&"archived".to_string()), "{:?}", events);
+        // Archivada sigue disponible en detalle, y no se vuelve a archivar
+        assert!(store.memory.get_detail(expired).unwrap().is_some());
+        assert!(collect([&store], ExpiredAction::Archive).unwrap()[0].archived.is_empty());
+
+        let reports = collect([&store], ExpiredAction::Purge).unwrap();
+        assert_eq!(reports[0].purged, [expired]);
+        assert!(store.memory.get_detail(expired).unwrap().is_none());
+        assert_eq!(titles(&store.memory), ["permanente", "vigente"]);
+    }
+}
@@ -0,0 +1,192 @@
+//! Esquemas JSON de los argumentos de las herramientas
+//!
+//! Los esquemas se derivan de los structs de argumentos (`schemars`) y son la única
+//! fuente de verdad: el esquema que se publica en `tools/list` es el que se usa
+//! para validar los argumentos de `tools/call` antes de deserializarlos.
+
+use jsonschema::error::ValidationErrorKind;
+use jsonschema::Validator;
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Error de validación de un campo concreto
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    /// JSON Pointer del campo dentro de `arguments` (vacío = el objeto completo)
+    pub field: String,
+    pub message: String,
+}
+
+/// Los argumentos de una herramienta no cumplen su esquema
+#[derive(Debug, thiserror::Error)]
+#[error("Argumentos inválidos para '{tool}': {}", describe(.errors))]
+pub struct InvalidArguments {
+    pub tool: String,
+    pub errors: Vec<FieldError>,
+}
+
+fn describe(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| {
+            if e.field.is_empty() {
+                e.message.clone()
+            } else {
+                format!("{}: {}", e.field, e.message)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Esquema JSON (draft-07, sin `$ref`) del struct de argumentos `T`
+///
+/// Los objetos no admiten propiedades que no declaran: un argumento mal escrito
+/// se rechaza en lugar de ignorarse.
+pub fn schema_for<T: JsonSchema>() -> Value {
+    let settings = SchemaSettings::draft07().with(|s| {
+        s.inline_subschemas = true;
+        s.option_add_null_type = false;
+        s.meta_schema = None;
+    });
+    let mut schema = serde_json::to_value(settings.into_generator().into_root_schema_for::<T>())
+        .unwrap_or_else(|_| serde_json::json!({ "type": "object" }));
+    // Título y descripción raíz vienen del struct de Rust: no aportan nada al cliente
+    if let Some(obj) = schema.as_object_mut() {
+        obj.remove("title");
+        obj.remove("description");
+    }
+    close_objects(&mut schema);
+    schema
+}
+
+/// Agrega `additionalProperties: false` a los objetos con `properties` que no lo definen
+fn close_objects(schema: &mut Value) {
+    match schema {
+        Value::Object(obj) => {
+            if obj.contains_key("properties") && !obj.contains_key("additionalProperties") {
+                obj.insert("additionalProperties".to_string(), Value::Bool(false));
+            }
+            obj.values_mut().for_each(close_objects);
+        }
+        Value::Array(items) => items.iter_mut().for_each(close_objects),
+        _ => {}
+    }
+}
+
+/// Validadores compilados de los esquemas publicados, por herramienta
+///
+/// Un esquema puede cambiar mientras el servidor corre (p. ej. `types add` agrega
+/// un tipo de conocimiento): el validador se recompila solo cuando cambia.
+#[derive(Default)]
+pub struct Validators {
+    compiled: Mutex<HashMap<String, (Value, Arc<Validator>)>>,
+}
+
+impl Validators {
+    /// Valida `arguments` contra `schema`, el esquema publicado de `tool`
+    pub fn validate(&self, tool: &str, schema: &Value, arguments: &Value) -> Result<(), InvalidArguments> {
+        let invalid = |errors| InvalidArguments {
+            tool: tool.to_string(),
+            errors,
+        };
+
+        let validator = {
+            let mut compiled = self.compiled.lock().unwrap();
+            match compiled.get(tool) {
+                Some((cached, validator)) if cached == schema => validator.clone(),
+                _ => {
+                    let validator = Arc::new(jsonschema::validator_for(schema).map_err(|e| {
+                        invalid(vec![FieldError {
+                            field: String::new(),
+                            message: format!("Esquema inválido: {}", e),
+                        }])
+                    })?);
+                    compiled.insert(tool.to_string(), (schema.clone(), validator.clone()));
+                    validator
+                }
+            }
+        };
+
+        let errors: Vec<FieldError> = validator
+            .iter_errors(arguments)
+            .flat_map(|e| {
+                // Un campo requerido faltante o desconocido se reporta en el propio
+                // campo, no en el objeto padre
+                let fields = match e.kind {
+                    ValidationErrorKind::Required { ref property } => {
+                        vec![format!("{}/{}", e.instance_path, property.as_str().unwrap_or_default())]
+                    }
+                    ValidationErrorKind::AdditionalProperties { ref unexpected } => unexpected
+                        .iter()
+                        .map(|property| format!("{}/{}", e.instance_path, property))
+                        .collect(),
+                    _ => vec![e.instance_path.to_string()],
+                };
+                let message = e.to_string();
+                fields.into_iter().map(move |field| FieldError {
+                    field,
+                    message: message.clone(),
+                })
+            })
+            .collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(invalid(errors))
+        }
+    }
+}
+
+/// Deserializa `arguments`, que el registro ya validó contra el esquema publicado
+pub fn parse_args<T: DeserializeOwned>(tool: &str, arguments: &Value) -> Result<T, InvalidArguments> {
+    // Un esquema válido debería deserializar siempre; por si acaso, no entrar en pánico
+    serde_json::from_value(arguments.clone()).map_err(|e| InvalidArguments {
+        tool: tool.to_string(),
+        errors: vec![FieldError {
+            field: String::new(),
+            message: e.to_string(),
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(serde::Deserialize, JsonSchema)]
+    #[allow(dead_code)]
+    struct Args {
+        name: String,
+        limit: Option<i64>,
+    }
+
+    #[test]
+    fn derived_schemas_reject_unknown_properties() {
+        let schema = schema_for::<Args>();
+        assert_eq!(schema["additionalProperties"], false);
+
+        let validators = Validators::default();
+        assert!(validators.validate("t", &schema, &json!({ "name": "a", "limit": 1 })).is_ok());
+        let errors = validators.validate("t", &schema, &json!({ "limit": 1, "x": 1, "y": 2 })).unwrap_err().errors;
+        let mut fields: Vec<&str> = errors.iter().map(|e| e.field.as_str()).collect();
+        fields.sort();
+        assert_eq!(fields, ["/name", "/x", "/y"]);
+    }
+
+    #[test]
+    fn validator_follows_a_changed_schema() {
+        let validators = Validators::default();
+        let strict = json!({ "type": "object", "properties": { "kind": { "enum": ["a"] } } });
+        assert!(validators.validate("t", &strict, &json!({ "kind": "b" })).is_err());
+
+        let extended = json!({ "type": "object", "properties": { "kind": { "enum": ["a", "b"] } } });
+        assert!(validators.validate("t", &extended, &json!({ "kind": "b" })).is_ok());
+    }
+}
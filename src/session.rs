@@ -0,0 +1,291 @@
+//! Diario de la sesión MCP y resumen automático
+//!
+//! El servidor anota qué herramientas se llamaron y qué entradas se guardaron,
+//! actualizaron o consultaron durante la sesión. Al terminar (fin de stdin o
+//! `mem_session_end`) el diario se convierte en una entrada de tipo `summary` que
+//! enlaza todo lo tocado, sin depender de que el agente se acuerde de guardarla.
+
+use crate::config::Config;
+use crate::memory::{NewKnowledge, SessionInfo, DEFAULT_IMPORTANCE};
+use crate::retention;
+use crate::stores::{Store, Stores};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Qué se hizo con una entrada durante la sesión
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Touch {
+    Saved,
+    Updated,
+    Consulted,
+}
+
+impl Touch {
+    /// Relación del enlace del resumen a la entrada
+    fn relation(self) -> &'static str {
+        match self {
+            Touch::Saved => "saved",
+            Touch::Updated => "updated",
+            Touch::Consulted => "consulted",
+        }
+    }
+}
+
+/// Lo registrado desde el inicio de la sesión (o desde el último resumen)
+#[derive(Debug)]
+pub struct JournalState {
+    pub started: DateTime<Utc>,
+    /// Llamadas por herramienta
+    pub calls: BTreeMap<String, usize>,
+    /// Entradas tocadas: (store, ID) con lo más significativo que se hizo con ellas
+    pub touched: BTreeMap<(String, i64), Touch>,
+}
+
+impl JournalState {
+    /// ¿Se guardó o actualizó alguna entrada? (consultar no cuenta)
+    pub fn has_changes(&self) -> bool {
+        self.touched.values().any(|touch| *touch != Touch::Consulted)
+    }
+}
+
+impl Default for JournalState {
+    fn default() -> Self {
+        JournalState {
+            started: Utc::now(),
+            calls: BTreeMap::new(),
+            touched: BTreeMap::new(),
+        }
+    }
+}
+
+/// Diario de la sesión, compartido por todas las solicitudes
+#[derive(Debug, Default)]
+pub struct Journal {
+    state: Mutex<JournalState>,
+}
+
+impl Journal {
+    /// Empieza el diario de una sesión nueva
+    pub fn start(&self) {
+        *self.state.lock().unwrap() = JournalState::default();
+    }
+
+    pub fn record_call(&self, tool: &str) {
+        *self.state.lock().unwrap().calls.entry(tool.to_string()).or_default() += 1;
+    }
+
+    /// Anota una entrada; si ya estaba, conserva lo más significativo
+    /// (guardada > actualizada > consultada)
+    pub fn touch(&self, store: &str, id: i64, touch: Touch) {
+        let mut state = self.state.lock().unwrap();
+        let current = state.touched.entry((store.to_string(), id)).or_insert(touch);
+        *current = (*current).min(touch);
+    }
+
+    /// Devuelve lo registrado y empieza un diario nuevo de la misma sesión
+    pub fn take(&self) -> JournalState {
+        std::mem::take(&mut *self.state.lock().unwrap())
+    }
+}
+
+/// Guarda el resumen de la sesión `session` en `store`
+///
+/// La sesión se registra en `store` si todavía no estaba. `note` es el resumen escrito por el agente, si lo hay. Sin nota ni entradas
+/// tocadas no hay nada que resumir y devuelve `None`.
+pub fn summarize(
+    stores: &Stores,
+    store: &Store,
+    config: &Config,
+    session: Option<&SessionInfo>,
+    journal: JournalState,
+    project: Option<String>,
+    note: Option<&str>,
+) -> anyhow::Result<Option<i64>> {
+    let note = note.map(str::trim).filter(|n| !n.is_empty());
+    if note.is_none() && journal.touched.is_empty() {
+        return Ok(None);
+    }
+
+    let client = session.and_then(|s| s.client.as_deref()).unwrap_or("desconocido");
+    let now = Utc::now();
+    let mut content = format!(
+        "Sesión de {} del {} al {}.\n",
+        client,
+        journal.started.format("%Y-%m-%d %H:%M"),
+        now.format("%Y-%m-%d %H:%M UTC")
+    );
+    if let Some(note) = note {
+        content.push_str(&format!("\n{}\n", note));
+    }
+
+    let mut counts = BTreeMap::new();
+    let mut links = Vec::new();
+    for (heading, touch) in [
+        ("Entradas guardadas", Touch::Saved),
+        ("Entradas actualizadas", Touch::Updated),
+        ("Entradas consultadas", Touch::Consulted),
+    ] {
+        let mut lines = Vec::new();
+        for (store_name, id) in journal.touched.iter().filter(|(_, t)| **t == touch).map(|(key, _)| key) {
+            // Las que ya no existen (eliminadas durante la sesión) no se enlazan
+            let Some(entry) = stores.get(Some(store_name)).map(|s| s.memory.get_detail(*id)).transpose()?.flatten() else {
+                continue;
+            };
+            lines.push(format!("- [{}] {} (ID {}, store {})", entry.knowledge_type, entry.title, id, store_name));
+            // Los enlaces son de una misma base; las de otros stores quedan solo en el texto
+            if *store_name == store.name {
+                links.push((*id, touch.relation()));
+            }
+        }
+        if !lines.is_empty() {
+            counts.insert(touch, lines.len());
+            content.push_str(&format!("\n## {}\n\n{}\n", heading, lines.join("\n")));
+        }
+    }
+    if !journal.calls.is_empty() {
+        let calls: Vec<String> = journal.calls.iter().map(|(tool, n)| format!("- {}: {}", tool, n)).collect();
+        content.push_str(&format!("\n## Herramientas usadas\n\n{}\n", calls.join("\n")));
+    }
+
+    let summary = match note {
+        Some(note) => note.split("\n\n").next().unwrap_or_default().trim().to_string(),
+        None => format!(
+            "Sesión de {}. Entradas guardadas: {}, actualizadas: {}, consultadas: {}.",
+            client,
+            counts.get(&Touch::Saved).copied().unwrap_or_default(),
+            counts.get(&Touch::Updated).copied().unwrap_or_default(),
+            counts.get(&Touch::Consulted).copied().unwrap_or_default()
+        ),
+    };
+    let importance = store
+        .memory
+        .knowledge_type("summary")?
+        .map_or(DEFAULT_IMPORTANCE, |t| t.default_importance);
+    let title = format!("Sesión de {} ({})", client, now.format("%Y-%m-%d %H:%M"));
+    let entry = NewKnowledge {
+        summary,
+        tags: vec!["session".to_string()],
+        project_path: project,
+        importance,
+        expires_at: retention::default_expiry(&config.retention, "summary"),
+        session_id: session.map(|s| s.id.clone()),
+        ..NewKnowledge::new("summary", &title, &content)
+    };
+    if let Some(session) = session {
+        store.memory.ensure_session(session)?;
+    }
+    let id = store.memory.save_knowledge(&entry)?;
+    for (target, relation) in links {
+        store.memory.link(id, target, relation)?;
+    }
+    Ok(Some(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::testing::TempDir;
+
+    fn stores(dir: &TempDir) -> Stores {
+        let mut config = Config {
+            database: dir.join("memory.db"),
+            ..Default::default()
+        };
+        let team = StoreConfig { database: Some(dir.join("team.db")), weight: 1.0 };
+        config.stores.insert("team".to_string(), team);
+        Stores::open(&config).unwrap()
+    }
+
+    fn session(id: &str) -> SessionInfo {
+        SessionInfo {
+            id: id.to_string(),
+            client: Some("cursor".to_string()),
+            project_path: Some("/work/app".to_string()),
+            started_at: 1_700_000_000,
+            ended_at: None,
+            entries: 0,
+        }
+    }
+
+    fn save(stores: &Stores, store: &str, title: &str) -> i64 {
+        let memory = &stores.get(Some(store)).unwrap().memory;
+        memory.save_knowledge(&NewKnowledge::new("decision", title, "contenido")).unwrap()
+    }
+
+    #[test]
+    fn journal_keeps_the_most_significant_touch() {
+        let journal = Journal::default();
+        journal.start();
+        journal.touch("default", 1, Touch::Consulted);
+        journal.touch("default", 2, Touch::Consulted);
+        assert!(!journal.state.lock().unwrap().has_changes());
+        journal.touch("default", 1, Touch::Saved);
+        journal.touch("default", 1, Touch::Consulted);
+        journal.touch("team", 1, Touch::Updated);
+        journal.record_call("mem_search");
+        journal.record_call("mem_search");
+
+        let state = journal.take();
+        assert!(state.has_changes());
+        let touched: Vec<(&str, i64, Touch)> = state.touched.iter().map(|((s, id), t)| (s.as_str(), *id, *t)).collect();
+        assert_eq!(touched, [("default", 1, Touch::Saved), ("default", 2, Touch::Consulted), ("team", 1, Touch::Updated)]);
+        assert_eq!(state.calls["mem_search"], 2);
+
+        // El diario siguiente empieza vacío
+        let next = journal.take();
+        assert!(next.touched.is_empty() && next.calls.is_empty());
+    }
+
+    #[test]
+    fn nothing_touched_leaves_no_summary() {
+        let dir = TempDir::new("session-empty");
+        let stores = stores(&dir);
+        let store = stores.get(None).unwrap();
+        let mut journal = JournalState::default();
+        journal.calls.insert("mem_search".to_string(), 3);
+        assert_eq!(summarize(&stores, store, &Config::default(), Some(&session("s1")), journal, None, Some("  ")).unwrap(), None);
+        assert_eq!(store.memory.count().unwrap(), 0);
+        assert!(store.memory.sessions(None, None, 10).unwrap().is_empty());
+
+        // Una nota alcanza, aunque no se haya tocado nada
+        let id = summarize(&stores, store, &Config::default(), None, JournalState::default(), None, Some("Pendiente: migrar.\n\nDetalle."));
+        let entry = store.memory.get_detail(id.unwrap().unwrap()).unwrap().unwrap();
+        assert_eq!(entry.summary, "Pendiente: migrar.");
+    }
+
+    #[test]
+    fn summary_lists_and_links_what_was_touched() {
+        let dir = TempDir::new("session-summary");
+        let stores = stores(&dir);
+        let saved = save(&stores, "default", "guardada");
+        let consulted = save(&stores, "default", "consultada");
+        let other = save(&stores, "team", "del equipo");
+        let mut journal = JournalState::default();
+        journal.touched.insert(("default".to_string(), saved), Touch::Saved);
+        journal.touched.insert(("default".to_string(), consulted), Touch::Consulted);
+        journal.touched.insert(("team".to_string(), other), Touch::Updated);
+        journal.touched.insert(("default".to_string(), 999), Touch::Updated);
+
+        let store = stores.get(None).unwrap();
+        let id = summarize(&stores, store, &Config::default(), Some(&session("s1")), journal, Some("/work/app".to_string()), None).unwrap().unwrap();
+        let entry = store.memory.get_detail(id).unwrap().unwrap();
+        assert_eq!(entry.knowledge_type, "summary");
+        assert_eq!(entry.tags, ["session"]);
+        assert_eq!(entry.project_path.as_deref(), Some("/work/app"));
+        assert_eq!(entry.session_id.as_deref(), Some("s1"));
+        assert_eq!(entry.summary, "Sesión de cursor. Entradas guardadas: 1, actualizadas: 1, consultadas: 1.");
+        assert!(entry.content.contains(&format!("- [decision] del equipo (ID {}, store team)", other)), "{}", entry.content);
+        assert!(!entry.content.contains("ID 999"));
+
+        // La sesión queda registrada solo en el store del resumen
+        let sessions = store.memory.sessions(None, None, 10).unwrap();
+        assert_eq!(sessions.iter().map(|s| (s.id.as_str(), s.entries)).collect::<Vec<_>>(), [("s1", 1)]);
+        assert!(stores.get(Some("team")).unwrap().memory.sessions(None, None, 10).unwrap().is_empty());
+
+        // Solo se enlazan las entradas del mismo store
+        let links: Vec<(i64, String)> = store.memory.links(id).unwrap().into_iter().map(|l| (l.id, l.relation)).collect();
+        assert_eq!(links, [(saved, "saved".to_string()), (consulted, "consulted".to_string())]);
+    }
+}
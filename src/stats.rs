@@ -1,9 +1,17 @@
 //! Ejecutable simple para mostrar métricas del servidor IDE Memory
 
+// Este binario solo consulta métricas y el uso de las entradas, no los registra
+#[allow(dead_code)]
 mod metrics;
+#[allow(dead_code)]
+mod memory;
+#[cfg(test)]
+#[allow(dead_code)]
+mod testing;
 
 use clap::Parser;
 use std::path::PathBuf;
+use memory::{Memory, UsageEntry};
 use metrics::Metrics;
 
 #[derive(Parser, Debug)]
@@ -13,6 +21,14 @@ struct Args {
     /// Ruta al archivo de base de datos de métricas
     #[arg(short, long, default_value = "ide_memory_metrics.db")]
     metrics_db: PathBuf,
+
+    /// Base de conocimiento: muestra además las entradas más usadas y las nunca usadas
+    #[arg(short, long)]
+    database: Option<PathBuf>,
+
+    /// Entradas por lista en el informe de uso
+    #[arg(long, default_value_t = 10)]
+    top: i64,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -78,6 +94,30 @@ fn main() -> anyhow::Result<()> {
                 println!("\n⚠️  No hay estadísticas de herramientas aún.");
             }
 
+            // Uso por cliente
+            if !stats.client_stats.is_empty() {
+                println!("\n💻 Uso por Cliente");
+                println!("   {:<20} {:>10} {:>10} {:>12} {:>10} {:>12}",
+                    "Cliente", "Sesiones", "Solicitudes", "Herramientas", "Errores", "Tiempo (ms)");
+                println!("   {}", "-".repeat(80));
+
+                for client in &stats.client_stats {
+                    println!("   {:<20} {:>10} {:>10} {:>12} {:>10} {:>12.2}",
+                        &client.client,
+                        client.sessions,
+                        client.total_requests,
+                        client.tool_calls,
+                        client.error_count,
+                        client.avg_response_time_ms
+                    );
+                }
+            }
+
+            // Uso de las entradas
+            if let Some(ref database) = args.database {
+                print_usage(database, args.top);
+            }
+
             // Información adicional
             println!("\n📁 Base de datos: {:?}", args.metrics_db);
             println!("\n💡 Para ver estadísticas en JSON, usa: IDE_Memory.exe --stats");
@@ -102,4 +142,45 @@ fn main() -> anyhow::Result<()> {
     }
 
     Ok(())
+}
+
+/// Muestra las entradas más usadas y las que nunca se usaron
+fn print_usage(database: &std::path::Path, top: i64) {
+    // Solo lectura: no crea la base si la ruta está mal ni migra una de otra versión
+    let report = match Memory::open_read_only(database).and_then(|m| Ok(m.usage_report(top)?)) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("\n❌ Error leyendo el uso de las entradas ({:?}): {}", database, e);
+            return;
+        }
+    };
+
+    let print_entries = |entries: &[UsageEntry]| {
+        println!("   {:>6} {:<12} {:<34} {:>9} {:>9} {:>6} {:>8}",
+            "ID", "Tipo", "Título", "Búsquedas", "Aperturas", "Útil", "No útil");
+        println!("   {}", "-".repeat(90));
+        for entry in entries {
+            let title: String = entry.title.chars().take(34).collect();
+            println!("   {:>6} {:<12} {:<34} {:>9} {:>9} {:>6} {:>8}",
+                entry.id,
+                entry.knowledge_type,
+                title,
+                entry.usage.search_hits,
+                entry.usage.detail_views,
+                entry.usage.useful,
+                entry.usage.not_useful
+            );
+        }
+    };
+
+    if report.most_used.is_empty() {
+        println!("\n⚠️  Ninguna entrada se usó todavía.");
+    } else {
+        println!("\n⭐ Entradas más usadas");
+        print_entries(&report.most_used);
+    }
+    if report.never_used_total > 0 {
+        println!("\n💤 Entradas nunca usadas ({} en total, las más antiguas primero)", report.never_used_total);
+        print_entries(&report.never_used);
+    }
 }
\ No newline at end of file
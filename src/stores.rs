@@ -0,0 +1,312 @@
+//! Varias bases de memoria montadas a la vez
+//!
+//! Además de la base principal (`database`, store `default`) se pueden montar
+//! otras con `[stores.<nombre>]`: por ejemplo una memoria personal privada y una
+//! compartida con el equipo. Cada store es una base SQLite independiente con su
+//! propia numeración de IDs, así que una entrada se identifica por store + ID.
+//! `mem_search` busca en todos y combina los resultados según el peso de cada uno.
+
+use crate::config::Config;
+use crate::memory::{KnowledgeEntry, KnowledgeTypeDef, Memory, SearchOptions};
+use std::sync::Arc;
+
+/// Nombre del store de la base principal (`database`)
+pub const DEFAULT_STORE: &str = "default";
+
+pub struct Store {
+    pub name: String,
+    pub memory: Arc<Memory>,
+    /// Multiplica la relevancia de sus resultados en la búsqueda combinada
+    pub weight: f64,
+}
+
+pub struct Stores {
+    stores: Vec<Store>,
+    /// Índice del store donde `mem_save` guarda si no se indica otro
+    default: usize,
+}
+
+impl Stores {
+    /// Abre la base principal y las de `[stores]`
+    pub fn open(config: &Config) -> anyhow::Result<Self> {
+        let mut stores = Vec::new();
+
+        let main = config.stores.get(DEFAULT_STORE);
+        let database = main.and_then(|s| s.database.clone()).unwrap_or_else(|| config.database.clone());
+        stores.push(Store {
+            name: DEFAULT_STORE.to_string(),
+            memory: Arc::new(Memory::new(&database)?),
+            weight: main.map_or(1.0, |s| s.weight),
+        });
+
+        for (name, store) in config.stores.iter().filter(|(name, _)| *name != DEFAULT_STORE) {
+            let database = store
+                .database
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("El store '{}' no indica 'database'", name))?;
+            let memory = Memory::new(database)
+                .map_err(|e| anyhow::anyhow!("No se pudo abrir el store '{}' ({:?}): {}", name, database, e))?;
+            stores.push(Store {
+                name: name.clone(),
+                memory: Arc::new(memory),
+                weight: store.weight,
+            });
+        }
+
+        let default = stores
+            .iter()
+            .position(|s| s.name == config.default_store)
+            .ok_or_else(|| anyhow::anyhow!("default_store desconocido: {}", config.default_store))?;
+        Ok(Stores { stores, default })
+    }
+
+    /// Store por nombre, o el store por defecto si no se indica
+    pub fn get(&self, name: Option<&str>) -> Option<&Store> {
+        match name {
+            Some(name) => self.stores.iter().find(|s| s.name == name),
+            None => self.stores.get(self.default),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Store> {
+        self.stores.iter()
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.stores.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    /// Tipos de conocimiento de todos los stores; si un nombre se repite vale la
+    /// definición del primer store que lo tiene (el principal primero)
+    pub fn knowledge_types(&self) -> rusqlite::Result<Vec<KnowledgeTypeDef>> {
+        let mut types: Vec<KnowledgeTypeDef> = Vec::new();
+        for store in &self.stores {
+            for def in store.memory.knowledge_types()? {
+                if !types.iter().any(|t| t.name == def.name) {
+                    types.push(def);
+                }
+            }
+        }
+        Ok(types)
+    }
+
+    /// Registra el fin de la sesión en los stores donde guardó algo (en los demás
+    /// no hay fila que actualizar)
+    pub fn end_session(&self, id: &str, project_path: Option<&str>, ended_at: i64) -> rusqlite::Result<()> {
+        for store in &self.stores {
+            store.memory.end_session(id, project_path, ended_at)?;
+        }
+        Ok(())
+    }
+
+    /// Cambia el proyecto de la sesión en los stores donde ya está registrada
+    pub fn update_session_project(&self, id: &str, project_path: &str) -> rusqlite::Result<()> {
+        for store in &self.stores {
+            store.memory.update_session_project(id, project_path)?;
+        }
+        Ok(())
+    }
+
+    /// Búsqueda combinada en todos los stores (o solo en `only`)
+    ///
+    /// La relevancia BM25 es negativa (más negativa = mejor), así que multiplicarla
+    /// por un peso mayor que 1 favorece a ese store. Después se normaliza a 0..1
+    /// dividiéndola por la del mejor resultado: 1 es el mejor de esta búsqueda y 0.5,
+    /// la mitad de relevante. Es una escala propia de cada búsqueda (un resultado
+    /// único siempre vale 1, así que `min_score` no lo descarta); para comparar
+    /// entre búsquedas está `raw_score` en la explicación (`explain`). La
+    /// normalización y `min_score` se aplican a todos los resultados de los stores
+    /// antes de recortar a `limit`. Sin consulta de texto no hay relevancia y los
+    /// resultados se ordenan por fecha de actualización.
+    pub fn search(
+        &self,
+        only: Option<&str>,
+        query: &str,
+        options: &SearchOptions,
+    ) -> rusqlite::Result<Vec<KnowledgeEntry>> {
+        let mut results = Vec::new();
+        for store in self.stores.iter().filter(|s| only.is_none_or(|name| s.name == name)) {
+            for mut entry in store.memory.search_compact(query, options)? {
+                entry.relevance_score = entry.relevance_score.map(|score| score * store.weight);
+                if let Some(ref mut explanation) = entry.explanation {
+                    explanation.raw_score *= store.weight;
+                    explanation.store = store.weight;
+                }
+                entry.store = Some(store.name.clone());
+                results.push(entry);
+            }
+        }
+
+        results.sort_by(|a, b| match (a.relevance_score, b.relevance_score) {
+            (Some(x), Some(y)) => x.total_cmp(&y),
+            _ => b.updated_at.cmp(&a.updated_at),
+        });
+
+        // Con los pesos validados la relevancia nunca es positiva; solo es 0 si todos
+        // los pesos de columna lo son, y entonces no hay nada que ordenar
+        let best = results.iter().filter_map(|e| e.relevance_score).fold(0.0, f64::min);
+        for entry in &mut results {
+            entry.relevance_score = entry
+                .relevance_score
+                .map(|score| if best < 0.0 { (score / best).clamp(0.0, 1.0) } else { 0.0 });
+        }
+        if let Some(min_score) = options.min_score {
+            results.retain(|e| e.relevance_score.is_none_or(|score| score >= min_score));
+        }
+        results.truncate(options.limit.max(0) as usize);
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::StoreConfig;
+    use crate::memory::NewKnowledge;
+    use crate::testing::TempDir;
+
+    fn store(dir: &TempDir, name: &str, weight: f64) -> Store {
+        Store {
+            name: name.to_string(),
+            memory: Arc::new(Memory::new(&dir.join(format!("{}.db", name))).unwrap()),
+            weight,
+        }
+    }
+
+    fn save(store: &Store, title: &str, content: &str) {
+        store.memory.save_knowledge(&NewKnowledge::new("decision", title, content)).unwrap();
+    }
+
+    fn scores(results: &[KnowledgeEntry]) -> Vec<(String, f64)> {
+        results.iter().map(|e| (e.title.clone(), e.relevance_score.unwrap())).collect()
+    }
+
+    #[test]
+    fn scores_are_relative_to_the_best_result() {
+        let dir = TempDir::new("stores-relative");
+        let main = store(&dir, "main", 1.0);
+        save(&main, "sqlite", "sqlite sqlite sqlite como base embebida");
+        save(&main, "postgres", "postgres en lugar de sqlite para el servidor central");
+        save(&main, "redis", "cache en redis");
+        let stores = Stores { stores: vec![main], default: 0 };
+
+        let results = scores(&stores.search(None, "sqlite", &SearchOptions::new(10)).unwrap());
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0], ("sqlite".to_string(), 1.0));
+        assert_eq!(results[1].0, "postgres");
+        assert!(results[1].1 > 0.0 && results[1].1 < 1.0, "{:?}", results);
+
+        let mut options = SearchOptions::new(10);
+        options.min_score = Some(results[1].1 + 0.01);
+        let filtered = scores(&stores.search(None, "sqlite", &options).unwrap());
+        assert_eq!(filtered, [("sqlite".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn lone_result_always_scores_one() {
+        let dir = TempDir::new("stores-lone");
+        let main = store(&dir, "main", 1.0);
+        save(&main, "redis", "cache en redis, mencionado de pasada");
+        let stores = Stores { stores: vec![main], default: 0 };
+
+        let mut options = SearchOptions::new(10);
+        options.min_score = Some(1.0);
+        let results = scores(&stores.search(None, "redis", &options).unwrap());
+        assert_eq!(results, [("redis".to_string(), 1.0)]);
+    }
+
+    #[test]
+    fn store_weight_scales_relevance() {
+        let dir = TempDir::new("stores-weight");
+        let team = store(&dir, "team", 1.0);
+        let personal = store(&dir, "personal", 2.0);
+        save(&team, "equipo", "convenciones de sqlite");
+        save(&personal, "personal", "convenciones de sqlite");
+        let stores = Stores { stores: vec![team, personal], default: 0 };
+
+        let results = stores.search(None, "sqlite", &SearchOptions::new(10)).unwrap();
+        assert_eq!(results[0].store.as_deref(), Some("personal"));
+        assert_eq!(results[0].relevance_score, Some(1.0));
+        assert_eq!(results[1].store.as_deref(), Some("team"));
+        assert!((results[1].relevance_score.unwrap() - 0.5).abs() < 1e-9, "{:?}", results[1].relevance_score);
+
+        let only = stores.search(Some("team"), "sqlite", &SearchOptions::new(10)).unwrap();
+        assert_eq!(only.len(), 1);
+        assert_eq!(only[0].relevance_score, Some(1.0));
+    }
+
+    #[test]
+    fn scores_are_normalized_over_all_stores_before_the_limit() {
+        let dir = TempDir::new("stores-limit");
+        let team = store(&dir, "team", 1.0);
+        let personal = store(&dir, "personal", 2.0);
+        save(&team, "equipo", "convenciones de sqlite");
+        save(&team, "equipo de pasada", "notas largas del equipo donde sqlite aparece una vez entre muchas otras palabras");
+        save(&personal, "personal", "convenciones de sqlite");
+        let stores = Stores { stores: vec![team, personal], default: 0 };
+
+        let mut options = SearchOptions::new(10);
+        options.explain = true;
+        let all = stores.search(None, "sqlite", &options).unwrap();
+        assert_eq!(all.len(), 3);
+
+        // Con límite cada resultado conserva la relevancia de la búsqueda completa
+        options.limit = 2;
+        let limited = stores.search(None, "sqlite", &options).unwrap();
+        assert_eq!(scores(&limited), scores(&all[..2]));
+
+        // min_score se aplica antes del límite: quedan los que lo cumplen, hasta `limit`
+        options.min_score = Some(all[2].relevance_score.unwrap() + 0.01);
+        options.limit = 3;
+        assert_eq!(scores(&stores.search(None, "sqlite", &options).unwrap()), scores(&all[..2]));
+
+        // raw_score no se normaliza: su cociente con el del mejor es la relevancia
+        let raw = |e: &KnowledgeEntry| e.explanation.as_ref().unwrap().raw_score;
+        for entry in &all {
+            assert!((raw(entry) / raw(&all[0]) - entry.relevance_score.unwrap()).abs() < 1e-9);
+        }
+        let alone = stores.search(Some("team"), "sqlite", &SearchOptions { explain: true, ..SearchOptions::new(10) }).unwrap();
+        assert_eq!((alone[0].relevance_score, raw(&alone[0])), (Some(1.0), raw(&all[1])));
+    }
+
+    #[test]
+    fn without_query_there_is_no_score_and_min_score_is_ignored() {
+        let dir = TempDir::new("stores-no-query");
+        let main = store(&dir, "main", 1.0);
+        save(&main, "uno", "a");
+        save(&main, "dos", "b");
+        let stores = Stores { stores: vec![main], default: 0 };
+
+        let mut options = SearchOptions::new(10);
+        options.min_score = Some(0.5);
+        let results = stores.search(None, "", &options).unwrap();
+        assert_eq!(results.len(), 2);
+        assert!(results.iter().all(|e| e.relevance_score.is_none()));
+    }
+
+    #[test]
+    fn open_mounts_the_configured_stores() {
+        let dir = TempDir::new("stores-open");
+        let mut config = Config {
+            database: dir.join("memory.db"),
+            default_store: "team".to_string(),
+            ..Default::default()
+        };
+        let team = StoreConfig { database: Some(dir.join("team.db")), weight: 2.0 };
+        config.stores.insert("team".to_string(), team);
+        let stores = Stores::open(&config).unwrap();
+        assert_eq!(stores.names(), ["default", "team"]);
+        assert_eq!(stores.get(None).unwrap().name, "team");
+        assert_eq!(stores.get(Some("team")).unwrap().weight, 2.0);
+        assert!(stores.get(Some("otro")).is_none());
+        assert!(dir.join("team.db").is_file());
+
+        config.default_store = "otro".to_string();
+        let error = Stores::open(&config).err().unwrap();
+        assert_eq!(error.to_string(), "default_store desconocido: otro");
+
+        config.stores.insert("scratch".to_string(), StoreConfig::default());
+        let error = Stores::open(&config).err().unwrap();
+        assert_eq!(error.to_string(), "El store 'scratch' no indica 'database'");
+    }
+}
@@ -0,0 +1,288 @@
+//! Sincronización entre máquinas mediante un changelog en archivos
+//!
+//! Cada base escribe sus propias operaciones (ver [`crate::memory::ChangeOp`]) en
+//! `<dir>/<nodo>.jsonl`, solo agregando líneas al final, y lee las de los demás
+//! nodos. Como ningún archivo tiene más de un escritor, el directorio puede estar
+//! en una carpeta compartida o en un repositorio git sin conflictos de merge.
+
+use crate::git;
+use crate::memory::{ChangeOp, Memory};
+use serde::Serialize;
+use std::io::Write;
+use std::path::Path;
+
+/// Resultado de una sincronización
+#[derive(Debug, Default, Serialize)]
+pub struct SyncReport {
+    /// Operaciones locales escritas en el directorio
+    pub exported: usize,
+    /// Operaciones de otros nodos aplicadas
+    pub imported: usize,
+    /// Campos con ediciones concurrentes (ver eventos `conflict` en el timeline)
+    pub conflicts: usize,
+}
+
+/// Importa las operaciones nuevas de otros nodos y exporta las locales
+///
+/// Con `use_git`, antes se hace `git pull` y después se hace commit (y push si
+/// el repositorio tiene remoto) del archivo de este nodo.
+pub fn sync(memory: &Memory, dir: &Path, use_git: bool) -> anyhow::Result<SyncReport> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow::anyhow!("No se pudo crear {}: {}", dir.display(), e))?;
+    let has_remote = use_git && !git::command(dir, &["remote"])?.is_empty();
+    if has_remote {
+        git::command(dir, &["pull", "--rebase", "--autostash"])?;
+    }
+
+    let node = memory.node_id()?;
+    // Las marcas de lo ya importado/exportado son por directorio
+    let dir_key = std::fs::canonicalize(dir)?.display().to_string();
+    let mut report = SyncReport::default();
+
+    // Importar primero: así las escrituras locales posteriores tienen reloj mayor
+    let mut ops = Vec::new();
+    let mut offsets = Vec::new();
+    let mut files: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jsonl"))
+        .collect();
+    files.sort();
+    for path in files {
+        let Some(peer) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+            continue;
+        };
+        if peer == node {
+            continue;
+        }
+        let key = format!("imported:{}:{}", dir_key, peer);
+        let skip: usize = memory.sync_state(&key)?.and_then(|v| v.parse().ok()).unwrap_or(0);
+
+        let text = std::fs::read_to_string(&path)?;
+        let mut lines: Vec<&str> = text.lines().collect();
+        // La última línea puede estar a medio escribir
+        if !text.ends_with('\n') {
+            lines.pop();
+        }
+        for (n, line) in lines.iter().enumerate().skip(skip) {
+            let op: ChangeOp = serde_json::from_str(line)
+                .map_err(|e| anyhow::anyhow!("{}:{}: operación inválida: {}", path.display(), n + 1, e))?;
+            ops.push(op);
+        }
+        offsets.push((key, lines.len()));
+    }
+
+    // En orden de versión, cada operación llega después de aquellas que vio su autor
+    ops.sort_by(|a, b| (a.clock, &a.node).cmp(&(b.clock, &b.node)));
+    for op in &ops {
+        let applied = memory.apply_change(op)?;
+        report.imported += applied.new as usize;
+        report.conflicts += applied.conflicts;
+    }
+    for (key, lines) in offsets {
+        memory.set_sync_state(&key, &lines.to_string())?;
+    }
+
+    let exported_key = format!("exported:{}", dir_key);
+    let exported_seq: i64 = memory.sync_state(&exported_key)?.and_then(|v| v.parse().ok()).unwrap_or(0);
+    let changes = memory.local_changes_after(exported_seq)?;
+    if let Some(&(last_seq, _)) = changes.last() {
+        let own_file = dir.join(format!("{}.jsonl", node));
+        let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&own_file)?;
+        for (_, data) in &changes {
+            writeln!(file, "{}", data)?;
+        }
+        file.sync_all()?;
+        memory.set_sync_state(&exported_key, &last_seq.to_string())?;
+        report.exported = changes.len();
+
+        if use_git {
+            let name = format!("{}.jsonl", node);
+            git::command(dir, &["add", "--", &name])?;
+            git::command(dir, &["commit", "-m", &format!("ide-memory: sync {}", node), "--", &name])?;
+            if has_remote {
+                git::command(dir, &["push"])?;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::NewKnowledge;
+    use crate::testing::TempDir;
+    use std::path::PathBuf;
+
+    fn entry(title: &str) -> NewKnowledge {
+        NewKnowledge {
+            summary: "resumen".to_string(),
+            ..NewKnowledge::new("decision", title, "contenido")
+        }
+    }
+
+    /// Dos bases que comparten el directorio de sincronización y una entrada ya sincronizada
+    fn nodes(name: &str) -> (TempDir, Memory, Memory, PathBuf, i64, i64) {
+        let root = TempDir::new(name);
+        let a = Memory::new(&root.join("a.db")).unwrap();
+        let b = Memory::new(&root.join("b.db")).unwrap();
+        let dir = root.join("sync");
+        let id_a = a.save_knowledge(&entry("original")).unwrap();
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        assert_eq!(b.count().unwrap(), 1);
+        let id_b = b.export_entries(&Default::default()).unwrap()[0].id;
+        (root, a, b, dir, id_a, id_b)
+    }
+
+    fn title(memory: &Memory, id: i64) -> String {
+        memory.get_detail(id).unwrap().unwrap().title
+    }
+
+    fn conflicts(memory: &Memory, id: i64) -> Vec<String> {
+        memory
+            .get_timeline(id)
+            .unwrap()
+            .into_iter()
+            .filter(|event| event.event_type == "conflict")
+            .map(|event| event.description)
+            .collect()
+    }
+
+    #[test]
+    fn sequential_edits_apply_without_conflict() {
+        let (_root, a, b, dir, id_a, id_b) = nodes("sync-sequential");
+        b.update_knowledge(id_b, &entry("de b"), "editada").unwrap();
+        sync(&b, &dir, false).unwrap();
+        let report = sync(&a, &dir, false).unwrap();
+        assert_eq!(report.conflicts, 0);
+        assert_eq!(title(&a, id_a), "de b");
+
+        // La respuesta de A parte de la versión de B: tampoco es concurrente
+        a.update_knowledge(id_a, &entry("de a"), "editada").unwrap();
+        sync(&a, &dir, false).unwrap();
+        let report = sync(&b, &dir, false).unwrap();
+        assert_eq!(report.conflicts, 0);
+        assert_eq!(title(&b, id_b), "de a");
+        assert!(conflicts(&b, id_b).is_empty());
+    }
+
+    #[test]
+    fn concurrent_edits_converge_and_record_conflict() {
+        let (_root, a, b, dir, id_a, id_b) = nodes("sync-concurrent");
+        a.update_knowledge(id_a, &entry("de a"), "editada").unwrap();
+        b.update_knowledge(id_b, &entry("de b"), "editada").unwrap();
+        sync(&a, &dir, false).unwrap();
+        let report_b = sync(&b, &dir, false).unwrap();
+        let report_a = sync(&a, &dir, false).unwrap();
+
+        // Gana la misma escritura en las dos bases, sin importar el orden de llegada
+        assert_eq!(title(&a, id_a), title(&b, id_b));
+        assert!(["de a", "de b"].contains(&title(&a, id_a).as_str()));
+        assert_eq!(report_a.conflicts, 1);
+        assert_eq!(report_b.conflicts, 1);
+        let description = &conflicts(&b, id_b)[0];
+        assert!(description.contains("'title'"), "{}", description);
+        assert!(description.contains("de a") && description.contains("de b"), "{}", description);
+    }
+
+    #[test]
+    fn repeated_sync_is_idempotent() {
+        let (_root, a, b, dir, _, id_b) = nodes("sync-idempotent");
+        assert_eq!(sync(&a, &dir, false).unwrap().imported, 0);
+        assert_eq!(sync(&b, &dir, false).unwrap().imported, 0);
+        assert_eq!(title(&b, id_b), "original");
+    }
+
+    fn exists(memory: &Memory, id: i64) -> bool {
+        memory.get_detail(id).unwrap().is_some()
+    }
+
+    #[test]
+    fn delete_survives_a_concurrent_edit() {
+        let (_root, a, b, dir, id_a, id_b) = nodes("sync-delete");
+        b.update_knowledge(id_b, &entry("de b"), "editada").unwrap();
+        sync(&b, &dir, false).unwrap();
+        a.delete_knowledge(id_a).unwrap();
+
+        // A descarta la edición de B y B aplica el borrado de A
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        sync(&a, &dir, false).unwrap();
+        assert!(!exists(&a, id_a));
+        assert!(!exists(&b, id_b));
+        assert_eq!((a.count().unwrap(), b.count().unwrap()), (0, 0));
+    }
+
+    #[test]
+    fn purged_entries_are_deleted_everywhere() {
+        let (_root, a, b, dir, _, id_b) = nodes("sync-purge");
+        let expired = NewKnowledge {
+            expires_at: Some(1),
+            ..entry("vencida")
+        };
+        a.save_knowledge(&expired).unwrap();
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        assert_eq!(b.count().unwrap(), 2);
+
+        assert_eq!(a.expire_entries(2, true).unwrap().len(), 1);
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        assert_eq!(b.count().unwrap(), 1);
+        assert!(exists(&b, id_b));
+    }
+
+    #[test]
+    fn saving_a_deleted_entry_again_revives_it() {
+        let (_root, a, b, dir, _, _) = nodes("sync-revive");
+        let mirrored = NewKnowledge {
+            uuid: Some("5b0c3a52-7f0e-4c1a-9d53-0b7c2f6a1e11".to_string()),
+            ..entry("espejo")
+        };
+        let id = a.save_knowledge(&mirrored).unwrap();
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        a.delete_knowledge(id).unwrap();
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        assert_eq!(b.count().unwrap(), 1);
+
+        // Como al volver a agregar el archivo del directorio espejo: mismo UUID
+        a.save_knowledge(&mirrored).unwrap();
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        assert_eq!(b.count().unwrap(), 2);
+        let titles: Vec<String> = b.export_entries(&Default::default()).unwrap().into_iter().map(|e| e.title).collect();
+        assert!(titles.contains(&"espejo".to_string()), "{:?}", titles);
+    }
+
+    #[test]
+    fn links_follow_their_entries() {
+        let (_root, a, b, dir, id_a, id_b) = nodes("sync-links");
+        let summary = a.save_knowledge(&entry("resumen")).unwrap();
+        assert!(a.link(summary, id_a, "updated").unwrap());
+        assert!(!a.link(summary, id_a, "updated").unwrap());
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+
+        let summary_b = b
+            .export_entries(&Default::default())
+            .unwrap()
+            .into_iter()
+            .find(|e| e.title == "resumen")
+            .unwrap()
+            .id;
+        let links = b.links(summary_b).unwrap();
+        assert_eq!(links.len(), 1);
+        assert_eq!((links[0].id, links[0].relation.as_str()), (id_b, "updated"));
+
+        // Al borrar la entrada enlazada el enlace desaparece en las dos bases
+        a.delete_knowledge(id_a).unwrap();
+        sync(&a, &dir, false).unwrap();
+        sync(&b, &dir, false).unwrap();
+        assert!(a.links(summary).unwrap().is_empty());
+        assert!(b.links(summary_b).unwrap().is_empty());
+    }
+}
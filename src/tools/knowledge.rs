@@ -0,0 +1,851 @@
+//! Herramientas básicas de conocimiento (Progressive Disclosure y mantenimiento)
+
+use super::{blocking, Tool, ToolAnnotations, ToolContext};
+use crate::git;
+use crate::mirror;
+use crate::memory::{Access, FieldKind, FileRef, KnowledgeEntry, KnowledgeTypeDef, Memory, NewKnowledge, SearchOptions};
+use crate::project::relative_to_project;
+use crate::retention;
+use crate::schema::{parse_args, schema_for, FieldError, InvalidArguments};
+use crate::session::Touch;
+use crate::stores::Stores;
+use crate::verify;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Argumentos de `mem_search`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SearchArgs {
+    /// Consulta de búsqueda (obligatoria salvo que se indique `file_path`, `field`, `tag` o `session_id`)
+    query: Option<String>,
+    /// Número máximo de resultados (default: `search.default_limit` de la configuración, 5 si no se cambió)
+    #[schemars(range(min = 1))]
+    limit: Option<i32>,
+    /// Proyecto en el que buscar (default: el proyecto activo del IDE). Siempre se incluyen las entradas sin proyecto.
+    project_path: Option<String>,
+    /// Buscar en todos los proyectos, ignorando el proyecto activo
+    #[serde(default)]
+    all_projects: bool,
+    /// Solo entradas que referencian este archivo o algún archivo bajo este directorio (relativo a la raíz del proyecto). Sin `query`, devuelve todas, más recientes primero.
+    file_path: Option<String>,
+    /// Buscar `query` solo en este campo estructurado (p. ej. `root_cause`). Sin `query`, devuelve las entradas que lo tienen.
+    field: Option<String>,
+    /// Solo entradas con este tag o uno de sus descendientes (`lang` incluye `lang/rust`). Sin `query`, devuelve todas, más recientes primero.
+    tag: Option<String>,
+    /// Solo entradas guardadas en esta sesión (ID de `mem_sessions`). Sin `query`, devuelve todas, más recientes primero.
+    session_id: Option<String>,
+    /// Buscar solo en este store (default: en todos)
+    store: Option<String>,
+    /// Incluir en cada resultado los componentes de su relevancia (`explanation`)
+    #[serde(default)]
+    explain: bool,
+    /// Descartar los resultados con `relevance_score` menor (p. ej. 0.3 deja los que tienen al menos un 30% de la relevancia del mejor). Solo con `query`.
+    #[schemars(range(min = 0.0, max = 1.0))]
+    min_score: Option<f64>,
+}
+
+pub struct SearchTool;
+
+#[async_trait]
+impl Tool for SearchTool {
+    fn name(&self) -> &'static str {
+        "mem_search"
+    }
+
+    fn description(&self) -> String {
+        "Busca conocimiento relevante usando búsqueda compacta (Progressive Disclosure Capa 1). \
+         relevance_score va de 0 a 1 respecto del mejor resultado de esta búsqueda (1 = el mejor, 0.5 = la mitad de relevante); \
+         es una escala de cada búsqueda (un resultado único siempre vale 1.0): para comparar entre búsquedas use explain y raw_score. \
+         Con min_score se descartan los resultados menos relevantes que el mejor (nunca el mejor mismo)."
+            .to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<SearchArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::read_only()
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: SearchArgs = parse_args(self.name(), &arguments)?;
+        if args.query.is_none()
+            && args.file_path.is_none()
+            && args.field.is_none()
+            && args.tag.is_none()
+            && args.session_id.is_none()
+        {
+            return Err(InvalidArguments {
+                tool: self.name().to_string(),
+                errors: vec![FieldError {
+                    field: "/query".to_string(),
+                    message: "Se requiere \"query\", \"file_path\", \"field\", \"tag\" o \"session_id\"".to_string(),
+                }],
+            }
+            .into());
+        }
+        if args.store.is_some() {
+            ctx.store(self.name(), args.store.as_deref())?;
+        }
+        let stores = ctx.stores.clone();
+        let search = &ctx.config.search;
+        let project = if args.all_projects {
+            None
+        } else {
+            ctx.project(args.project_path.as_deref()).map(|project| project.key)
+        };
+        let options = SearchOptions {
+            weights: search.weights.clone(),
+            snippet: search.snippet.clone(),
+            explain: args.explain,
+            min_score: args.min_score,
+            project,
+            allow_projects: ctx.access.allow_projects.clone(),
+            deny_projects: ctx.access.deny_projects.clone(),
+            file_path: args
+                .file_path
+                .as_deref()
+                .map(|path| relative_to_project(path, ctx.project.as_ref())),
+            field: args.field,
+            tag: args.tag,
+            session: args.session_id,
+            ..SearchOptions::new(args.limit.unwrap_or(search.default_limit))
+        };
+        let query = args.query.unwrap_or_default();
+        // Un cliente de solo lectura no escribe nada, tampoco los contadores de uso
+        let count_access = !ctx.access.read_only;
+        blocking(move || {
+            let results = stores.search(args.store.as_deref(), &query, &options)?;
+            for store in stores.iter().filter(|_| count_access) {
+                let ids: Vec<i64> = results
+                    .iter()
+                    .filter(|e| e.store.as_deref() == Some(store.name.as_str()))
+                    .map(|e| e.id)
+                    .collect();
+                if !ids.is_empty() {
+                    store.memory.record_access(&ids, Access::Search)?;
+                }
+            }
+            Ok(serde_json::to_value(results)?)
+        })
+        .await
+    }
+}
+
+/// Argumentos de `mem_save`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SaveArgs {
+    /// Tipo de conocimiento
+    knowledge_type: String,
+    /// Título de la entrada
+    title: String,
+    /// Contenido completo
+    content: String,
+    /// Resumen compacto (~100 tokens)
+    summary: String,
+    /// Tags para categorización
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Ruta del proyecto (default: el proyecto activo del IDE, detectado de sus raíces o del repositorio git)
+    project_path: Option<String>,
+    /// Commit al que se refiere la entrada (default: HEAD del repositorio del proyecto)
+    git_commit: Option<String>,
+    /// Rama (default: la rama actual, si no se indica `git_commit`)
+    branch: Option<String>,
+    /// Archivos a los que se refiere la entrada; rutas relativas a la raíz del proyecto
+    #[serde(default)]
+    file_paths: Vec<FileRef>,
+    /// Campos estructurados del tipo: texto o lista de textos según el campo
+    #[serde(default)]
+    fields: BTreeMap<String, Value>,
+    /// Importancia de 1 (baja) a 5 (crítica) (default: la del tipo, 3 si no se cambió)
+    #[schemars(range(min = 1, max = 5))]
+    importance: Option<i64>,
+    /// Fijar la entrada: se incluye siempre al exportar reglas (`export-rules`)
+    #[serde(default)]
+    pinned: bool,
+    /// Fecha de vencimiento, RFC 3339 (`2026-12-31T18:00:00Z`) o `AAAA-MM-DD`; vencida, la entrada deja de aparecer en las búsquedas (default: según el TTL del tipo en `[retention.ttl_days]`, sin vencimiento si no tiene)
+    expires_at: Option<String>,
+    /// Días de vigencia desde ahora, en lugar de `expires_at`
+    #[schemars(range(min = 1))]
+    ttl_days: Option<u32>,
+    /// Store donde guardar (default: `default_store` de la configuración)
+    store: Option<String>,
+}
+
+pub struct SaveTool;
+
+#[async_trait]
+impl Tool for SaveTool {
+    fn name(&self) -> &'static str {
+        "mem_save"
+    }
+
+    fn description(&self) -> String {
+        "Guarda una entrada de conocimiento en la memoria persistente".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<SaveArgs>()
+    }
+
+    fn list_schema(&self, stores: &Stores) -> Value {
+        let mut schema = self.input_schema();
+        // Los tipos válidos son los registrados en las bases, no una lista fija
+        match stores.knowledge_types() {
+            Ok(types) => {
+                let description = types
+                    .iter()
+                    .map(|t| format!("{}: {}", t.name, t.description))
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                schema["properties"]["knowledge_type"] = serde_json::json!({
+                    "type": "string",
+                    "enum": types.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+                    "description": format!("Tipo de conocimiento. {}", description),
+                });
+                let fields = types
+                    .iter()
+                    .filter(|t| !t.fields.is_empty())
+                    .map(|t| {
+                        let names: Vec<String> = t
+                            .fields
+                            .iter()
+                            .map(|f| match f.kind {
+                                FieldKind::Text => format!("{} ({})", f.name, f.description),
+                                FieldKind::List => format!("{} (lista: {})", f.name, f.description),
+                            })
+                            .collect();
+                        format!("{}: {}", t.name, names.join(", "))
+                    })
+                    .collect::<Vec<_>>()
+                    .join("; ");
+                schema["properties"]["fields"]["description"] =
+                    Value::String(format!("Campos estructurados según el tipo. {}", fields));
+            }
+            Err(e) => tracing::warn!(error = %e, "No se pudieron leer los tipos de conocimiento"),
+        }
+        schema
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, false)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: SaveArgs = parse_args(self.name(), &arguments)?;
+        check_line_ranges(self.name(), &args.file_paths)?;
+        let project = ctx.project(args.project_path.as_deref());
+        ctx.access.check_project(project.as_ref().map(|project| project.key.as_str()))?;
+        let store = ctx.store(self.name(), args.store.as_deref())?;
+        let (memory, store_name) = (store.memory.clone(), store.name.clone());
+        // En modo espejo las entradas del proyecto también se escriben como archivos
+        let config = ctx.config.clone();
+        let mirrored = config.mirror.enabled
+            && config.mirror.write_back
+            && store_name == mirror::mirror_store(&config);
+        let tool = self.name();
+        let journal = ctx.journal.clone();
+        let session = ctx.session.clone();
+        blocking(move || {
+            let knowledge_type = memory.knowledge_type(&args.knowledge_type)?.ok_or_else(|| {
+                let known: Vec<String> = memory
+                    .knowledge_types()
+                    .map(|types| types.into_iter().map(|t| t.name).collect())
+                    .unwrap_or_default();
+                InvalidArguments {
+                    tool: tool.to_string(),
+                    errors: vec![FieldError {
+                        field: "/knowledge_type".to_string(),
+                        message: format!("Tipo desconocido '{}' (tipos: {})", args.knowledge_type, known.join(", ")),
+                    }],
+                }
+            })?;
+            let fields = check_fields(tool, &knowledge_type, args.fields)?;
+            let expires_at = match (args.expires_at.as_deref(), args.ttl_days) {
+                (Some(_), Some(_)) => {
+                    return Err(InvalidArguments {
+                        tool: tool.to_string(),
+                        errors: vec![FieldError {
+                            field: "/ttl_days".to_string(),
+                            message: "Indique \"expires_at\" o \"ttl_days\", no ambos".to_string(),
+                        }],
+                    }
+                    .into())
+                }
+                (Some(text), None) => Some(retention::parse_expiry(text).ok_or_else(|| InvalidArguments {
+                    tool: tool.to_string(),
+                    errors: vec![FieldError {
+                        field: "/expires_at".to_string(),
+                        message: format!("Fecha inválida '{}' (use RFC 3339 o AAAA-MM-DD)", text),
+                    }],
+                })?),
+                (None, Some(days)) => Some(retention::expiry_in_days(days)),
+                (None, None) => retention::default_expiry(&config.retention, &args.knowledge_type),
+            };
+            // El hash de cada rango permite detectar después si el código cambió
+            let file_paths = args
+                .file_paths
+                .into_iter()
+                .map(|file| {
+                    let mut file = FileRef {
+                        path: relative_to_project(&file.path, project.as_ref()),
+                        ..file
+                    };
+                    file.content_hash = project.as_ref().and_then(|project| verify::fingerprint(&project.dir, &file));
+                    file
+                })
+                .collect();
+            let (git_commit, branch) = match (args.git_commit, args.branch) {
+                (None, None) => match project.as_ref().and_then(|project| git::head(&project.dir)) {
+                    Some(head) => (Some(head.commit), head.branch),
+                    None => (None, None),
+                },
+                given => given,
+            };
+            let entry = NewKnowledge {
+                knowledge_type: args.knowledge_type,
+                title: args.title,
+                content: args.content,
+                summary: args.summary,
+                tags: args.tags,
+                project_path: project.as_ref().map(|project| project.key.clone()),
+                git_commit,
+                branch,
+                file_paths,
+                fields,
+                importance: args.importance.unwrap_or(knowledge_type.default_importance),
+                pinned: args.pinned,
+                expires_at,
+                session_id: session.as_ref().map(|session| session.id.clone()),
+                uuid: None,
+                source_path: None,
+                source_hash: None,
+            };
+            check_required_fields(tool, &knowledge_type, &entry)?;
+            if let Some(project) = &project {
+                memory.remember_project_dir(&project.key, &project.dir)?;
+            }
+            if let Some(session) = &session {
+                memory.ensure_session(session)?;
+            }
+            let id = match project.as_ref().filter(|_| mirrored) {
+                Some(project) => mirror::save_entry(&memory, project, &config.mirror, entry)?,
+                None => memory.save_knowledge(&entry)?,
+            };
+            journal.touch(&store_name, id, Touch::Saved);
+
+            Ok(serde_json::json!({
+                "id": id,
+                "store": store_name,
+                "success": true
+            }))
+        })
+        .await
+    }
+}
+
+/// Campos que el tipo de la entrada exige, ya completados los defaults
+fn check_required_fields(tool: &str, knowledge_type: &KnowledgeTypeDef, entry: &NewKnowledge) -> Result<(), InvalidArguments> {
+    let errors: Vec<FieldError> = knowledge_type
+        .required_fields
+        .iter()
+        .filter(|field| match field.as_str() {
+            "tags" => entry.tags.is_empty(),
+            "file_paths" => entry.file_paths.is_empty(),
+            "git_commit" => entry.git_commit.is_none(),
+            "branch" => entry.branch.is_none(),
+            "project_path" => entry.project_path.is_none(),
+            field => !entry.fields.contains_key(field),
+        })
+        .map(|field| FieldError {
+            field: format!("/{}", field),
+            message: format!("Obligatorio para el tipo '{}'", knowledge_type.name),
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidArguments {
+            tool: tool.to_string(),
+            errors,
+        })
+    }
+}
+
+/// Valida los campos estructurados contra el tipo; los vacíos se descartan
+fn check_fields(
+    tool: &str,
+    knowledge_type: &KnowledgeTypeDef,
+    fields: BTreeMap<String, Value>,
+) -> Result<Map<String, Value>, InvalidArguments> {
+    let mut valid = Map::new();
+    let mut errors = Vec::new();
+    for (name, value) in fields {
+        let Some(def) = knowledge_type.fields.iter().find(|f| f.name == name) else {
+            let known: Vec<&str> = knowledge_type.fields.iter().map(|f| f.name.as_str()).collect();
+            errors.push(FieldError {
+                field: format!("/fields/{}", name),
+                message: format!(
+                    "Campo desconocido para el tipo '{}' (campos: {})",
+                    knowledge_type.name,
+                    if known.is_empty() { "ninguno".to_string() } else { known.join(", ") }
+                ),
+            });
+            continue;
+        };
+        let ok = match (def.kind, &value) {
+            (FieldKind::Text, Value::String(_)) => true,
+            (FieldKind::List, Value::Array(items)) => items.iter().all(Value::is_string),
+            _ => false,
+        };
+        if !ok {
+            errors.push(FieldError {
+                field: format!("/fields/{}", name),
+                message: match def.kind {
+                    FieldKind::Text => "Debe ser un texto".to_string(),
+                    FieldKind::List => "Debe ser una lista de textos".to_string(),
+                },
+            });
+            continue;
+        }
+        let empty = match &value {
+            Value::String(text) => text.trim().is_empty(),
+            Value::Array(items) => items.is_empty(),
+            _ => false,
+        };
+        if !empty {
+            valid.insert(name, value);
+        }
+    }
+    if errors.is_empty() {
+        Ok(valid)
+    } else {
+        Err(InvalidArguments {
+            tool: tool.to_string(),
+            errors,
+        })
+    }
+}
+
+/// Campos estructurados de una entrada en el orden del tipo, con su descripción
+///
+/// Los campos que el tipo ya no define (o de un tipo desconocido) van al final.
+fn render_fields(knowledge_type: Option<&KnowledgeTypeDef>, fields: &Map<String, Value>) -> Value {
+    let defined = knowledge_type.map_or(&[][..], |t| &t.fields[..]);
+    let mut rendered: Vec<Value> = defined
+        .iter()
+        .filter_map(|def| {
+            fields.get(&def.name).map(|value| {
+                serde_json::json!({ "name": def.name, "description": def.description, "value": value })
+            })
+        })
+        .collect();
+    rendered.extend(
+        fields
+            .iter()
+            .filter(|(name, _)| !defined.iter().any(|def| &def.name == *name))
+            .map(|(name, value)| serde_json::json!({ "name": name, "value": value })),
+    );
+    Value::Array(rendered)
+}
+
+/// Los rangos de líneas deben tener `start_line` y no terminar antes de empezar
+fn check_line_ranges(tool: &str, files: &[FileRef]) -> Result<(), InvalidArguments> {
+    let errors: Vec<FieldError> = files
+        .iter()
+        .enumerate()
+        .filter_map(|(i, file)| match (file.start_line, file.end_line) {
+            (None, Some(_)) => Some(FieldError {
+                field: format!("/file_paths/{}/start_line", i),
+                message: "\"end_line\" requiere \"start_line\"".to_string(),
+            }),
+            (Some(start), Some(end)) if end < start => Some(FieldError {
+                field: format!("/file_paths/{}/end_line", i),
+                message: format!("{} es menor que start_line ({})", end, start),
+            }),
+            _ => None,
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(InvalidArguments {
+            tool: tool.to_string(),
+            errors,
+        })
+    }
+}
+
+/// Argumentos de `mem_get_detail` y `mem_timeline`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct IdArgs {
+    /// ID de la entrada de conocimiento
+    id: i64,
+    /// Store de la entrada (default: el store por defecto)
+    store: Option<String>,
+}
+
+pub struct GetDetailTool;
+
+#[async_trait]
+impl Tool for GetDetailTool {
+    fn name(&self) -> &'static str {
+        "mem_get_detail"
+    }
+
+    fn description(&self) -> String {
+        "Obtiene el detalle completo de una entrada (Progressive Disclosure Capa 3)".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<IdArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::read_only()
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: IdArgs = parse_args(self.name(), &arguments)?;
+        let store = ctx.store(self.name(), args.store.as_deref())?;
+        let (memory, store_name) = (store.memory.clone(), store.name.clone());
+        let access = ctx.access.clone();
+        let journal = ctx.journal.clone();
+        blocking(move || match memory.get_detail(args.id)? {
+            // Una entrada de un proyecto denegado se trata como inexistente
+            Some(entry) if access.project_allowed(entry.project_path.as_deref()) => {
+                journal.touch(&store_name, entry.id, Touch::Consulted);
+                if !access.read_only {
+                    memory.record_access(&[entry.id], Access::Detail)?;
+                }
+                let knowledge_type = memory.knowledge_type(&entry.knowledge_type)?;
+                let fields = render_fields(knowledge_type.as_ref(), &entry.fields);
+                let mut detail = serde_json::to_value(KnowledgeEntry {
+                    store: Some(store_name),
+                    ..entry
+                })?;
+                if fields.as_array().is_some_and(|f| !f.is_empty()) {
+                    detail["fields"] = fields;
+                }
+                let mut links = memory.links(entry.id)?;
+                links.retain(|link| access.project_allowed(link.project_path.as_deref()));
+                if !links.is_empty() {
+                    detail["links"] = serde_json::to_value(links)?;
+                }
+                Ok(detail)
+            }
+            _ => Err(anyhow::anyhow!("Entrada no encontrada: {}", args.id)),
+        })
+        .await
+    }
+}
+
+pub struct TimelineTool;
+
+#[async_trait]
+impl Tool for TimelineTool {
+    fn name(&self) -> &'static str {
+        "mem_timeline"
+    }
+
+    fn description(&self) -> String {
+        "Obtiene el timeline de una entrada (Progressive Disclosure Capa 2)".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<IdArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::read_only()
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: IdArgs = parse_args(self.name(), &arguments)?;
+        let store = ctx.store(self.name(), args.store.as_deref())?;
+        let (memory, store_name) = (store.memory.clone(), store.name.clone());
+        let access = ctx.access.clone();
+        let journal = ctx.journal.clone();
+        blocking(move || {
+            match memory.get_detail(args.id)? {
+                Some(entry) if access.project_allowed(entry.project_path.as_deref()) => {}
+                _ => anyhow::bail!("Entrada no encontrada: {}", args.id),
+            }
+            journal.touch(&store_name, args.id, Touch::Consulted);
+            let timeline = memory.get_timeline(args.id)?;
+            Ok(serde_json::to_value(timeline)?)
+        })
+        .await
+    }
+}
+
+/// Argumentos de `mem_pin`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PinArgs {
+    /// ID de la entrada de conocimiento
+    id: i64,
+    /// Fijar (`true`) o soltar (`false`) la entrada (default: no cambiar)
+    pinned: Option<bool>,
+    /// Nueva importancia, de 1 (baja) a 5 (crítica) (default: no cambiar)
+    #[schemars(range(min = 1, max = 5))]
+    importance: Option<i64>,
+    /// Store de la entrada (default: el store por defecto)
+    store: Option<String>,
+}
+
+pub struct PinTool;
+
+#[async_trait]
+impl Tool for PinTool {
+    fn name(&self) -> &'static str {
+        "mem_pin"
+    }
+
+    fn description(&self) -> String {
+        "Fija una entrada o cambia su importancia (criterios de selección de export-rules)".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<PinArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, true)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: PinArgs = parse_args(self.name(), &arguments)?;
+        let store = ctx.store(self.name(), args.store.as_deref())?;
+        let (memory, store_name) = (store.memory.clone(), store.name.clone());
+        let access = ctx.access.clone();
+        let journal = ctx.journal.clone();
+        blocking(move || {
+            match memory.get_detail(args.id)? {
+                Some(entry) if access.project_allowed(entry.project_path.as_deref()) => {}
+                _ => anyhow::bail!("Entrada no encontrada: {}", args.id),
+            }
+            let changed = memory
+                .set_priority(args.id, args.pinned, args.importance)?
+                .ok_or_else(|| anyhow::anyhow!("Entrada no encontrada: {}", args.id))?;
+            if changed {
+                journal.touch(&store_name, args.id, Touch::Updated);
+            }
+            Ok(serde_json::json!({
+                "id": args.id,
+                "changed": changed,
+                "success": true
+            }))
+        })
+        .await
+    }
+}
+
+/// Argumentos de `mem_feedback`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct FeedbackArgs {
+    /// ID de la entrada de conocimiento
+    id: i64,
+    /// Si la entrada resultó útil para la tarea
+    useful: bool,
+    /// Store de la entrada (default: el store por defecto)
+    store: Option<String>,
+}
+
+pub struct FeedbackTool;
+
+#[async_trait]
+impl Tool for FeedbackTool {
+    fn name(&self) -> &'static str {
+        "mem_feedback"
+    }
+
+    fn description(&self) -> String {
+        "Valora si una entrada resultó útil; las valoraciones influyen en el orden de mem_search".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<FeedbackArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, false)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: FeedbackArgs = parse_args(self.name(), &arguments)?;
+        let memory = ctx.store(self.name(), args.store.as_deref())?.memory.clone();
+        let access = ctx.access.clone();
+        blocking(move || {
+            match memory.get_detail(args.id)? {
+                Some(entry) if access.project_allowed(entry.project_path.as_deref()) => {}
+                _ => anyhow::bail!("Entrada no encontrada: {}", args.id),
+            }
+            let usage = memory
+                .record_feedback(args.id, args.useful)?
+                .ok_or_else(|| anyhow::anyhow!("Entrada no encontrada: {}", args.id))?;
+            Ok(serde_json::json!({
+                "id": args.id,
+                "useful": usage.useful,
+                "not_useful": usage.not_useful,
+                "success": true
+            }))
+        })
+        .await
+    }
+}
+
+/// Argumentos de `mem_reindex`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ReindexArgs {
+    /// Entradas reindexadas por lote
+    #[serde(default = "default_batch_size")]
+    #[schemars(range(min = 1))]
+    batch_size: usize,
+    /// Reindexar solo este store (default: todos)
+    store: Option<String>,
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+pub struct ReindexTool;
+
+#[async_trait]
+impl Tool for ReindexTool {
+    fn name(&self) -> &'static str {
+        "mem_reindex"
+    }
+
+    fn description(&self) -> String {
+        "Reconstruye el índice de búsqueda full-text por lotes. Emite notifications/progress si se envía _meta.progressToken y puede cancelarse con notifications/cancelled (el índice queda como estaba)".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<ReindexArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, true)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: ReindexArgs = parse_args(self.name(), &arguments)?;
+        let stores = selected_stores(ctx, self.name(), args.store.as_deref())?;
+        let request = ctx.request.clone();
+        blocking(move || {
+            // El progreso es acumulado sobre todos los stores
+            let total = stores
+                .iter()
+                .map(|(_, memory)| memory.count())
+                .sum::<rusqlite::Result<usize>>()?;
+            let mut offset = 0;
+            let mut reindexed = serde_json::Map::new();
+            for (name, memory) in &stores {
+                let done = memory.rebuild_index(args.batch_size, |done, _| {
+                    request.progress((offset + done) as u64, Some(total as u64), Some("Reindexando entradas"));
+                    !request.is_cancelled()
+                })?;
+                request.check_cancelled()?;
+                let done = done.unwrap_or_default();
+                offset += done;
+                reindexed.insert(name.clone(), done.into());
+            }
+
+            Ok(serde_json::json!({
+                "reindexed": offset,
+                "stores": reindexed,
+                "success": true
+            }))
+        })
+        .await
+    }
+}
+
+/// Store indicado en `store`, o todos si no se indica, como pares (nombre, memoria)
+fn selected_stores(
+    ctx: &ToolContext,
+    tool: &str,
+    store: Option<&str>,
+) -> Result<Vec<(String, Arc<Memory>)>, InvalidArguments> {
+    let selected = match store {
+        Some(_) => vec![ctx.store(tool, store)?],
+        None => ctx.stores.iter().collect(),
+    };
+    Ok(selected
+        .into_iter()
+        .map(|s| (s.name.clone(), s.memory.clone()))
+        .collect())
+}
+
+/// Argumentos de `mem_verify`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct VerifyArgs {
+    /// Verificar solo esta entrada
+    id: Option<i64>,
+    /// Proyecto a verificar (default: el proyecto activo del IDE)
+    project_path: Option<String>,
+    /// Verificar las entradas de todos los proyectos
+    #[serde(default)]
+    all_projects: bool,
+    /// Verificar solo este store (default: todos; con `id`, el store por defecto)
+    store: Option<String>,
+}
+
+pub struct VerifyTool;
+
+#[async_trait]
+impl Tool for VerifyTool {
+    fn name(&self) -> &'static str {
+        "mem_verify"
+    }
+
+    fn description(&self) -> String {
+        "Compara los archivos, rangos de líneas y símbolos referenciados por las entradas con el árbol de trabajo actual y marca como posiblemente obsoletas (stale) las que cambiaron".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<VerifyArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, true)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: VerifyArgs = parse_args(self.name(), &arguments)?;
+        let project = if args.all_projects || args.id.is_some() {
+            None
+        } else {
+            ctx.project(args.project_path.as_deref()).map(|project| project.key)
+        };
+        let stores = ctx.stores.clone();
+        let only = match (args.store.as_deref(), args.id) {
+            (None, None) => None,
+            (store, _) => Some(ctx.store(self.name(), store)?.name.clone()),
+        };
+        let access = ctx.access.clone();
+        let request = ctx.request.clone();
+        blocking(move || {
+            let report = verify::verify_entries(
+                stores.iter().filter(|s| only.as_ref().is_none_or(|name| &s.name == name)),
+                project.as_deref(),
+                args.id,
+                |project| access.project_allowed(project),
+                |done, total| {
+                    request.progress(done as u64, Some(total as u64), Some("Verificando entradas"));
+                    !request.is_cancelled()
+                },
+            )?;
+            request.check_cancelled()?;
+            Ok(serde_json::to_value(report)?)
+        })
+        .await
+    }
+}
@@ -0,0 +1,345 @@
+//! Registro de herramientas MCP
+//!
+//! Cada herramienta se declara una sola vez implementando [`Tool`]: nombre,
+//! descripción, esquema de argumentos, anotaciones y handler. El servidor solo
+//! consulta el [`ToolRegistry`] tanto para `tools/list` como para `tools/call`.
+
+mod knowledge;
+mod session;
+mod tags;
+
+use crate::access::AccessPolicy;
+use crate::config::Config;
+use crate::mcp_server::RequestContext;
+use crate::memory::SessionInfo;
+use crate::project::Project;
+use crate::schema::{FieldError, InvalidArguments, Validators};
+use crate::session::Journal;
+use crate::stores::{Store, Stores};
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::Value;
+use std::path::Path;
+use std::sync::Arc;
+
+pub use knowledge::{FeedbackTool, GetDetailTool, PinTool, ReindexTool, SaveTool, SearchTool, TimelineTool, VerifyTool};
+pub use session::{SessionEndTool, SessionsTool};
+pub use tags::{RenameTagTool, TagsTool};
+
+/// Anotaciones MCP de una herramienta: pistas para el cliente sobre su comportamiento
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolAnnotations {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_only_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub destructive_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub idempotent_hint: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub open_world_hint: Option<bool>,
+}
+
+impl ToolAnnotations {
+    /// Herramienta que solo lee la memoria
+    pub fn read_only() -> Self {
+        ToolAnnotations {
+            read_only_hint: Some(true),
+            open_world_hint: Some(false),
+            ..Default::default()
+        }
+    }
+
+    /// Herramienta que modifica la memoria
+    pub fn mutating(destructive: bool, idempotent: bool) -> Self {
+        ToolAnnotations {
+            read_only_hint: Some(false),
+            destructive_hint: Some(destructive),
+            idempotent_hint: Some(idempotent),
+            open_world_hint: Some(false),
+        }
+    }
+}
+
+/// Lo que una herramienta recibe al ejecutarse
+#[derive(Clone)]
+pub struct ToolContext {
+    pub stores: Arc<Stores>,
+    pub request: RequestContext,
+    /// Política de acceso efectiva del cliente que hace la llamada
+    pub access: AccessPolicy,
+    pub config: Arc<Config>,
+    /// Proyecto activo del cliente (raíces de `roots/list` o repositorio git actual)
+    pub project: Option<Project>,
+    /// Sesión actual (desde `initialize`); se registra en el store de la primera entrada que guarda
+    pub session: Option<SessionInfo>,
+    /// Diario de la sesión: las herramientas anotan las entradas que tocan
+    pub journal: Arc<Journal>,
+}
+
+impl ToolContext {
+    /// Store indicado en el argumento `store` (o el store por defecto)
+    pub fn store(&self, tool: &str, name: Option<&str>) -> Result<&Store, InvalidArguments> {
+        self.stores.get(name).ok_or_else(|| InvalidArguments {
+            tool: tool.to_string(),
+            errors: vec![FieldError {
+                field: "/store".to_string(),
+                message: format!(
+                    "Store desconocido: {} (disponibles: {})",
+                    name.unwrap_or_default(),
+                    self.stores.names().join(", ")
+                ),
+            }],
+        })
+    }
+
+    /// Proyecto del argumento `project_path` o, sin argumento, el proyecto activo
+    ///
+    /// Si el argumento nombra el proyecto activo se usa su raíz ya resuelta.
+    pub fn project(&self, given: Option<&str>) -> Option<Project> {
+        let Some(path) = given else {
+            return self.project.clone();
+        };
+        let project = Project::at(Path::new(path));
+        match &self.project {
+            Some(active) if active.key == project.key => Some(active.clone()),
+            _ => Some(project),
+        }
+    }
+}
+
+/// Herramienta MCP
+#[async_trait]
+pub trait Tool: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn description(&self) -> String;
+
+    /// JSON Schema de los argumentos (se publica en `tools/list`)
+    fn input_schema(&self) -> Value;
+
+    /// Esquema publicado en `tools/list` (default: `input_schema`); lo redefinen las
+    /// herramientas cuyos valores válidos dependen de la base, como los tipos de conocimiento
+    fn list_schema(&self, _stores: &Stores) -> Value {
+        self.input_schema()
+    }
+
+    fn annotations(&self) -> ToolAnnotations;
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value>;
+}
+
+/// Ejecuta trabajo bloqueante (SQLite) en el pool de hilos bloqueantes de tokio
+/// para no frenar al resto de solicitudes
+pub async fn blocking<T, F>(f: F) -> anyhow::Result<T>
+where
+    F: FnOnce() -> anyhow::Result<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|e| Err(anyhow::anyhow!("La herramienta terminó abruptamente: {}", e)))
+}
+
+/// Herramientas disponibles en el servidor, en el orden en que se listan
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Arc<dyn Tool>>,
+    validators: Arc<Validators>,
+}
+
+impl ToolRegistry {
+    /// Registro con todas las herramientas incluidas en el servidor
+    pub fn with_builtin_tools() -> Self {
+        let mut registry = ToolRegistry::default();
+        registry.register(SearchTool);
+        registry.register(SaveTool);
+        registry.register(GetDetailTool);
+        registry.register(TimelineTool);
+        registry.register(PinTool);
+        registry.register(FeedbackTool);
+        registry.register(TagsTool);
+        registry.register(RenameTagTool);
+        registry.register(SessionsTool);
+        registry.register(SessionEndTool);
+        registry.register(ReindexTool);
+        registry.register(VerifyTool);
+        registry
+    }
+
+    /// Registra una herramienta, reemplazando a otra con el mismo nombre
+    pub fn register<T: Tool + 'static>(&mut self, tool: T) {
+        self.tools.retain(|t| t.name() != tool.name());
+        self.tools.push(Arc::new(tool));
+    }
+
+    /// Conserva solo las herramientas indicadas
+    pub fn retain_only(&mut self, names: &[String]) {
+        self.tools.retain(|t| names.iter().any(|n| n == t.name()));
+    }
+
+    /// Deshabilita herramientas por nombre; devuelve los nombres que no existían
+    pub fn disable<'a>(&mut self, names: &'a [String]) -> Vec<&'a str> {
+        let unknown = names
+            .iter()
+            .filter(|n| self.get(n).is_none())
+            .map(|n| n.as_str())
+            .collect();
+        self.tools.retain(|t| !names.iter().any(|n| n == t.name()));
+        unknown
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn Tool>> {
+        self.tools.iter().find(|t| t.name() == name).cloned()
+    }
+
+    /// Valida los argumentos de una llamada contra el esquema que `tools/list` publica
+    pub fn validate(&self, tool: &dyn Tool, stores: &Stores, arguments: &Value) -> Result<(), InvalidArguments> {
+        self.validators.validate(tool.name(), &tool.list_schema(stores), arguments)
+    }
+
+    /// Descriptores para la respuesta de `tools/list`, solo de las herramientas
+    /// que la política permite
+    pub fn list(&self, access: &AccessPolicy, stores: &Stores) -> Vec<Value> {
+        self.tools
+            .iter()
+            .filter(|t| access.tool_allowed(t.as_ref()))
+            .map(|t| {
+                serde_json::json!({
+                    "name": t.name(),
+                    "description": t.description(),
+                    "inputSchema": t.list_schema(stores),
+                    "annotations": t.annotations(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::KnowledgeTypeDef;
+    use crate::testing::TempDir;
+
+    struct Fake;
+
+    #[async_trait]
+    impl Tool for Fake {
+        fn name(&self) -> &'static str {
+            "mem_search"
+        }
+
+        fn description(&self) -> String {
+            "reemplazo".to_string()
+        }
+
+        fn input_schema(&self) -> Value {
+            serde_json::json!({ "type": "object" })
+        }
+
+        fn annotations(&self) -> ToolAnnotations {
+            ToolAnnotations::mutating(true, false)
+        }
+
+        async fn call(&self, _ctx: &ToolContext, _arguments: Value) -> anyhow::Result<Value> {
+            Ok(Value::Null)
+        }
+    }
+
+    fn names(registry: &ToolRegistry, access: &AccessPolicy, stores: &Stores) -> Vec<String> {
+        registry
+            .list(access, stores)
+            .iter()
+            .map(|t| t["name"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn stores(dir: &TempDir) -> Stores {
+        let config = Config {
+            database: dir.join("memory.db"),
+            ..Default::default()
+        };
+        Stores::open(&config).unwrap()
+    }
+
+    #[test]
+    fn builtin_tools_are_unique_and_fully_described() {
+        let registry = ToolRegistry::with_builtin_tools();
+        let mut seen = std::collections::HashSet::new();
+        for tool in &registry.tools {
+            assert!(seen.insert(tool.name()), "{} repetida", tool.name());
+            assert!(!tool.description().is_empty());
+            assert_eq!(tool.input_schema()["type"], "object", "{}", tool.name());
+            assert_eq!(tool.input_schema()["additionalProperties"], false, "{}", tool.name());
+            assert!(tool.annotations().read_only_hint.is_some(), "{}", tool.name());
+        }
+    }
+
+    #[test]
+    fn register_replaces_a_tool_with_the_same_name() {
+        let mut registry = ToolRegistry::with_builtin_tools();
+        let count = registry.tools.len();
+        registry.register(Fake);
+        assert_eq!(registry.tools.len(), count);
+        assert_eq!(registry.get("mem_search").unwrap().description(), "reemplazo");
+    }
+
+    #[test]
+    fn tools_can_be_restricted_and_disabled() {
+        let dir = TempDir::new("registry-restrict");
+        let stores = stores(&dir);
+        let mut registry = ToolRegistry::with_builtin_tools();
+        registry.retain_only(&["mem_search".to_string(), "mem_save".to_string(), "mem_pin".to_string()]);
+        assert_eq!(names(&registry, &AccessPolicy::default(), &stores), ["mem_search", "mem_save", "mem_pin"]);
+
+        let disabled = ["mem_pin".to_string(), "mem_nope".to_string()];
+        assert_eq!(registry.disable(&disabled), ["mem_nope"]);
+        assert_eq!(names(&registry, &AccessPolicy::default(), &stores), ["mem_search", "mem_save"]);
+        assert!(registry.get("mem_pin").is_none());
+    }
+
+    #[test]
+    fn list_follows_the_access_policy() {
+        let dir = TempDir::new("registry-policy");
+        let stores = stores(&dir);
+        let registry = ToolRegistry::with_builtin_tools();
+
+        let read_only = AccessPolicy {
+            read_only: true,
+            ..Default::default()
+        };
+        let listed = names(&registry, &read_only, &stores);
+        assert!(listed.contains(&"mem_search".to_string()));
+        assert!(!listed.contains(&"mem_save".to_string()));
+        assert!(listed.iter().all(|name| registry.get(name).unwrap().annotations().read_only_hint == Some(true)));
+
+        let deny = AccessPolicy {
+            deny_tools: vec!["mem_search".to_string()],
+            ..Default::default()
+        };
+        assert!(!names(&registry, &deny, &stores).contains(&"mem_search".to_string()));
+    }
+
+    #[test]
+    fn save_schema_lists_the_registered_types() {
+        let dir = TempDir::new("registry-types");
+        let stores = stores(&dir);
+        let runbook = KnowledgeTypeDef {
+            name: "runbook".to_string(),
+            description: "Procedimiento operativo".to_string(),
+            required_fields: Vec::new(),
+            fields: Vec::new(),
+            default_importance: 3,
+            builtin: false,
+        };
+        stores.get(None).unwrap().memory.define_type(&runbook).unwrap();
+
+        let registry = ToolRegistry::with_builtin_tools();
+        let save = registry.list(&AccessPolicy::default(), &stores).into_iter().find(|t| t["name"] == "mem_save").unwrap();
+        let knowledge_type = &save["inputSchema"]["properties"]["knowledge_type"];
+        let names: Vec<&str> = knowledge_type["enum"].as_array().unwrap().iter().map(|n| n.as_str().unwrap()).collect();
+        assert_eq!(names, ["decision", "bugfix", "pattern", "config", "context", "summary", "runbook"]);
+        assert!(knowledge_type["description"].as_str().unwrap().contains("runbook: Procedimiento operativo"));
+    }
+}
@@ -0,0 +1,138 @@
+//! Sesiones: listado de las anteriores y cierre explícito de la actual
+
+use super::{blocking, Tool, ToolAnnotations, ToolContext};
+use crate::schema::{parse_args, schema_for};
+use crate::session;
+use async_trait::async_trait;
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Argumentos de `mem_sessions`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SessionsArgs {
+    /// Solo sesiones iniciadas en los últimos N días (1 = desde ayer a esta hora)
+    #[schemars(range(min = 1))]
+    days: Option<u32>,
+    /// Solo sesiones de este cliente (`clientInfo.name`, p. ej. `cursor`)
+    client: Option<String>,
+    /// Número máximo de sesiones (default: 10)
+    #[schemars(range(min = 1))]
+    limit: Option<i64>,
+    /// Store a consultar (default: el store por defecto)
+    store: Option<String>,
+}
+
+pub struct SessionsTool;
+
+#[async_trait]
+impl Tool for SessionsTool {
+    fn name(&self) -> &'static str {
+        "mem_sessions"
+    }
+
+    fn description(&self) -> String {
+        "Lista las sesiones recientes (cliente, proyecto, inicio, fin y entradas guardadas); con mem_search y session_id se ve qué se aprendió en cada una".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<SessionsArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::read_only()
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: SessionsArgs = parse_args(self.name(), &arguments)?;
+        let memory = ctx.store(self.name(), args.store.as_deref())?.memory.clone();
+        let access = ctx.access.clone();
+        let current = ctx.session.as_ref().map(|session| session.id.clone());
+        blocking(move || {
+            let since = args.days.map(|days| Utc::now().timestamp() - i64::from(days) * 86_400);
+            let sessions: Vec<Value> = memory
+                .sessions(since, args.client.as_deref(), args.limit.unwrap_or(10))?
+                .into_iter()
+                .filter(|s| access.project_allowed(s.project_path.as_deref()))
+                .map(|s| {
+                    let mut value = serde_json::to_value(&s).unwrap_or_default();
+                    if current.as_deref() == Some(s.id.as_str()) {
+                        value["current"] = true.into();
+                    }
+                    value
+                })
+                .collect();
+            Ok(Value::Array(sessions))
+        })
+        .await
+    }
+}
+
+/// Argumentos de `mem_session_end`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SessionEndArgs {
+    /// Resumen escrito por el agente: qué se hizo y qué quedó pendiente. Se agrega a lo registrado automáticamente.
+    note: Option<String>,
+    /// Store donde guardar el resumen (default: `default_store` de la configuración)
+    store: Option<String>,
+}
+
+pub struct SessionEndTool;
+
+#[async_trait]
+impl Tool for SessionEndTool {
+    fn name(&self) -> &'static str {
+        "mem_session_end"
+    }
+
+    fn description(&self) -> String {
+        "Cierra la sesión: guarda un resumen (tipo summary) con las entradas guardadas, actualizadas y consultadas desde el inicio o el último cierre".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<SessionEndArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, false)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: SessionEndArgs = parse_args(self.name(), &arguments)?;
+        let project = ctx.project.as_ref().map(|project| project.key.clone());
+        ctx.access.check_project(project.as_deref())?;
+        let store_name = ctx.store(self.name(), args.store.as_deref())?.name.clone();
+        let stores = ctx.stores.clone();
+        let config = ctx.config.clone();
+        let journal = ctx.journal.clone();
+        let session = ctx.session.clone();
+        blocking(move || {
+            let store = stores
+                .get(Some(&store_name))
+                .ok_or_else(|| anyhow::anyhow!("Store desconocido: {}", store_name))?;
+            let id = session::summarize(
+                &stores,
+                store,
+                &config,
+                session.as_ref(),
+                journal.take(),
+                project,
+                args.note.as_deref(),
+            )?;
+            Ok(match id {
+                Some(id) => serde_json::json!({
+                    "id": id,
+                    "store": store_name,
+                    "success": true
+                }),
+                None => serde_json::json!({
+                    "id": null,
+                    "message": "No hubo actividad que resumir en esta sesión",
+                    "success": true
+                }),
+            })
+        })
+        .await
+    }
+}
@@ -0,0 +1,207 @@
+//! Herramientas de tags: listado jerárquico con conteos y renombrado/fusión
+//!
+//! Los tags se guardan normalizados (ver [`normalize_tag`]) y `/` separa niveles:
+//! `lang/rust` es hijo de `lang`. Filtrar o renombrar un tag incluye siempre a
+//! sus descendientes.
+
+use super::{blocking, Tool, ToolAnnotations, ToolContext};
+use crate::memory::{normalize_tag, SearchOptions};
+use crate::schema::{parse_args, schema_for, FieldError, InvalidArguments};
+use crate::session::Touch;
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Argumentos de `mem_tags`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct TagsArgs {
+    /// Solo este tag y sus descendientes (p. ej. `lang`)
+    prefix: Option<String>,
+    /// Proyecto cuyas entradas se cuentan (default: el proyecto activo del IDE). Siempre se incluyen las entradas sin proyecto.
+    project_path: Option<String>,
+    /// Contar las entradas de todos los proyectos, ignorando el proyecto activo
+    #[serde(default)]
+    all_projects: bool,
+    /// Contar solo en este store (default: en todos)
+    store: Option<String>,
+}
+
+/// Un tag con sus conteos
+#[derive(Debug, Serialize)]
+struct TagCount {
+    tag: String,
+    /// Entradas con exactamente este tag
+    count: usize,
+    /// Entradas con este tag o alguno de sus descendientes
+    total: usize,
+}
+
+pub struct TagsTool;
+
+#[async_trait]
+impl Tool for TagsTool {
+    fn name(&self) -> &'static str {
+        "mem_tags"
+    }
+
+    fn description(&self) -> String {
+        "Lista los tags en uso con el número de entradas de cada uno, incluida su jerarquía (`lang/rust`)".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<TagsArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::read_only()
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: TagsArgs = parse_args(self.name(), &arguments)?;
+        if args.store.is_some() {
+            ctx.store(self.name(), args.store.as_deref())?;
+        }
+        let stores = ctx.stores.clone();
+        let project = if args.all_projects {
+            None
+        } else {
+            ctx.project(args.project_path.as_deref()).map(|project| project.key)
+        };
+        let options = SearchOptions {
+            project,
+            allow_projects: ctx.access.allow_projects.clone(),
+            deny_projects: ctx.access.deny_projects.clone(),
+            ..SearchOptions::new(0)
+        };
+        blocking(move || {
+            // Por tag: entradas que lo usan directamente y entradas bajo él (store, ID)
+            let mut direct: BTreeMap<String, BTreeSet<(String, i64)>> = BTreeMap::new();
+            let mut under: BTreeMap<String, BTreeSet<(String, i64)>> = BTreeMap::new();
+            for store in stores.iter().filter(|s| args.store.as_deref().is_none_or(|name| s.name == name)) {
+                for (tag, id) in store.memory.tag_assignments(&options, args.prefix.as_deref())? {
+                    let entry = (store.name.clone(), id);
+                    direct.entry(tag.clone()).or_default().insert(entry.clone());
+                    let mut ancestor = tag.as_str();
+                    loop {
+                        under.entry(ancestor.to_string()).or_default().insert(entry.clone());
+                        match ancestor.rfind('/') {
+                            Some(end) => ancestor = &ancestor[..end],
+                            None => break,
+                        }
+                    }
+                }
+            }
+
+            // Los ancestros fuera del prefijo pedido no se listan
+            let prefix = args.prefix.as_deref().map(normalize_tag);
+            let tags: Vec<TagCount> = under
+                .into_iter()
+                .filter(|(tag, _)| {
+                    prefix.as_deref().is_none_or(|p| tag == p || tag.starts_with(&format!("{}/", p)))
+                })
+                .map(|(tag, entries)| TagCount {
+                    count: direct.get(&tag).map_or(0, BTreeSet::len),
+                    total: entries.len(),
+                    tag,
+                })
+                .collect();
+            Ok(serde_json::json!({ "tags": tags }))
+        })
+        .await
+    }
+}
+
+/// Argumentos de `mem_rename_tag`
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RenameTagArgs {
+    /// Tags a renombrar; con varios, se fusionan todos en `to`. Sus descendientes se mueven con ellos (`from/x` pasa a `to/x`).
+    #[schemars(length(min = 1))]
+    from: Vec<String>,
+    /// Nuevo nombre del tag
+    to: String,
+    /// Store donde renombrar (default: el store por defecto)
+    store: Option<String>,
+}
+
+pub struct RenameTagTool;
+
+#[async_trait]
+impl Tool for RenameTagTool {
+    fn name(&self) -> &'static str {
+        "mem_rename_tag"
+    }
+
+    fn description(&self) -> String {
+        "Renombra un tag, o fusiona varios en uno, en todas las entradas que lo usan".to_string()
+    }
+
+    fn input_schema(&self) -> Value {
+        schema_for::<RenameTagArgs>()
+    }
+
+    fn annotations(&self) -> ToolAnnotations {
+        ToolAnnotations::mutating(false, true)
+    }
+
+    async fn call(&self, ctx: &ToolContext, arguments: Value) -> anyhow::Result<Value> {
+        let args: RenameTagArgs = parse_args(self.name(), &arguments)?;
+        let mut errors = Vec::new();
+        if normalize_tag(&args.to).is_empty() {
+            errors.push(FieldError {
+                field: "/to".to_string(),
+                message: "El tag no puede estar vacío".to_string(),
+            });
+        }
+        let to = normalize_tag(&args.to);
+        for (i, from) in args.from.iter().enumerate() {
+            let from = normalize_tag(from);
+            if from.is_empty() {
+                errors.push(FieldError {
+                    field: format!("/from/{}", i),
+                    message: "El tag no puede estar vacío".to_string(),
+                });
+            } else if to.starts_with(&format!("{}/", from)) {
+                errors.push(FieldError {
+                    field: "/to".to_string(),
+                    message: format!("'{}' está debajo de '{}': un tag no puede moverse dentro de sí mismo", to, from),
+                });
+            }
+        }
+        if !errors.is_empty() {
+            return Err(InvalidArguments {
+                tool: self.name().to_string(),
+                errors,
+            }
+            .into());
+        }
+
+        let store = ctx.store(self.name(), args.store.as_deref())?;
+        let (memory, store_name) = (store.memory.clone(), store.name.clone());
+        let access = ctx.access.clone();
+        let journal = ctx.journal.clone();
+        blocking(move || {
+            let mut renamed = BTreeSet::new();
+            let mut skipped = BTreeSet::new();
+            for from in &args.from {
+                for (id, project) in memory.entries_with_tag(from)? {
+                    // Las entradas de proyectos vedados al cliente no se tocan
+                    if !access.project_allowed(project.as_deref()) {
+                        skipped.insert(id);
+                    } else if memory.rename_tag(id, from, &args.to)? {
+                        journal.touch(&store_name, id, Touch::Updated);
+                        renamed.insert(id);
+                    }
+                }
+            }
+            Ok(serde_json::json!({
+                "to": normalize_tag(&args.to),
+                "updated": renamed,
+                "skipped": skipped.len(),
+                "success": true
+            }))
+        })
+        .await
+    }
+}
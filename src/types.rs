@@ -0,0 +1,181 @@
+//! Registro de tipos de conocimiento
+//!
+//! Reglas para definir y eliminar tipos desde la línea de comandos (`types add`
+//! y `types remove`): nombres válidos, campos estructurados, campos obligatorios
+//! y protección de los tipos incluidos y de los tipos con entradas.
+
+use crate::memory::{FieldDef, FieldKind, KnowledgeTypeDef, Memory, DEFAULT_IMPORTANCE, REQUIRABLE_FIELDS};
+
+/// Cambios pedidos sobre un tipo; lo que no se indica conserva el valor actual
+#[derive(Debug, Default)]
+pub struct TypeChange {
+    pub description: Option<String>,
+    /// Campos como `nombre=descripción` o `nombre:list=descripción`; los vacíos se ignoran
+    pub fields: Option<Vec<String>>,
+    pub required: Option<Vec<String>>,
+    pub importance: Option<i64>,
+}
+
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+fn parse_field(spec: &str) -> anyhow::Result<FieldDef> {
+    let (field, description) = spec.split_once('=').unwrap_or((spec, ""));
+    let (field, kind) = match field.split_once(':') {
+        Some((field, "list")) => (field, FieldKind::List),
+        Some((field, "text")) | Some((field, "")) => (field, FieldKind::Text),
+        Some((_, kind)) => anyhow::bail!("Clase de campo desconocida: '{}' (text o list)", kind),
+        None => (field, FieldKind::Text),
+    };
+    if !valid_name(field) {
+        anyhow::bail!("Nombre de campo inválido: '{}' (solo minúsculas, dígitos, '_' y '-')", field);
+    }
+    Ok(FieldDef {
+        name: field.to_string(),
+        description: description.trim().to_string(),
+        kind,
+    })
+}
+
+/// Registra el tipo `name` o aplica `change` al existente. Devuelve la definición guardada.
+pub fn define_type(memory: &Memory, name: &str, change: TypeChange) -> anyhow::Result<KnowledgeTypeDef> {
+    if !valid_name(name) {
+        anyhow::bail!("Nombre de tipo inválido: '{}' (solo minúsculas, dígitos, '_' y '-')", name);
+    }
+    let existing = memory.knowledge_type(name)?;
+    let fields: Vec<FieldDef> = match change.fields {
+        Some(specs) => specs
+            .iter()
+            .filter(|spec| !spec.is_empty())
+            .map(|spec| parse_field(spec))
+            .collect::<anyhow::Result<_>>()?,
+        None => existing.as_ref().map(|t| t.fields.clone()).unwrap_or_default(),
+    };
+    let required: Vec<String> = match change.required {
+        Some(fields) => fields.into_iter().filter(|f| !f.is_empty()).collect(),
+        None => existing.as_ref().map(|t| t.required_fields.clone()).unwrap_or_default(),
+    };
+    let known: Vec<&str> = REQUIRABLE_FIELDS.iter().copied().chain(fields.iter().map(|f| f.name.as_str())).collect();
+    if let Some(field) = required.iter().find(|f| !known.contains(&f.as_str())) {
+        anyhow::bail!("Campo desconocido: '{}' (campos: {})", field, known.join(", "));
+    }
+    let description = change
+        .description
+        .or_else(|| existing.as_ref().map(|t| t.description.clone()))
+        .ok_or_else(|| anyhow::anyhow!("Indique --description para el tipo nuevo '{}'", name))?;
+    let def = KnowledgeTypeDef {
+        name: name.to_string(),
+        description,
+        required_fields: required,
+        fields,
+        default_importance: change
+            .importance
+            .or_else(|| existing.as_ref().map(|t| t.default_importance))
+            .unwrap_or(DEFAULT_IMPORTANCE),
+        builtin: existing.is_some_and(|t| t.builtin),
+    };
+    memory.define_type(&def)?;
+    Ok(def)
+}
+
+/// Elimina un tipo definido por el equipo; los incluidos y los que tienen entradas se rechazan
+pub fn remove_type(memory: &Memory, name: &str) -> anyhow::Result<()> {
+    if memory.knowledge_type(name)?.is_some_and(|t| t.builtin) {
+        anyhow::bail!("'{}' es un tipo incluido y no se puede eliminar", name);
+    }
+    let usage = memory.type_usage(name)?;
+    if usage > 0 {
+        anyhow::bail!("El tipo '{}' tiene {} entradas", name, usage);
+    }
+    if !memory.remove_type(name)? {
+        anyhow::bail!("Tipo desconocido: {}", name);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::NewKnowledge;
+    use crate::testing::TempDir;
+
+    fn memory(name: &str) -> (TempDir, Memory) {
+        let dir = TempDir::new(name);
+        let memory = Memory::new(&dir.join("memory.db")).unwrap();
+        (dir, memory)
+    }
+
+    fn change(description: &str, fields: &[&str], required: &[&str]) -> TypeChange {
+        TypeChange {
+            description: Some(description.to_string()),
+            fields: Some(fields.iter().map(|f| f.to_string()).collect()),
+            required: Some(required.iter().map(|f| f.to_string()).collect()),
+            importance: None,
+        }
+    }
+
+    #[test]
+    fn builtin_types_come_first() {
+        let (_dir, memory) = memory("types-builtin");
+        define_type(&memory, "runbook", change("Procedimiento operativo", &[], &[])).unwrap();
+        let names: Vec<String> = memory.knowledge_types().unwrap().into_iter().map(|t| t.name).collect();
+        assert_eq!(names, ["decision", "bugfix", "pattern", "config", "context", "summary", "runbook"]);
+    }
+
+    #[test]
+    fn new_types_are_validated() {
+        let (_dir, memory) = memory("types-define");
+        let error = |name: &str, change: TypeChange| define_type(&memory, name, change).unwrap_err().to_string();
+        assert!(error("Runbook", change("x", &[], &[])).starts_with("Nombre de tipo inválido"));
+        assert_eq!(error("runbook", TypeChange::default()), "Indique --description para el tipo nuevo 'runbook'");
+        assert_eq!(error("runbook", change("x", &["pasos:tabla"], &[])), "Clase de campo desconocida: 'tabla' (text o list)");
+        assert!(error("runbook", change("x", &["pasos"], &["owner"])).starts_with("Campo desconocido: 'owner'"));
+
+        let fields = ["pasos:list=Pasos a seguir", "", "servicio"];
+        let def = define_type(&memory, "runbook", change("Procedimiento", &fields, &["pasos", "tags"])).unwrap();
+        assert_eq!(def.fields.len(), 2);
+        assert_eq!((def.fields[0].name.as_str(), def.fields[0].kind), ("pasos", FieldKind::List));
+        assert_eq!(def.fields[0].description, "Pasos a seguir");
+        assert_eq!(def.fields[1].kind, FieldKind::Text);
+        assert_eq!(def.default_importance, DEFAULT_IMPORTANCE);
+        assert!(!def.builtin);
+    }
+
+    #[test]
+    fn changes_keep_what_is_not_given() {
+        let (_dir, memory) = memory("types-change");
+        define_type(&memory, "runbook", change("Procedimiento", &["pasos:list"], &["pasos"])).unwrap();
+        let importance = TypeChange {
+            importance: Some(5),
+            ..Default::default()
+        };
+        let def = define_type(&memory, "runbook", importance).unwrap();
+        assert_eq!(def.description, "Procedimiento");
+        assert_eq!(def.required_fields, ["pasos"]);
+        assert_eq!(def.default_importance, 5);
+        assert_eq!(memory.knowledge_type("runbook").unwrap().unwrap().default_importance, 5);
+
+        // Un tipo incluido se puede ajustar y sigue siendo incluido
+        let importance = TypeChange {
+            importance: Some(4),
+            ..Default::default()
+        };
+        assert!(define_type(&memory, "bugfix", importance).unwrap().builtin);
+    }
+
+    #[test]
+    fn only_unused_team_types_can_be_removed() {
+        let (_dir, memory) = memory("types-remove");
+        let error = |name: &str| remove_type(&memory, name).unwrap_err().to_string();
+        assert_eq!(error("decision"), "'decision' es un tipo incluido y no se puede eliminar");
+        assert_eq!(error("runbook"), "Tipo desconocido: runbook");
+
+        define_type(&memory, "runbook", change("Procedimiento", &[], &[])).unwrap();
+        let id = memory.save_knowledge(&NewKnowledge::new("runbook", "Reiniciar", "pasos")).unwrap();
+        assert_eq!(error("runbook"), "El tipo 'runbook' tiene 1 entradas");
+        memory.delete_knowledge(id).unwrap();
+        remove_type(&memory, "runbook").unwrap();
+        assert!(memory.knowledge_type("runbook").unwrap().is_none());
+    }
+}
@@ -0,0 +1,285 @@
+//! Detección de conocimiento obsoleto
+//!
+//! Al guardar una entrada se calcula el hash de cada archivo (o rango de líneas)
+//! que referencia. Verificar la entrada compara esos hashes con el árbol de
+//! trabajo actual y comprueba que los archivos y símbolos sigan existiendo. Las
+//! entradas cuyo código cambió quedan marcadas como posiblemente obsoletas.
+
+use crate::memory::FileRef;
+use crate::stores::Store;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// Resultado de verificar un conjunto de entradas
+#[derive(Debug, Default, Serialize)]
+pub struct VerifyReport {
+    /// Entradas cuyo código referenciado no cambió
+    pub verified: usize,
+    /// Entradas posiblemente obsoletas
+    pub stale: Vec<StaleEntry>,
+    /// Entradas que no se pudieron verificar (sin proyecto o proyecto inexistente)
+    pub skipped: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StaleEntry {
+    pub store: String,
+    pub id: i64,
+    pub title: String,
+    pub problems: Vec<String>,
+}
+
+/// SHA-256 del rango referenciado (o del archivo completo si no hay rango)
+///
+/// Las líneas se comparan sin sus finales de línea, así que pasar de LF a CRLF no
+/// cuenta como cambio. `None` si el archivo no se puede leer o el rango no existe.
+pub fn fingerprint(root: &Path, file: &FileRef) -> Option<String> {
+    let text = std::fs::read_to_string(root.join(&file.path)).ok()?;
+    let lines = referenced_lines(&text, file)?;
+    let mut hasher = Sha256::new();
+    for line in lines {
+        hasher.update(line.as_bytes());
+        hasher.update(b"\n");
+    }
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// SHA-256 en hexadecimal de un texto
+pub fn hash_text(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// Líneas del rango de `file` dentro de `text`, o `None` si el rango sobrepasa el final
+fn referenced_lines<'a>(text: &'a str, file: &FileRef) -> Option<Vec<&'a str>> {
+    let lines: Vec<&str> = text.lines().collect();
+    let Some(start) = file.start_line else {
+        return Some(lines);
+    };
+    let end = file.end_line.unwrap_or(start);
+    if start == 0 || end as usize > lines.len() {
+        return None;
+    }
+    Some(lines[start as usize - 1..end as usize].to_vec())
+}
+
+/// Problemas encontrados al comparar una referencia con el árbol de trabajo
+pub fn check(root: &Path, file: &FileRef) -> Vec<String> {
+    let path = root.join(&file.path);
+    let Ok(text) = std::fs::read_to_string(&path) else {
+        return vec![format!("{}: el archivo ya no existe", file.path)];
+    };
+
+    let mut problems = Vec::new();
+    if let Some(ref symbol) = file.symbol {
+        if !text.contains(symbol.as_str()) {
+            problems.push(format!("{}: el símbolo '{}' ya no aparece", file.path, symbol));
+        }
+    }
+    match (referenced_lines(&text, file), &file.content_hash) {
+        (None, _) => problems.push(format!(
+            "{}: el rango de líneas {}-{} ya no existe",
+            file.path,
+            file.start_line.unwrap_or_default(),
+            file.end_line.or(file.start_line).unwrap_or_default()
+        )),
+        (Some(_), Some(hash)) if fingerprint(root, file).as_ref() != Some(hash) => {
+            problems.push(format!("{}: el código referenciado cambió", file.path))
+        }
+        _ => {}
+    }
+    problems
+}
+
+/// Verifica las entradas con archivos referenciados y registra el resultado
+///
+/// `project` limita la verificación a un proyecto; `id` a una sola entrada (de
+/// cada store indicado). `allowed` filtra por proyecto (política de acceso).
+/// `on_entry(hechas, total)` se invoca tras cada entrada; si devuelve `false` se
+/// detiene la verificación.
+pub fn verify_entries<'a, A, F>(
+    stores: impl IntoIterator<Item = &'a Store>,
+    project: Option<&str>,
+    id: Option<i64>,
+    allowed: A,
+    mut on_entry: F,
+) -> anyhow::Result<VerifyReport>
+where
+    A: Fn(Option<&str>) -> bool,
+    F: FnMut(usize, usize) -> bool,
+{
+    let mut targets = Vec::new();
+    for store in stores {
+        let ids = match id {
+            Some(id) => vec![id],
+            None => store.memory.entries_with_files(project)?,
+        };
+        targets.extend(ids.into_iter().map(|entry_id| (store, entry_id)));
+    }
+
+    let mut report = VerifyReport::default();
+    for (done, &(store, entry_id)) in targets.iter().enumerate() {
+        let Some(entry) = store.memory.get_detail(entry_id)? else {
+            anyhow::bail!("Entrada no encontrada: {}", entry_id);
+        };
+        if !allowed(entry.project_path.as_deref()) {
+            // Una entrada pedida explícitamente de un proyecto denegado se trata como inexistente
+            if id.is_some() {
+                anyhow::bail!("Entrada no encontrada: {}", entry_id);
+            }
+            continue;
+        }
+
+        // `project_path` es una clave normalizada: la raíz en disco es la que se
+        // anotó al guardar o indexar el proyecto en esta máquina
+        let root = match entry.project_path.as_deref() {
+            Some(key) => Some(store.memory.project_dir(key)?.unwrap_or_else(|| PathBuf::from(key))),
+            None => None,
+        };
+        match root.filter(|root| root.is_dir()) {
+            Some(root) if !entry.file_paths.is_empty() => {
+                let problems: Vec<String> =
+                    entry.file_paths.iter().flat_map(|file| check(&root, file)).collect();
+                let stale = !problems.is_empty();
+                let description = if stale {
+                    format!("Posiblemente obsoleta: {}", problems.join("; "))
+                } else {
+                    "Código referenciado sin cambios".to_string()
+                };
+                store.memory.record_verification(entry.id, stale, &description)?;
+                if stale {
+                    report.stale.push(StaleEntry {
+                        store: store.name.clone(),
+                        id: entry.id,
+                        title: entry.title,
+                        problems,
+                    });
+                } else {
+                    report.verified += 1;
+                }
+            }
+            _ => report.skipped += 1,
+        }
+
+        if !on_entry(done + 1, targets.len()) {
+            break;
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MirrorConfig;
+    use crate::memory::{Memory, NewKnowledge};
+    use crate::mirror;
+    use crate::project::{normalize_path, relative_to_project, Project};
+    use crate::testing::TempDir;
+    use std::sync::Arc;
+
+    fn project(name: &str, source: &str) -> TempDir {
+        let root = TempDir::new(name);
+        std::fs::write(root.join("lib.rs"), source).unwrap();
+        root
+    }
+
+    /// Referencia a `lib.rs` con el hash que se calcularía al guardar la entrada
+    fn reference(root: &Path, lines: Option<(u32, u32)>, symbol: Option<&str>) -> FileRef {
+        let mut file = FileRef {
+            path: "lib.rs".to_string(),
+            start_line: lines.map(|(start, _)| start),
+            end_line: lines.map(|(_, end)| end),
+            symbol: symbol.map(str::to_string),
+            content_hash: None,
+        };
+        file.content_hash = fingerprint(root, &file);
+        file
+    }
+
+    const SOURCE: &str = "// cabecera\nfn parse() {\n    todo!()\n}\n";
+
+    #[test]
+    fn unchanged_range_has_no_problems() {
+        let root = project("verify-unchanged", SOURCE);
+        let file = reference(root.path(), Some((2, 4)), Some("fn parse"));
+        // Cambios fuera del rango y de finales de línea no cuentan
+        std::fs::write(root.join("lib.rs"), SOURCE.replace("cabecera", "otra").replace('\n', "\r\n")).unwrap();
+        assert!(check(root.path(), &file).is_empty());
+    }
+
+    #[test]
+    fn changed_range_is_reported() {
+        let root = project("verify-changed", SOURCE);
+        let file = reference(root.path(), Some((2, 4)), None);
+        std::fs::write(root.join("lib.rs"), SOURCE.replace("todo!()", "unimplemented!()")).unwrap();
+        assert_eq!(check(root.path(), &file), ["lib.rs: el código referenciado cambió"]);
+    }
+
+    #[test]
+    fn missing_symbol_range_and_file_are_reported() {
+        let root = project("verify-missing", SOURCE);
+        let file = reference(root.path(), Some((2, 4)), Some("fn parse"));
+        std::fs::write(root.join("lib.rs"), "fn other() {}\n").unwrap();
+        assert_eq!(
+            check(root.path(), &file),
+            [
+                "lib.rs: el símbolo 'fn parse' ya no aparece",
+                "lib.rs: el rango de líneas 2-4 ya no existe"
+            ]
+        );
+
+        std::fs::remove_file(root.join("lib.rs")).unwrap();
+        assert_eq!(check(root.path(), &file), ["lib.rs: el archivo ya no existe"]);
+    }
+
+    /// Un proyecto abierto desde WSL se guarda con la clave `c:/work/app`, pero
+    /// guardar, verificar y revisar el espejo leen y escriben en su raíz real
+    #[test]
+    fn wsl_project_round_trips_through_save_verify_and_mirror() {
+        let root = project("verify-wsl", SOURCE);
+        let project = Project {
+            key: normalize_path("/mnt/c/Work/App"),
+            dir: root.path().to_path_buf(),
+        };
+        let store = Store {
+            name: "default".to_string(),
+            memory: Arc::new(Memory::new(&root.join("memory.db")).unwrap()),
+            weight: 1.0,
+        };
+        let config = MirrorConfig::default();
+
+        let mut file = FileRef {
+            path: relative_to_project("/mnt/c/Work/App/lib.rs", Some(&project)),
+            start_line: Some(2),
+            end_line: Some(4),
+            symbol: None,
+            content_hash: None,
+        };
+        file.content_hash = fingerprint(&project.dir, &file);
+        assert_eq!(file.path, "lib.rs");
+        assert!(file.content_hash.is_some());
+        store.memory.remember_project_dir(&project.key, &project.dir).unwrap();
+        let entry = NewKnowledge {
+            project_path: Some(project.key.clone()),
+            file_paths: vec![file],
+            ..NewKnowledge::new("decision", "Parser", "parse() queda sin implementar")
+        };
+        let id = mirror::save_entry(&store.memory, &project, &config, entry).unwrap();
+        assert!(root.join(".ide-memory/parser.md").is_file());
+        assert!(!Path::new("c:").exists());
+
+        let report = verify_entries([&store], Some(&project.key), None, |_| true, |_, _| true).unwrap();
+        assert_eq!((report.verified, report.stale.len(), report.skipped), (1, 0, 0));
+
+        // La revisión del espejo reconoce el archivo escrito: no duplica la entrada
+        let scanned = mirror::scan(&store.memory, &project, &config).unwrap();
+        assert!(scanned.is_empty());
+        assert_eq!(scanned.unchanged, 1);
+        assert_eq!(store.memory.entries_with_files(Some(&project.key)).unwrap(), [id]);
+
+        std::fs::write(root.join("lib.rs"), SOURCE.replace("todo!()", "unimplemented!()")).unwrap();
+        let report = verify_entries([&store], Some(&project.key), None, |_| true, |_, _| true).unwrap();
+        assert_eq!(report.stale.len(), 1);
+    }
+}